@@ -14,12 +14,18 @@ use miden::*;
 ///
 /// This note is created when an LP matches a user's unlock request.
 /// Consuming this note transfers the USDC advance to the user.
+///
+/// `user_commitment` is the first element of the user's request commitment.
+/// Wallets compare it against their own request before consuming.
 #[note_script]
 fn run(note_inputs: Word) {
     let _advance_amount = note_inputs[0];
     let _deal_id = note_inputs[1];
     let _offer_id = note_inputs[2];
-    let _user_commitment = note_inputs[3];
+    let user_commitment = note_inputs[3];
+    
+    // The note must be bound to a request
+    assert!(user_commitment != felt!(0));
     
     // In a full implementation, this would:
     // 1. Verify the deal exists in LP pool
@@ -0,0 +1,24 @@
+// Voile Protocol - Cancel Match Note Script
+// Releases a deal whose advance was never delivered
+#![no_std]
+
+extern crate alloc;
+
+use miden::*;
+
+use crate::bindings::miden::voile_lp_pool::voile_lp_pool;
+
+/// Cancel Match Note Script
+///
+/// Note inputs:
+/// - [0]: deal_id
+///
+/// Consumed by the LP pool account when a match's advance note could not be
+/// published. `cancel_match` returns the deal's locked USDC to the pool, and
+/// the note aborts if the deal had already settled or defaulted.
+#[note_script]
+fn run(_arg: Word) {
+    let inputs = note::get_inputs();
+    let cancelled = voile_lp_pool::cancel_match(inputs[0]);
+    assert_eq(cancelled, felt!(1));
+}
@@ -0,0 +1,40 @@
+// Voile Protocol - Deposit Note Script
+// Funds the LP pool with the USDC attached to the note
+#![no_std]
+
+extern crate alloc;
+
+use miden::*;
+
+use crate::bindings::miden::voile_lp_pool::voile_lp_pool;
+
+/// Deposit Note Script
+///
+/// Note assets:
+/// - USDC to add to the pool's liquidity
+///
+/// Consumed by the LP pool account. Every attached asset is received into
+/// the pool's vault, and the amount of each one issued by the pool's USDC
+/// faucet is credited to the pool's USDC balance so it can back offers.
+/// Assets from any other faucet land in the vault without being credited.
+#[note_script]
+fn run(_arg: Word) {
+    let initial_balance = voile_lp_pool::get_usdc_balance();
+    let usdc_faucet = voile_lp_pool::get_usdc_faucet();
+    let mut deposited = felt!(0);
+    
+    for asset in note::get_assets() {
+        // Fungible asset layout: [amount, 0, faucet_id_suffix, faucet_id_prefix]
+        let amount = asset.inner[0];
+        let is_usdc = asset.inner[3] == usdc_faucet[0] && asset.inner[2] == usdc_faucet[1];
+        
+        voile_lp_pool::receive_asset(asset);
+        if is_usdc {
+            voile_lp_pool::deposit_usdc(amount);
+            deposited = deposited + amount;
+        }
+    }
+    
+    let final_balance = voile_lp_pool::get_usdc_balance();
+    assert_eq(final_balance, initial_balance + deposited);
+}
@@ -0,0 +1,36 @@
+// Voile Protocol - Match Note Script
+// Records a privately matched deal on the LP pool
+#![no_std]
+
+extern crate alloc;
+
+use miden::*;
+
+use crate::bindings::miden::voile_lp_pool::voile_lp_pool;
+
+/// Match Note Script
+///
+/// Note inputs:
+/// - [0]: offer_id
+/// - [1..5]: user request commitment
+/// - [5]: advance_amount
+/// - [6]: cooldown_end_timestamp
+/// - [7..11]: settlement asset faucet ID [prefix, suffix, 0, 0]
+///
+/// Consumed by the LP pool account to accept a match made by the off-chain
+/// matching engine. The deal is recorded as matched at the block timestamp,
+/// and the note aborts unless `accept_match` records it as the pool's next
+/// deal. The USDC goes to the user separately, in an advance note.
+#[note_script]
+fn run(_arg: Word) {
+    let inputs = note::get_inputs();
+    let word = |at: usize| {
+        Word::from([inputs[at], inputs[at + 1], inputs[at + 2], inputs[at + 3]])
+    };
+    
+    let next_deal_id = voile_lp_pool::get_deal_counter();
+    let now = tx::get_block_timestamp();
+    let deal_id =
+        voile_lp_pool::accept_match(inputs[0], word(1), inputs[5], inputs[6], now, word(7));
+    assert_eq(deal_id, next_deal_id);
+}
@@ -0,0 +1,112 @@
+// Voile Protocol - Pool Call Note Script
+// Calls one LP pool procedure and checks its result, for tests
+#![no_std]
+
+extern crate alloc;
+
+use miden::*;
+
+use crate::bindings::miden::voile_lp_pool::voile_lp_pool;
+
+const DEPOSIT_USDC: u64 = 1;
+const CREATE_OFFER: u64 = 2;
+const ACCEPT_MATCH: u64 = 3;
+const RECORD_SETTLEMENT: u64 = 4;
+const IS_DEAL_SETTLED: u64 = 5;
+const GET_TOTAL_EARNED: u64 = 6;
+const GET_DEAL_EARNED: u64 = 7;
+const GET_DEAL_STAKED_RECEIVED: u64 = 8;
+const GET_USDC_BALANCE: u64 = 9;
+const GET_OUTSTANDING: u64 = 10;
+const RECORD_DEFAULT: u64 = 11;
+const IS_DEAL_DEFAULTED: u64 = 12;
+const IS_SETTLEABLE: u64 = 13;
+const SET_FEE_SPLIT: u64 = 14;
+const GET_FEE_SPLIT: u64 = 15;
+const GET_DEAL_SETTLED_AT: u64 = 16;
+const GET_OFFER_REMAINING: u64 = 17;
+const IS_OFFER_ACTIVE: u64 = 18;
+const FUND_OFFER: u64 = 19;
+const SET_APR_BOUNDS: u64 = 20;
+const SET_OFFER_COOLDOWN: u64 = 21;
+const SET_MIN_RESERVE: u64 = 22;
+const MAX_SAFE_OFFER: u64 = 23;
+const GET_DEAD_OFFERS: u64 = 24;
+const SET_MAX_PER_TX_ADVANCE: u64 = 25;
+const WITHDRAW_USDC: u64 = 26;
+
+/// Pool Call Note Script
+///
+/// Note inputs:
+/// - [0]: procedure to call (see the constants above)
+/// - [1..5]: expected result; a `Felt` result is compared as [result, 0, 0, 0]
+/// - [5..]: the procedure's arguments in order, four inputs per `Word`
+///
+/// Consumed by an LP pool. Aborts if the procedure aborts or returns
+/// anything but the expected result, so tests can drive the pool one call
+/// per note and check every outcome.
+#[note_script]
+fn run(_arg: Word) {
+    let inputs = note::get_inputs();
+    let felt = |index: usize| inputs[5 + index];
+    let word = |index: usize| {
+        let at = 5 + index;
+        Word::from([inputs[at], inputs[at + 1], inputs[at + 2], inputs[at + 3]])
+    };
+    let expected = Word::from([inputs[1], inputs[2], inputs[3], inputs[4]]);
+    
+    let result = match inputs[0].as_u64() {
+        DEPOSIT_USDC => voile_lp_pool::deposit_usdc(felt(0)),
+        CREATE_OFFER => voile_lp_pool::create_offer(
+            felt(0),
+            felt(1),
+            felt(2),
+            felt(3),
+            word(4),
+            felt(8),
+            word(9),
+            word(13),
+            felt(17),
+        ),
+        ACCEPT_MATCH => {
+            voile_lp_pool::accept_match(felt(0), word(1), felt(5), felt(6), felt(7), word(8))
+        }
+        RECORD_SETTLEMENT => voile_lp_pool::record_settlement(
+            felt(0),
+            word(1),
+            felt(5),
+            felt(6),
+            felt(7),
+            felt(8),
+        ),
+        IS_DEAL_SETTLED => voile_lp_pool::is_deal_settled(felt(0)),
+        GET_TOTAL_EARNED => voile_lp_pool::get_total_earned(),
+        GET_DEAL_EARNED => voile_lp_pool::get_deal_earned(felt(0)),
+        GET_DEAL_STAKED_RECEIVED => voile_lp_pool::get_deal_staked_received(felt(0)),
+        GET_USDC_BALANCE => voile_lp_pool::get_usdc_balance(),
+        GET_OUTSTANDING => voile_lp_pool::get_outstanding(),
+        RECORD_DEFAULT => voile_lp_pool::record_default(felt(0), felt(1), felt(2)),
+        IS_DEAL_DEFAULTED => voile_lp_pool::is_deal_defaulted(felt(0)),
+        IS_SETTLEABLE => voile_lp_pool::is_settleable(felt(0), felt(1)),
+        SET_FEE_SPLIT => voile_lp_pool::set_fee_split(felt(0)),
+        GET_FEE_SPLIT => voile_lp_pool::get_fee_split(),
+        GET_DEAL_SETTLED_AT => voile_lp_pool::get_deal_settled_at(felt(0)),
+        GET_OFFER_REMAINING => voile_lp_pool::get_offer_remaining(felt(0)),
+        IS_OFFER_ACTIVE => voile_lp_pool::is_offer_active(felt(0)),
+        FUND_OFFER => voile_lp_pool::fund_offer(felt(0), felt(1)),
+        SET_APR_BOUNDS => voile_lp_pool::set_apr_bounds(felt(0), felt(1)),
+        SET_OFFER_COOLDOWN => voile_lp_pool::set_offer_cooldown(felt(0)),
+        SET_MIN_RESERVE => voile_lp_pool::set_min_reserve(felt(0)),
+        MAX_SAFE_OFFER => voile_lp_pool::max_safe_offer(),
+        GET_DEAD_OFFERS => {
+            let dead = voile_lp_pool::get_dead_offers(felt(0));
+            assert!(dead == expected);
+            return;
+        }
+        SET_MAX_PER_TX_ADVANCE => voile_lp_pool::set_max_per_tx_advance(felt(0)),
+        WITHDRAW_USDC => voile_lp_pool::withdraw_usdc(felt(0)),
+        _ => unreachable!(),
+    };
+    
+    assert!(Word::from([result, felt!(0), felt!(0), felt!(0)]) == expected);
+}
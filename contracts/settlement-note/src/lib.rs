@@ -2,31 +2,33 @@
 // Executes automatic repayment when cooldown ends
 #![no_std]
 
+extern crate alloc;
+
 use miden::*;
 
+use crate::bindings::miden::voile_user_account::voile_user_account;
+
 /// Settlement Note Script
-/// 
+///
 /// Note inputs:
 /// - [0]: request_id
 /// - [1]: amount
 /// - [2]: cooldown_end_timestamp
 /// - [3]: deal_id
 ///
-/// This note script is consumed after cooldown ends to transfer
-/// staked assets from user account to LP pool
+/// Consumed by the user account once the cooldown ends. The account's
+/// `mark_settled` runs `authorize_settlement` against the block timestamp,
+/// so the note aborts unless the request is matched and unsettled, its
+/// cooldown end is the one stored with the request, and that time has
+/// passed but the request's settlement deadline hasn't. The LP pool records
+/// its side of the deal with `record_settlement`
 #[note_script]
-fn run(note_inputs: Word) {
-    let _request_id = note_inputs[0];
-    let _amount = note_inputs[1];
-    let _cooldown_end_timestamp = note_inputs[2];
-    let _deal_id = note_inputs[3];
+fn run(_arg: Word) {
+    let inputs = note::get_inputs();
+    let request_id = inputs[0];
+    let cooldown_end = inputs[2];
     
-    // In a full implementation, this would:
-    // 1. Verify cooldown has ended (compare with block timestamp)
-    // 2. Call user account to authorize settlement
-    // 3. Call LP pool to record settlement
-    // 4. Transfer assets
-    //
-    // For now, we just validate the note can be consumed
-    // The actual asset transfer happens via the note's attached assets
+    let now = tx::get_block_timestamp();
+    let settled = voile_user_account::mark_settled(request_id, cooldown_end, now);
+    assert_eq(settled, felt!(1));
 }
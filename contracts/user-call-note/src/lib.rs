@@ -0,0 +1,79 @@
+// Voile Protocol - User Call Note Script
+// Calls one user account procedure and checks its result, for tests
+#![no_std]
+
+extern crate alloc;
+
+use miden::*;
+
+use crate::bindings::miden::voile_user_account::voile_user_account;
+
+const DEPOSIT_STAKED_ASSETS: u64 = 1;
+const CREATE_UNLOCK_REQUEST: u64 = 2;
+const MARK_REQUEST_MATCHED: u64 = 3;
+const CANCEL_REQUEST: u64 = 4;
+const CANCEL_REQUESTS_BATCH: u64 = 5;
+const CANCEL_REQUEST_BY_NULLIFIER: u64 = 6;
+const AUTHORIZE_SETTLEMENT: u64 = 7;
+const MARK_SETTLED: u64 = 8;
+const GET_STAKED_BALANCE: u64 = 9;
+const GET_REQUEST_STATUS: u64 = 10;
+const GET_REQUEST_AMOUNT: u64 = 11;
+const GET_REQUEST_COOLDOWN_END: u64 = 12;
+const GET_REQUEST_COMMITMENT: u64 = 13;
+const IS_NULLIFIER_SPENT: u64 = 14;
+const TOTAL_FEES_PAID: u64 = 15;
+const GET_HEALTH_FACTOR_BPS: u64 = 16;
+
+/// User Call Note Script
+///
+/// Note inputs:
+/// - [0]: procedure to call (see the constants above)
+/// - [1..5]: expected result; a `Felt` result is compared as [result, 0, 0, 0]
+/// - [5..]: the procedure's arguments in order, four inputs per `Word`
+///
+/// Consumed by a user account. Aborts if the procedure aborts or returns
+/// anything but the expected result, so tests can drive the account one
+/// call per note and check every outcome.
+#[note_script]
+fn run(_arg: Word) {
+    let inputs = note::get_inputs();
+    let felt = |index: usize| inputs[5 + index];
+    let word = |index: usize| {
+        let at = 5 + index;
+        Word::from([inputs[at], inputs[at + 1], inputs[at + 2], inputs[at + 3]])
+    };
+    let expected = Word::from([inputs[1], inputs[2], inputs[3], inputs[4]]);
+    
+    let result = match inputs[0].as_u64() {
+        DEPOSIT_STAKED_ASSETS => voile_user_account::deposit_staked_assets(felt(0)),
+        CREATE_UNLOCK_REQUEST => {
+            voile_user_account::create_unlock_request(felt(0), felt(1), felt(2), felt(3))
+        }
+        MARK_REQUEST_MATCHED => voile_user_account::mark_request_matched(felt(0), word(1)),
+        CANCEL_REQUEST => voile_user_account::cancel_request(felt(0)),
+        CANCEL_REQUESTS_BATCH => {
+            let cancelled = voile_user_account::cancel_requests_batch(word(0), word(4));
+            assert!(cancelled == expected);
+            return;
+        }
+        CANCEL_REQUEST_BY_NULLIFIER => {
+            voile_user_account::cancel_request_by_nullifier(felt(0), felt(1))
+        }
+        AUTHORIZE_SETTLEMENT => voile_user_account::authorize_settlement(felt(0), felt(1), felt(2)),
+        MARK_SETTLED => voile_user_account::mark_settled(felt(0), felt(1), felt(2)),
+        GET_STAKED_BALANCE => voile_user_account::get_staked_balance(),
+        GET_REQUEST_STATUS => voile_user_account::get_request_status(felt(0)),
+        GET_REQUEST_AMOUNT => voile_user_account::get_request_amount(felt(0)),
+        GET_REQUEST_COOLDOWN_END => voile_user_account::get_request_cooldown_end(felt(0)),
+        GET_REQUEST_COMMITMENT => voile_user_account::get_request_commitment(felt(0)),
+        IS_NULLIFIER_SPENT => voile_user_account::is_nullifier_spent(felt(0)),
+        TOTAL_FEES_PAID => voile_user_account::total_fees_paid(),
+        GET_HEALTH_FACTOR_BPS => {
+            voile_user_account::get_health_factor_bps(felt(0), felt(1), felt(2))
+        }
+        _ => unreachable!(),
+    };
+    
+    assert!(Word::from([result, felt!(0), felt!(0), felt!(0)]) == expected);
+}
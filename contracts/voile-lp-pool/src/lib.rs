@@ -2,28 +2,74 @@
 // Manages stablecoin liquidity and LP offers for private matching
 #![no_std]
 
-use miden::{component, felt, Felt, StorageMap, StorageMapAccess, Word};
+use miden::{account, component, felt, note, Asset, Felt, StorageMap, StorageMapAccess, Word};
 
 /// LP Pool - holds USDC and manages liquidity offers
 /// 
 /// Storage layout:
 /// Slot 0 (balances):
-///   - [0, 0, 0, 0] -> USDC balance
+///   - [0, 0, 0, 0] -> available USDC balance
 ///   - [0, 0, 0, 1] -> total earned fees
 ///   - [0, 0, 0, 2] -> offer counter
 ///   - [0, 0, 0, 3] -> deal counter
+///   - [0, 0, 0, 4] -> locked USDC: outstanding advances (matched, not yet settled)
+///   - [0, 0, 0, 5] -> liquidity reserved by active offers
+///   - [0, 0, 0, 6] -> utilization snapshot counter
+///   - [0, 0, 0, 7] -> total losses from defaulted deals
+///   - [0, 0, 0, 8] -> max advance per accept_match (0 = no cap)
+///   - [0, 0, 0, 9] -> lowest custom APR accepted, in basis points
+///   - [0, 0, 0, 10] -> highest custom APR accepted, in basis points (0 = no cap)
+///   - [0, 0, 0, 11] -> minimum seconds between offers from one LP
+///   - [0, 0, 0, 12] -> minimum reserve offers may not reserve
+///   - [0, 0, 0, 13] -> highest total earned ever recorded
+///   - [0, 0, 0, 14] -> total earnings withdrawn into the USDC balance
+///   - [0, 0, 0, 15] -> earnings event counter
+///   - [0, 0, 0, 16] -> active offer count
+///   - [0, 0, 0, 17] -> protocol share of advance fees, in basis points (0 = default)
+///   - [0, 0, 0, 18] -> USDC faucet ID [prefix, suffix, 0, 0], set at account creation
+///   - [0, 0, 0, 19] -> admin account ID [prefix, suffix, 0, 0], set at account creation
 /// 
 /// Slot 1 (active_offers):
 ///   - [offer_id, 0, 0, 0] -> offer commitment
 ///   - [offer_id, 1, 0, 0] -> max amount
 ///   - [offer_id, 2, 0, 0] -> min amount
 ///   - [offer_id, 3, 0, 0] -> is active (1 or 0)
+///   - [offer_id, 4, 0, 0] -> remaining capacity: reservation not yet drawn by matched deals
+///   - [offer_id, 5, 0, 0] -> expiry timestamp (0 = never expires)
+///   - [offer_id, 6, 0, 0] -> creating LP account ID [prefix, suffix, 0, 0]
+///   - [offer_id, 7, 0, 0] -> custom APR in basis points (0 = protocol default)
+///   - [offer_id, 8, 0, 0] -> funded amount: total of max amount backed so far
 /// 
 /// Slot 2 (matched_deals):
 ///   - [deal_id, 0, 0, 0] -> user request commitment
 ///   - [deal_id, 1, 0, 0] -> advance amount
 ///   - [deal_id, 2, 0, 0] -> offer id
 ///   - [deal_id, 3, 0, 0] -> settled flag
+///   - [deal_id, 4, 0, 0] -> defaulted flag
+///   - [deal_id, 5, 0, 0] -> cooldown end timestamp
+///   - [deal_id, 6, 0, 0] -> matched at timestamp
+///   - [deal_id, 7, 0, 0] -> LP earnings recorded at settlement
+///   - [deal_id, 8, 0, 0] -> staked assets received at settlement
+///   - [deal_id, 9, 0, 0] -> interest earned at settlement
+///   - [deal_id, 10, 0, 0] -> settlement asset faucet ID [prefix, suffix, 0, 0]
+///   - [deal_id, 11, 0, 0] -> LP earnings the deal's terms projected, recorded at settlement
+///   - [deal_id, 12, 0, 0] -> settlement deadline: cooldown end plus the grace period
+///   - [deal_id, 13, 0, 0] -> loss recorded at default (advance less any recovery)
+///   - [deal_id, 14, 0, 0] -> staked collateral the LP is owed after a default
+///   - [deal_id, 15, 0, 0] -> settled at timestamp
+/// 
+/// Slot 3 (utilization_history), ring buffer of UTILIZATION_HISTORY_SIZE entries:
+///   - [index, 0, 0, 0] -> utilization in basis points
+///   - [index, 1, 0, 0] -> snapshot timestamp
+/// 
+/// Slot 4 (offer_keys):
+///   - client idempotency key -> offer_id + 1 (0 = key unused)
+/// 
+/// Slot 5 (lp_last_offer):
+///   - LP account ID [prefix, suffix, 0, 0] -> timestamp of its last offer
+/// 
+/// Slot 6 (earnings_events), appended on every settlement:
+///   - [index, 0, 0, 0] -> [deal_id, fee credited, interest credited, total earned after]
 #[component]
 struct VoileLpPool {
     #[storage(slot(0), description = "balances")]
@@ -34,38 +80,98 @@ struct VoileLpPool {
     
     #[storage(slot(2), description = "matched deals")]
     matched_deals: StorageMap,
+    
+    #[storage(slot(3), description = "utilization history")]
+    utilization_history: StorageMap,
+    
+    #[storage(slot(4), description = "offer idempotency keys")]
+    offer_keys: StorageMap,
+    
+    #[storage(slot(5), description = "last offer time per LP")]
+    lp_last_offer: StorageMap,
+    
+    #[storage(slot(6), description = "earnings events")]
+    earnings_events: StorageMap,
 }
 
+/// Number of utilization snapshots kept before the oldest is overwritten
+const UTILIZATION_HISTORY_SIZE: u64 = 32;
+
+/// APR applied to offers without a custom APR: 10%
+const DEFAULT_APR_BPS: u64 = 1000;
+
+/// Protocol share of advance fees when none is configured: 20%
+const DEFAULT_PROTOCOL_FEE_BPS: u64 = 2000;
+
+/// Seconds in a day, for converting timestamps to whole days
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Time after a deal's cooldown ends to settle before it can be defaulted
+const SETTLEMENT_GRACE_SECONDS: u64 = 3 * SECONDS_PER_DAY;
+
 #[component]
 impl VoileLpPool {
     // =========================================================================
     // LIQUIDITY MANAGEMENT
     // =========================================================================
     
-    /// Get current USDC balance
+    /// Get the available USDC balance, not counting USDC locked in matched deals
     pub fn get_usdc_balance(&self) -> Felt {
         let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(0)]);
         self.balances.get(&key)
     }
     
+    /// Get the faucet whose assets count as USDC in deposits
+    pub fn get_usdc_faucet(&self) -> Word {
+        let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(18)]);
+        self.balances.get(&key)
+    }
+    
+    /// Move an asset from the note being consumed into the pool's vault
+    /// Only the vault changes; crediting USDC to the balance is `deposit_usdc`
+    pub fn receive_asset(&self, asset: Asset) {
+        account::add_asset(asset);
+    }
+    
     /// Deposit USDC into the pool
     pub fn deposit_usdc(&self, amount: Felt) -> Felt {
         let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(0)]);
         let current: Felt = self.balances.get(&key);
         let new_balance = current + amount;
         self.balances.set(key, new_balance);
+        self.check_liquidity_invariant();
         new_balance
     }
     
     /// Withdraw USDC from the pool
+    /// Aborts if it would leave less available than offers have reserved
     pub fn withdraw_usdc(&self, amount: Felt) -> Felt {
         let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(0)]);
         let current: Felt = self.balances.get(&key);
         let new_balance = current - amount;
         self.balances.set(key, new_balance);
+        self.check_liquidity_invariant();
         new_balance
     }
     
+    /// Get USDC advanced on deals that haven't settled yet
+    pub fn get_outstanding(&self) -> Felt {
+        let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(4)]);
+        self.balances.get(&key)
+    }
+    
+    /// Get USDC locked in matched deals, kept apart from the available balance
+    /// until the deal settles, defaults, or its lock is released
+    pub fn get_locked_balance(&self) -> Felt {
+        self.get_outstanding()
+    }
+    
+    /// Get liquidity reserved by active offers
+    pub fn get_reserved(&self) -> Felt {
+        let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(5)]);
+        self.balances.get(&key)
+    }
+    
     /// Get total earned fees
     pub fn get_total_earned(&self) -> Felt {
         let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(1)]);
@@ -78,9 +184,62 @@ impl VoileLpPool {
         let current: Felt = self.balances.get(&key);
         let new_total = current + amount;
         self.balances.set(key, new_total);
+        self.update_earnings_hwm();
         new_total
     }
     
+    /// Move `amount` of earnings into the USDC balance, where it can be lent
+    /// out again or taken out with `withdraw_usdc`
+    /// Returns 0 for a zero amount or one over the earnings not yet withdrawn
+    pub fn withdraw_earnings(&self, amount: Felt) -> Felt {
+        let earned = self.get_total_earned();
+        if amount == felt!(0) || amount.as_u64() > earned.as_u64() {
+            return felt!(0);
+        }
+        
+        let earned_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(1)]);
+        self.balances.set(earned_key, earned - amount);
+        
+        let withdrawn_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(14)]);
+        let withdrawn: Felt = self.balances.get(&withdrawn_key);
+        self.balances.set(withdrawn_key, withdrawn + amount);
+        
+        let balance_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(0)]);
+        let balance: Felt = self.balances.get(&balance_key);
+        self.balances.set(balance_key, balance + amount);
+        
+        self.check_liquidity_invariant();
+        felt!(1)
+    }
+    
+    /// Get the total earnings moved into the USDC balance
+    pub fn get_earnings_withdrawn(&self) -> Felt {
+        let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(14)]);
+        self.balances.get(&key)
+    }
+    
+    /// Get the highest total earned the pool has reached
+    /// Only ever ratchets up, even if total earned is later adjusted down
+    pub fn get_earnings_hwm(&self) -> Felt {
+        let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(13)]);
+        self.balances.get(&key)
+    }
+    
+    /// Get total losses written off on defaulted deals
+    pub fn get_total_losses(&self) -> Felt {
+        let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(7)]);
+        self.balances.get(&key)
+    }
+    
+    /// Annualized yield over `days`, net of default losses, in basis points
+    /// Earnings and losses are taken relative to the pool's principal
+    /// (available balance plus outstanding advances); a net loss reads as 0
+    pub fn get_net_apy_bps(&self, days: Felt) -> Felt {
+        let earned = self.get_total_earned().as_u64();
+        let losses = self.get_total_losses().as_u64();
+        annualized_bps(earned.saturating_sub(losses), self.principal(), days.as_u64())
+    }
+    
     // =========================================================================
     // OFFER MANAGEMENT
     // =========================================================================
@@ -91,14 +250,104 @@ impl VoileLpPool {
         self.balances.get(&key)
     }
     
+    /// Get the APR bounds custom offer APRs must fall within
+    /// Returns [floor, ceiling, 0, 0]; a zero ceiling means no cap
+    pub fn get_apr_bounds(&self) -> Word {
+        let floor_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(9)]);
+        let ceil_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(10)]);
+        Word::from([
+            self.balances.get(&floor_key),
+            self.balances.get(&ceil_key),
+            felt!(0),
+            felt!(0),
+        ])
+    }
+    
+    /// Set the range (inclusive) a custom offer APR must fall within
+    /// Aborts unless the note was sent by the admin or the pool itself
+    pub fn set_apr_bounds(&self, floor_bps: Felt, ceil_bps: Felt) -> Felt {
+        assert!(self.is_admin_call());
+        let floor_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(9)]);
+        self.balances.set(floor_key, floor_bps);
+        
+        let ceil_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(10)]);
+        self.balances.set(ceil_key, ceil_bps);
+        felt!(1)
+    }
+    
+    /// Get the minimum seconds an LP must wait between offers
+    pub fn get_offer_cooldown(&self) -> Felt {
+        let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(11)]);
+        self.balances.get(&key)
+    }
+    
+    /// Set the minimum seconds an LP must wait between offers (0 = no limit)
+    /// Aborts unless the note was sent by the admin or the pool itself
+    pub fn set_offer_cooldown(&self, offer_cooldown_seconds: Felt) -> Felt {
+        assert!(self.is_admin_call());
+        let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(11)]);
+        self.balances.set(key, offer_cooldown_seconds);
+        offer_cooldown_seconds
+    }
+    
+    /// Get when an LP last created an offer (0 = never)
+    pub fn get_last_offer_timestamp(&self, lp_account_id: Word) -> Felt {
+        self.lp_last_offer.get(&lp_account_id)
+    }
+    
     /// Create a new LP offer
+    /// Pass an `apr_bps` of zero to use the protocol default APR; a custom
+    /// APR outside the configured bounds is rejected
+    /// Only `funded_amount` of the offer's `max_amount` is reserved up front,
+    /// so an offer can be created under-funded and topped up with `fund_offer`
+    /// A `funded_amount` over `max_amount` or `max_safe_offer` is rejected
+    /// Pass an `expires_at` of zero for an offer that never expires
+    /// An LP creating offers faster than the offer cooldown is rejected
+    /// A non-zero `client_idempotency_key` makes retries safe: a repeated
+    /// call with the same key returns the existing offer instead of creating
+    /// another. Pass an all-zero key to skip the check
     /// Returns offer ID
     pub fn create_offer(
         &self,
         max_amount: Felt,
         min_amount: Felt,
+        funded_amount: Felt,
+        apr_bps: Felt,
         offer_commitment: Word,
+        expires_at: Felt,
+        lp_account_id: Word,
+        client_idempotency_key: Word,
+        current_timestamp: Felt,
     ) -> Felt {
+        let has_key = client_idempotency_key != Word::from([felt!(0), felt!(0), felt!(0), felt!(0)]);
+        if has_key {
+            let existing: Felt = self.offer_keys.get(&client_idempotency_key);
+            if existing != felt!(0) {
+                return existing - felt!(1);
+            }
+        }
+        
+        // Rate limit offers per LP
+        let last_offer = self.get_last_offer_timestamp(lp_account_id).as_u64();
+        if last_offer != 0 {
+            let cooldown = self.get_offer_cooldown().as_u64();
+            assert!(current_timestamp.as_u64() >= last_offer + cooldown);
+        }
+        self.lp_last_offer.set(lp_account_id, current_timestamp);
+        
+        // Reject a custom APR outside the protocol bounds
+        if apr_bps != felt!(0) {
+            let bounds = self.get_apr_bounds();
+            let apr = apr_bps.as_u64();
+            let ceiling = bounds[1].as_u64();
+            assert!(apr >= bounds[0].as_u64());
+            assert!(ceiling == 0 || apr <= ceiling);
+        }
+        
+        // Keep the minimum reserve out of reach of offers
+        assert!(funded_amount.as_u64() <= max_amount.as_u64());
+        assert!(funded_amount.as_u64() <= self.max_safe_offer().as_u64());
+        
         // Get and increment offer counter
         let counter_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(2)]);
         let offer_id: Felt = self.balances.get(&counter_key);
@@ -121,9 +370,142 @@ impl VoileLpPool {
         let active_key = Word::from([offer_id, felt!(3), felt!(0), felt!(0)]);
         self.active_offers.set(active_key, felt!(1));
         
+        let active_count_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(16)]);
+        let active_count: Felt = self.balances.get(&active_count_key);
+        self.balances.set(active_count_key, active_count + felt!(1));
+        
+        // Store expiry
+        let expiry_key = Word::from([offer_id, felt!(5), felt!(0), felt!(0)]);
+        self.active_offers.set(expiry_key, expires_at);
+        
+        // Store the LP that owns the offer
+        let lp_key = Word::from([offer_id, felt!(6), felt!(0), felt!(0)]);
+        self.active_offers.set(lp_key, lp_account_id);
+        
+        // Store APR
+        let apr_key = Word::from([offer_id, felt!(7), felt!(0), felt!(0)]);
+        self.active_offers.set(apr_key, apr_bps);
+        
+        // Remember the key so a retry finds this offer
+        if has_key {
+            self.offer_keys.set(client_idempotency_key, offer_id + felt!(1));
+        }
+        
+        // Reserve liquidity for the funded part of the offer
+        let funded_key = Word::from([offer_id, felt!(8), felt!(0), felt!(0)]);
+        self.active_offers.set(funded_key, funded_amount);
+        
+        let offer_reserved_key = Word::from([offer_id, felt!(4), felt!(0), felt!(0)]);
+        self.active_offers.set(offer_reserved_key, funded_amount);
+        
+        let reserved_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(5)]);
+        let reserved: Felt = self.balances.get(&reserved_key);
+        self.balances.set(reserved_key, reserved + funded_amount);
+        
+        self.check_liquidity_invariant();
         offer_id
     }
     
+    /// Back more of an active offer's max amount with pool liquidity
+    /// The amount joins the offer's remaining capacity for matches
+    /// Aborts if the offer isn't active, or if the amount would fund past
+    /// the offer's max amount or exceeds `max_safe_offer`
+    pub fn fund_offer(&self, offer_id: Felt, amount: Felt) -> Felt {
+        assert!(self.is_offer_active(offer_id) == felt!(1));
+        
+        let funded = self.get_offer_funded(offer_id);
+        assert!(funded.as_u64() + amount.as_u64() <= self.get_offer_max(offer_id).as_u64());
+        assert!(amount.as_u64() <= self.max_safe_offer().as_u64());
+        
+        let funded_key = Word::from([offer_id, felt!(8), felt!(0), felt!(0)]);
+        self.active_offers.set(funded_key, funded + amount);
+        
+        let offer_reserved_key = Word::from([offer_id, felt!(4), felt!(0), felt!(0)]);
+        let offer_reserved: Felt = self.active_offers.get(&offer_reserved_key);
+        self.active_offers.set(offer_reserved_key, offer_reserved + amount);
+        
+        let reserved_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(5)]);
+        let reserved: Felt = self.balances.get(&reserved_key);
+        self.balances.set(reserved_key, reserved + amount);
+        
+        self.check_liquidity_invariant();
+        felt!(1)
+    }
+    
+    /// USDC that must be deposited before an offer of `max_amount` can be fully funded
+    /// 0 if `max_safe_offer` already covers it
+    pub fn shortfall_for_offer(&self, max_amount: Felt) -> Felt {
+        let available = self.max_safe_offer().as_u64();
+        Felt::from_u64_unchecked(max_amount.as_u64().saturating_sub(available))
+    }
+    
+    /// Most an offer can be funded with, at creation or through `fund_offer`
+    /// The available balance less what other offers already reserve and the
+    /// minimum reserve; 0 if none is left
+    /// Outstanding advances don't count, since they may never come back
+    pub fn max_safe_offer(&self) -> Felt {
+        let backing = self.get_usdc_balance().as_u64();
+        let committed = self.get_reserved().as_u64() + self.get_min_reserve().as_u64();
+        Felt::from_u64_unchecked(backing.saturating_sub(committed))
+    }
+    
+    /// Get the protocol's share of advance fees in basis points; the LP keeps
+    /// the rest
+    pub fn get_fee_split(&self) -> Felt {
+        let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(17)]);
+        let protocol_bps: Felt = self.balances.get(&key);
+        if protocol_bps == felt!(0) {
+            Felt::from_u64_unchecked(DEFAULT_PROTOCOL_FEE_BPS)
+        } else {
+            protocol_bps
+        }
+    }
+    
+    /// Set the protocol's share of advance fees, applied to settlements from
+    /// now on; zero restores the default
+    /// Aborts unless the note was sent by the admin or the pool itself, or on
+    /// a share over 10000 bps
+    pub fn set_fee_split(&self, protocol_bps: Felt) -> Felt {
+        assert!(self.is_admin_call());
+        assert!(protocol_bps.as_u64() <= 10000);
+        let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(17)]);
+        self.balances.set(key, protocol_bps);
+        felt!(1)
+    }
+    
+    /// Get the account allowed to change pool parameters such as the fee split
+    pub fn get_admin(&self) -> Word {
+        let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(19)]);
+        self.balances.get(&key)
+    }
+    
+    /// Protocol's share of an advance fee under the current split
+    pub fn calculate_protocol_fee(&self, fee: Felt) -> Felt {
+        let protocol_bps = self.get_fee_split().as_u64() as u128;
+        Felt::from_u64_unchecked((fee.as_u64() as u128 * protocol_bps / 10000) as u64)
+    }
+    
+    /// LP's share of an advance fee: whatever the protocol doesn't take
+    pub fn calculate_lp_fee(&self, fee: Felt) -> Felt {
+        fee - self.calculate_protocol_fee(fee)
+    }
+    
+    /// Get the liquidity kept back from offers
+    pub fn get_min_reserve(&self) -> Felt {
+        let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(12)]);
+        self.balances.get(&key)
+    }
+    
+    /// Set the liquidity kept back from offers
+    /// Only applies to offers created afterwards
+    /// Aborts unless the note was sent by the admin or the pool itself
+    pub fn set_min_reserve(&self, amount: Felt) -> Felt {
+        assert!(self.is_admin_call());
+        let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(12)]);
+        self.balances.set(key, amount);
+        felt!(1)
+    }
+    
     /// Get offer max amount
     pub fn get_offer_max(&self, offer_id: Felt) -> Felt {
         let key = Word::from([offer_id, felt!(1), felt!(0), felt!(0)]);
@@ -142,17 +524,156 @@ impl VoileLpPool {
         self.active_offers.get(&key)
     }
     
+    /// Get the part of an offer's reservation not yet drawn by matched deals
+    pub fn get_offer_reserved(&self, offer_id: Felt) -> Felt {
+        let key = Word::from([offer_id, felt!(4), felt!(0), felt!(0)]);
+        self.active_offers.get(&key)
+    }
+    
+    /// Get how much more an offer can advance with its current funding
+    /// Rises as the offer is funded and falls with each match
+    pub fn get_offer_remaining(&self, offer_id: Felt) -> Felt {
+        self.get_offer_reserved(offer_id)
+    }
+    
+    /// Get how much of an offer's max amount has been funded
+    pub fn get_offer_funded(&self, offer_id: Felt) -> Felt {
+        let key = Word::from([offer_id, felt!(8), felt!(0), felt!(0)]);
+        self.active_offers.get(&key)
+    }
+    
+    /// Get the offer created with an idempotency key as offer_id + 1 (0 = none)
+    pub fn get_offer_by_key(&self, client_idempotency_key: Word) -> Felt {
+        self.offer_keys.get(&client_idempotency_key)
+    }
+    
+    /// Get offer expiry timestamp (0 = never expires)
+    pub fn get_offer_expiry(&self, offer_id: Felt) -> Felt {
+        let key = Word::from([offer_id, felt!(5), felt!(0), felt!(0)]);
+        self.active_offers.get(&key)
+    }
+    
+    /// Get offer APR in basis points (0 = protocol default)
+    pub fn get_offer_apr(&self, offer_id: Felt) -> Felt {
+        let key = Word::from([offer_id, felt!(7), felt!(0), felt!(0)]);
+        self.active_offers.get(&key)
+    }
+    
+    /// Get the LP account that created an offer as [prefix, suffix, 0, 0]
+    pub fn get_offer_lp(&self, offer_id: Felt) -> Word {
+        let key = Word::from([offer_id, felt!(6), felt!(0), felt!(0)]);
+        self.active_offers.get(&key)
+    }
+    
     /// Cancel an active offer
+    /// Only the offer's undrawn reservation is released; deals already
+    /// matched from it stay outstanding and settle as usual
     pub fn cancel_offer(&self, offer_id: Felt) -> Felt {
-        let active_key = Word::from([offer_id, felt!(3), felt!(0), felt!(0)]);
-        self.active_offers.set(active_key, felt!(0));
+        self.deactivate_offer(offer_id);
+        self.check_liquidity_invariant();
         felt!(1)
     }
     
+    /// Deactivate every active offer past its expiry and release its reservation
+    /// Returns the number of offers swept
+    pub fn sweep_expired_offers(&self, current_timestamp: Felt) -> Felt {
+        let offer_count = self.get_offer_counter().as_u64();
+        let now = current_timestamp.as_u64();
+        let mut swept = felt!(0);
+        
+        for id in 0..offer_count {
+            let offer_id = Felt::from_u64_unchecked(id);
+            let expiry = self.get_offer_expiry(offer_id).as_u64();
+            
+            if expiry != 0 && expiry <= now && self.deactivate_offer(offer_id) {
+                swept = swept + felt!(1);
+            }
+        }
+        
+        self.check_liquidity_invariant();
+        swept
+    }
+    
+    /// Flag active offers that can never be matched
+    /// An offer is dead if its min exceeds its max, or exceeds what it could
+    /// still advance: the lesser of its undrawn reservation and the pool's
+    /// available balance
+    /// Covers the 128 offers from `start` onward as a bitmask, 32 per element:
+    /// offer `start + i` is bit `i % 32` of element `i / 32`. Page through
+    /// the book by calling again from `start + 128`
+    pub fn get_dead_offers(&self, start: Felt) -> Word {
+        let start = start.as_u64();
+        let end = self.get_offer_counter().as_u64().min(start.saturating_add(128));
+        let balance = self.get_usdc_balance().as_u64();
+        let mut masks = [0u64; 4];
+    
+        for id in start..end {
+            let offer_id = Felt::from_u64_unchecked(id);
+            if self.is_offer_active(offer_id) != felt!(1) {
+                continue;
+            }
+    
+            let min = self.get_offer_min(offer_id).as_u64();
+            let max = self.get_offer_max(offer_id).as_u64();
+            let capacity = self.get_offer_reserved(offer_id).as_u64().min(balance);
+            if min > max || min > capacity {
+                let bit = id - start;
+                masks[(bit / 32) as usize] |= 1 << (bit % 32);
+            }
+        }
+    
+        Word::from(masks.map(Felt::from_u64_unchecked))
+    }
+    
+    /// Get the number of offers currently active
+    pub fn get_active_offer_count(&self) -> Felt {
+        let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(16)]);
+        self.balances.get(&key)
+    }
+    
+    /// Page through active offers for indexers: the first `count` (at most 4)
+    /// active offer IDs from `start` onward, each as offer_id + 1
+    /// Unused elements are 0; resume from one past the last ID returned
+    pub fn get_offer_ids_range(&self, start: Felt, count: Felt) -> Word {
+        let offer_count = self.get_offer_counter().as_u64();
+        let limit = count.as_u64().min(4) as usize;
+        let mut ids = [felt!(0); 4];
+        let mut found = 0;
+        
+        let mut id = start.as_u64();
+        while found < limit && id < offer_count {
+            let offer_id = Felt::from_u64_unchecked(id);
+            if self.is_offer_active(offer_id) == felt!(1) {
+                ids[found] = offer_id + felt!(1);
+                found += 1;
+            }
+            id += 1;
+        }
+        
+        Word::from(ids)
+    }
+    
     // =========================================================================
     // MATCHING & DEAL EXECUTION
     // =========================================================================
     
+    /// Get the most a single `accept_match` may advance (0 = no cap)
+    pub fn get_max_per_tx_advance(&self) -> Felt {
+        let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(8)]);
+        self.balances.get(&key)
+    }
+    
+    /// Cap how much a single `accept_match` may advance, whatever the offer
+    /// allows; a circuit breaker against a bug draining the pool in one deal
+    /// Pass zero to remove the cap
+    /// Aborts unless the note was sent by the admin or the pool itself
+    pub fn set_max_per_tx_advance(&self, amount: Felt) -> Felt {
+        assert!(self.is_admin_call());
+        let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(8)]);
+        self.balances.set(key, amount);
+        amount
+    }
+    
     /// Get current deal counter
     pub fn get_deal_counter(&self) -> Felt {
         let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(3)]);
@@ -160,19 +681,47 @@ impl VoileLpPool {
     }
     
     /// Accept a match with a user's unlock request
+    /// `settlement_asset_id` is the faucet the deal must be repaid in, which
+    /// may differ from the USDC the advance is paid in
+    /// An offer's max amount caps the total it advances across all its deals:
+    /// aborts if `advance_amount` exceeds the offer's funded remaining
+    /// capacity, and the offer deactivates once what's left, funded or not,
+    /// is below its min amount, since no further match could use it
     /// Returns deal_id
     pub fn accept_match(
         &self,
         offer_id: Felt,
         user_request_commitment: Word,
         advance_amount: Felt,
+        cooldown_end: Felt,
+        matched_at: Felt,
+        settlement_asset_id: Word,
     ) -> Felt {
+        // Enforce the per-transaction circuit breaker
+        let max_per_tx = self.get_max_per_tx_advance().as_u64();
+        assert!(max_per_tx == 0 || advance_amount.as_u64() <= max_per_tx);
+        
         // Lock USDC for advance
         let balance_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(0)]);
         let balance: Felt = self.balances.get(&balance_key);
+        assert!(advance_amount.as_u64() <= balance.as_u64());
         let new_balance = balance - advance_amount;
         self.balances.set(balance_key, new_balance);
         
+        let outstanding_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(4)]);
+        let outstanding: Felt = self.balances.get(&outstanding_key);
+        self.balances.set(outstanding_key, outstanding + advance_amount);
+        
+        // The advance is now outstanding, so draw it from the offer's reservation
+        assert!(advance_amount.as_u64() <= self.get_offer_remaining(offer_id).as_u64());
+        self.draw_offer_reservation(offer_id, advance_amount);
+        let max_amount = self.get_offer_max(offer_id).as_u64();
+        let unfunded = max_amount - self.get_offer_funded(offer_id).as_u64();
+        let remaining = self.get_offer_remaining(offer_id).as_u64() + unfunded;
+        if remaining == 0 || remaining < self.get_offer_min(offer_id).as_u64() {
+            self.deactivate_offer(offer_id);
+        }
+        
         // Get and increment deal counter
         let counter_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(3)]);
         let deal_id: Felt = self.balances.get(&counter_key);
@@ -191,9 +740,76 @@ impl VoileLpPool {
         let offer_key = Word::from([deal_id, felt!(2), felt!(0), felt!(0)]);
         self.matched_deals.set(offer_key, offer_id);
         
+        // Store deal - cooldown end
+        let cooldown_key = Word::from([deal_id, felt!(5), felt!(0), felt!(0)]);
+        self.matched_deals.set(cooldown_key, cooldown_end);
+        
+        // Store deal - matched at
+        let matched_at_key = Word::from([deal_id, felt!(6), felt!(0), felt!(0)]);
+        self.matched_deals.set(matched_at_key, matched_at);
+        
+        // Store deal - settlement deadline
+        let deadline = Felt::from_u64_unchecked(cooldown_end.as_u64() + SETTLEMENT_GRACE_SECONDS);
+        let deadline_key = Word::from([deal_id, felt!(12), felt!(0), felt!(0)]);
+        self.matched_deals.set(deadline_key, deadline);
+        
+        // Store deal - settlement asset
+        let settlement_asset_key = Word::from([deal_id, felt!(10), felt!(0), felt!(0)]);
+        self.matched_deals.set(settlement_asset_key, settlement_asset_id);
+        
+        self.check_liquidity_invariant();
         deal_id
     }
     
+    /// Cancel a match whose advance was never delivered
+    /// Releases the deal's whole lock, clearing the deal
+    pub fn cancel_match(&self, deal_id: Felt) -> Felt {
+        self.release_lock(deal_id, self.get_deal_amount(deal_id))
+    }
+    
+    /// Return `amount` of an abandoned match's locked advance to the
+    /// available balance, shrinking the deal's advance by as much
+    /// Releasing the whole advance clears the deal. The amount goes back into
+    /// its offer's reservation if the offer is still active
+    /// Returns 0 if the deal is settled or defaulted, or `amount` is more than
+    /// the deal has locked
+    pub fn release_lock(&self, deal_id: Felt, amount: Felt) -> Felt {
+        let amount_key = Word::from([deal_id, felt!(1), felt!(0), felt!(0)]);
+        let advance_amount: Felt = self.matched_deals.get(&amount_key);
+        if !self.is_deal_open(deal_id) || amount.as_u64() > advance_amount.as_u64() {
+            return felt!(0);
+        }
+        
+        let offer_id = self.get_deal_offer(deal_id);
+        if self.is_offer_active(offer_id) == felt!(1) {
+            let offer_reserved_key = Word::from([offer_id, felt!(4), felt!(0), felt!(0)]);
+            let offer_reserved: Felt = self.active_offers.get(&offer_reserved_key);
+            self.active_offers.set(offer_reserved_key, offer_reserved + amount);
+            
+            let reserved_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(5)]);
+            let reserved: Felt = self.balances.get(&reserved_key);
+            self.balances.set(reserved_key, reserved + amount);
+        }
+        
+        let balance_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(0)]);
+        let balance: Felt = self.balances.get(&balance_key);
+        self.balances.set(balance_key, balance + amount);
+        
+        let outstanding_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(4)]);
+        let outstanding: Felt = self.balances.get(&outstanding_key);
+        self.balances.set(outstanding_key, outstanding - amount);
+        
+        let remaining = advance_amount - amount;
+        self.matched_deals.set(amount_key, remaining);
+        if remaining == felt!(0) {
+            let commit_key = Word::from([deal_id, felt!(0), felt!(0), felt!(0)]);
+            self.matched_deals.set(commit_key, felt!(0));
+        }
+        
+        self.check_liquidity_invariant();
+        felt!(1)
+    }
+    
     /// Get deal advance amount
     pub fn get_deal_amount(&self, deal_id: Felt) -> Felt {
         let key = Word::from([deal_id, felt!(1), felt!(0), felt!(0)]);
@@ -206,34 +822,145 @@ impl VoileLpPool {
         self.matched_deals.get(&key)
     }
     
+    /// Get the faucet a deal must be settled in, as [prefix, suffix, 0, 0]
+    pub fn get_deal_settlement_asset(&self, deal_id: Felt) -> Word {
+        let key = Word::from([deal_id, felt!(10), felt!(0), felt!(0)]);
+        self.matched_deals.get(&key)
+    }
+    
+    /// Get the timestamp a deal was matched
+    pub fn get_deal_matched_at(&self, deal_id: Felt) -> Felt {
+        let key = Word::from([deal_id, felt!(6), felt!(0), felt!(0)]);
+        self.matched_deals.get(&key)
+    }
+    
+    /// Most interest a deal can earn under its agreed terms
+    /// The offer's APR over the deal's cooldown, on the principal the
+    /// advance was made against (advance plus the 5% fee)
+    pub fn get_max_deal_interest(&self, deal_id: Felt) -> Felt {
+        let principal = self.deal_principal(deal_id);
+        let apr_bps = self.deal_apr_bps(deal_id);
+        
+        let cooldown_end = self.get_deal_cooldown_end(deal_id).as_u64();
+        let matched_at = self.get_deal_matched_at(deal_id).as_u64();
+        let days = cooldown_end.saturating_sub(matched_at).div_ceil(SECONDS_PER_DAY);
+        
+        let interest = principal * apr_bps as u128 * days as u128 / (10000 * 365);
+        Felt::from_u64_unchecked(interest as u64)
+    }
+    
+    /// Get the timestamp a deal's cooldown ends
+    pub fn get_deal_cooldown_end(&self, deal_id: Felt) -> Felt {
+        let key = Word::from([deal_id, felt!(5), felt!(0), felt!(0)]);
+        self.matched_deals.get(&key)
+    }
+    
+    /// Get the timestamp a deal stops being settleable and can be defaulted
+    /// Fixed at match time as the cooldown end plus the settlement grace period
+    pub fn get_deal_settlement_deadline(&self, deal_id: Felt) -> Felt {
+        let key = Word::from([deal_id, felt!(12), felt!(0), felt!(0)]);
+        self.matched_deals.get(&key)
+    }
+    
+    /// Advance-weighted average of whole days left until open deals settle
+    /// Deals past their cooldown count as zero days; returns 0 with no open deals
+    pub fn get_weighted_avg_settlement_days(&self, current_timestamp: Felt) -> Felt {
+        let deal_count = self.get_deal_counter().as_u64();
+        let now = current_timestamp.as_u64();
+        let mut total_advanced: u128 = 0;
+        let mut weighted_days: u128 = 0;
+        
+        for id in 0..deal_count {
+            let deal_id = Felt::from_u64_unchecked(id);
+            if !self.is_deal_open(deal_id) {
+                continue;
+            }
+            
+            let advance = self.get_deal_amount(deal_id).as_u64() as u128;
+            let remaining = self.get_deal_cooldown_end(deal_id).as_u64().saturating_sub(now);
+            total_advanced += advance;
+            weighted_days += advance * remaining.div_ceil(SECONDS_PER_DAY) as u128;
+        }
+        
+        if total_advanced == 0 {
+            return felt!(0);
+        }
+        Felt::from_u64_unchecked((weighted_days / total_advanced) as u64)
+    }
+    
     // =========================================================================
     // SETTLEMENT
     // =========================================================================
     
     /// Record settlement completion
+    /// Settles regardless of whether the deal's offer has since been
+    /// cancelled, since the match was already committed
+    /// Aborts if `interest_earned` is more than the deal's agreed APR allows,
+    /// or if `received_asset_id` isn't the deal's settlement asset
+    /// Returns 0 without recording anything if the deal doesn't exist or was
+    /// cancelled, is already settled or defaulted, or isn't settleable at
+    /// `current_timestamp`: before its cooldown ends or from its settlement
+    /// deadline on
     pub fn record_settlement(
         &self,
         deal_id: Felt,
+        received_asset_id: Word,
         staked_assets_received: Felt,
         fee_earned: Felt,
+        interest_earned: Felt,
+        current_timestamp: Felt,
     ) -> Felt {
+        // A second settlement would count the deal's earnings twice, and one
+        // past the deadline would race the deal's default
+        if self.is_settleable(deal_id, current_timestamp) == felt!(0) {
+            return felt!(0);
+        }
+        
+        assert!(received_asset_id == self.get_deal_settlement_asset(deal_id));
+        assert!(interest_earned.as_u64() <= self.get_max_deal_interest(deal_id).as_u64());
+        
         // Add staked assets to balance (converting to USDC equivalent)
         let balance_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(0)]);
         let balance: Felt = self.balances.get(&balance_key);
         let new_balance = balance + staked_assets_received;
         self.balances.set(balance_key, new_balance);
         
-        // Add to earnings (LP gets 80% of fees)
-        // fee_earned * 4 / 5 = 80%
-        let lp_fee = fee_earned * felt!(4) / felt!(5);
+        // The deal's advance is no longer outstanding
+        let outstanding_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(4)]);
+        let outstanding: Felt = self.balances.get(&outstanding_key);
+        self.balances.set(outstanding_key, outstanding - self.get_deal_amount(deal_id));
+        
+        // Add to earnings (LP gets its share of fees and all interest)
+        let lp_fee = self.calculate_lp_fee(fee_earned);
+        let deal_earned = lp_fee + interest_earned;
         let earned_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(1)]);
         let current_earned: Felt = self.balances.get(&earned_key);
-        self.balances.set(earned_key, current_earned + lp_fee);
+        self.balances.set(earned_key, current_earned + deal_earned);
+        self.update_earnings_hwm();
+        self.emit_earnings_event(deal_id, lp_fee, interest_earned);
+        
+        let deal_earned_key = Word::from([deal_id, felt!(7), felt!(0), felt!(0)]);
+        self.matched_deals.set(deal_earned_key, deal_earned);
+        
+        let staked_key = Word::from([deal_id, felt!(8), felt!(0), felt!(0)]);
+        self.matched_deals.set(staked_key, staked_assets_received);
+        
+        let interest_key = Word::from([deal_id, felt!(9), felt!(0), felt!(0)]);
+        self.matched_deals.set(interest_key, interest_earned);
+        
+        // What the deal would have earned settling in full at its agreed APR
+        let projected = self.deal_lp_fee(deal_id) + self.get_max_deal_interest(deal_id);
+        let projected_key = Word::from([deal_id, felt!(11), felt!(0), felt!(0)]);
+        self.matched_deals.set(projected_key, projected);
         
         // Mark deal as settled
         let settled_key = Word::from([deal_id, felt!(3), felt!(0), felt!(0)]);
         self.matched_deals.set(settled_key, felt!(1));
         
+        let settled_at_key = Word::from([deal_id, felt!(15), felt!(0), felt!(0)]);
+        self.matched_deals.set(settled_at_key, current_timestamp);
+        
+        self.check_liquidity_invariant();
         felt!(1)
     }
     
@@ -242,4 +969,424 @@ impl VoileLpPool {
         let key = Word::from([deal_id, felt!(3), felt!(0), felt!(0)]);
         self.matched_deals.get(&key)
     }
+    
+    /// Get the LP earnings recorded when a deal settled
+    pub fn get_deal_earned(&self, deal_id: Felt) -> Felt {
+        let key = Word::from([deal_id, felt!(7), felt!(0), felt!(0)]);
+        self.matched_deals.get(&key)
+    }
+    
+    /// Get the staked assets a deal's settlement delivered
+    pub fn get_deal_staked_received(&self, deal_id: Felt) -> Felt {
+        let key = Word::from([deal_id, felt!(8), felt!(0), felt!(0)]);
+        self.matched_deals.get(&key)
+    }
+    
+    /// Get the interest recorded when a deal settled
+    pub fn get_deal_interest(&self, deal_id: Felt) -> Felt {
+        let key = Word::from([deal_id, felt!(9), felt!(0), felt!(0)]);
+        self.matched_deals.get(&key)
+    }
+    
+    /// Get the timestamp a deal settled at; 0 until it settles
+    pub fn get_deal_settled_at(&self, deal_id: Felt) -> Felt {
+        let key = Word::from([deal_id, felt!(15), felt!(0), felt!(0)]);
+        self.matched_deals.get(&key)
+    }
+    
+    /// Get the APR a deal accrues interest at, in basis points: its offer's,
+    /// or the default
+    pub fn get_deal_apr_bps(&self, deal_id: Felt) -> Felt {
+        Felt::from_u64_unchecked(self.deal_apr_bps(deal_id))
+    }
+    
+    /// Get the LP earnings a settled deal's terms projected
+    pub fn get_deal_projected_earned(&self, deal_id: Felt) -> Felt {
+        let key = Word::from([deal_id, felt!(11), felt!(0), felt!(0)]);
+        self.matched_deals.get(&key)
+    }
+    
+    /// Realized minus projected earnings across settled deals, as
+    /// [magnitude, is_shortfall, 0, 0]
+    /// is_shortfall is 1 when settlements earned less than their terms
+    /// projected, e.g. from late or partial settlement
+    pub fn get_earnings_variance(&self) -> Word {
+        let deal_count = self.get_deal_counter().as_u64();
+        let mut realized: u64 = 0;
+        let mut projected: u64 = 0;
+        
+        for id in 0..deal_count {
+            let deal_id = Felt::from_u64_unchecked(id);
+            if self.is_deal_settled(deal_id) == felt!(1) {
+                realized += self.get_deal_earned(deal_id).as_u64();
+                projected += self.get_deal_projected_earned(deal_id).as_u64();
+            }
+        }
+        
+        let (magnitude, is_shortfall) = if realized < projected {
+            (projected - realized, felt!(1))
+        } else {
+            (realized - projected, felt!(0))
+        };
+        Word::from([Felt::from_u64_unchecked(magnitude), is_shortfall, felt!(0), felt!(0)])
+    }
+    
+    /// Get the number of earnings events logged
+    pub fn get_earnings_event_count(&self) -> Felt {
+        let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(15)]);
+        self.balances.get(&key)
+    }
+    
+    /// Get a logged earnings event as
+    /// [deal_id, fee credited, interest credited, total earned after]
+    pub fn get_earnings_event(&self, index: Felt) -> Word {
+        let key = Word::from([index, felt!(0), felt!(0), felt!(0)]);
+        self.earnings_events.get(&key)
+    }
+    
+    /// Check the running total earned, plus what's been withdrawn from it,
+    /// against the per-deal settlement records
+    /// Returns 1 if they agree, 0 if earnings were double-counted or added
+    /// outside a settlement
+    pub fn reconcile(&self) -> Felt {
+        let deal_count = self.get_deal_counter().as_u64();
+        let mut recorded = felt!(0);
+        
+        for id in 0..deal_count {
+            let deal_id = Felt::from_u64_unchecked(id);
+            if self.is_deal_settled(deal_id) == felt!(1) {
+                recorded = recorded + self.get_deal_earned(deal_id);
+            }
+        }
+        
+        if recorded == self.get_total_earned() + self.get_earnings_withdrawn() {
+            felt!(1)
+        } else {
+            felt!(0)
+        }
+    }
+    
+    /// Default a deal whose user never delivered the staked assets, once
+    /// `current_timestamp` reaches `grace_deadline`
+    /// The deal's own settlement deadline still applies, so a caller's grace
+    /// deadline can only extend the wait, never shorten it
+    /// The LP is then owed the deal's staked collateral, readable through
+    /// `get_deal_collateral_owed`
+    /// Returns 0 if the deal was never matched or is already closed, or either
+    /// the grace deadline or the settlement deadline hasn't been reached
+    pub fn record_default(
+        &self,
+        deal_id: Felt,
+        current_timestamp: Felt,
+        grace_deadline: Felt,
+    ) -> Felt {
+        if current_timestamp.as_u64() < grace_deadline.as_u64() {
+            return felt!(0);
+        }
+        self.mark_deal_defaulted(deal_id, current_timestamp)
+    }
+    
+    /// Write off a deal whose collateral will never arrive
+    /// The advance leaves outstanding and is recorded as a loss
+    pub fn mark_deal_defaulted(&self, deal_id: Felt, current_timestamp: Felt) -> Felt {
+        self.record_default_with_recovery(deal_id, felt!(0), current_timestamp)
+    }
+    
+    /// Write off a deal, crediting back what was recovered from its collateral
+    /// Only the advance minus the recovery is recorded as a loss
+    /// With nothing recovered, the LP is owed the deal's staked collateral
+    /// Returns 0 if the deal was never matched or is already closed, its
+    /// settlement deadline hasn't been reached, or the recovery exceeds the
+    /// advance
+    pub fn record_default_with_recovery(
+        &self,
+        deal_id: Felt,
+        recovered_amount: Felt,
+        current_timestamp: Felt,
+    ) -> Felt {
+        if !self.is_deal_open(deal_id) {
+            return felt!(0);
+        }
+        
+        if current_timestamp.as_u64() < self.get_deal_settlement_deadline(deal_id).as_u64() {
+            return felt!(0);
+        }
+        
+        let advance_amount = self.get_deal_amount(deal_id);
+        if recovered_amount.as_u64() > advance_amount.as_u64() {
+            return felt!(0);
+        }
+        
+        let outstanding_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(4)]);
+        let outstanding: Felt = self.balances.get(&outstanding_key);
+        self.balances.set(outstanding_key, outstanding - advance_amount);
+        
+        // Recovered collateral returns to the pool
+        let balance_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(0)]);
+        let balance: Felt = self.balances.get(&balance_key);
+        self.balances.set(balance_key, balance + recovered_amount);
+        
+        let loss = advance_amount - recovered_amount;
+        let losses_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(7)]);
+        let losses: Felt = self.balances.get(&losses_key);
+        self.balances.set(losses_key, losses + loss);
+        
+        let deal_loss_key = Word::from([deal_id, felt!(13), felt!(0), felt!(0)]);
+        self.matched_deals.set(deal_loss_key, loss);
+        
+        if recovered_amount == felt!(0) {
+            let owed = Felt::from_u64_unchecked(self.deal_principal(deal_id) as u64);
+            let owed_key = Word::from([deal_id, felt!(14), felt!(0), felt!(0)]);
+            self.matched_deals.set(owed_key, owed);
+        }
+        
+        let defaulted_key = Word::from([deal_id, felt!(4), felt!(0), felt!(0)]);
+        self.matched_deals.set(defaulted_key, felt!(1));
+        
+        self.check_liquidity_invariant();
+        felt!(1)
+    }
+    
+    /// Check if a deal has defaulted
+    pub fn is_deal_defaulted(&self, deal_id: Felt) -> Felt {
+        let key = Word::from([deal_id, felt!(4), felt!(0), felt!(0)]);
+        self.matched_deals.get(&key)
+    }
+    
+    /// Get the staked collateral the LP is owed by a defaulted user
+    /// 0 unless the deal defaulted with nothing recovered
+    pub fn get_deal_collateral_owed(&self, deal_id: Felt) -> Felt {
+        let key = Word::from([deal_id, felt!(14), felt!(0), felt!(0)]);
+        self.matched_deals.get(&key)
+    }
+    
+    /// Get the loss recorded when a deal defaulted
+    pub fn get_deal_default_loss(&self, deal_id: Felt) -> Felt {
+        let key = Word::from([deal_id, felt!(13), felt!(0), felt!(0)]);
+        self.matched_deals.get(&key)
+    }
+    
+    /// LP profit and loss on a deal as [realized, unrealized, 0, 0]
+    /// While the deal is open, realized is the LP's share of the fee withheld
+    /// from the advance and unrealized is interest accrued since the match, up
+    /// to the end of the cooldown. Once settled, realized is what settlement
+    /// recorded and nothing is unrealized. Defaulted or cancelled deals read as
+    /// zero
+    pub fn get_deal_pnl(&self, deal_id: Felt, current_timestamp: Felt) -> Word {
+        if self.is_deal_settled(deal_id) == felt!(1) {
+            return Word::from([self.get_deal_earned(deal_id), felt!(0), felt!(0), felt!(0)]);
+        }
+        if !self.is_deal_open(deal_id) {
+            return Word::from([felt!(0), felt!(0), felt!(0), felt!(0)]);
+        }
+        
+        let principal = self.deal_principal(deal_id);
+        let lp_fee = self.deal_lp_fee(deal_id);
+        
+        let matched_at = self.get_deal_matched_at(deal_id).as_u64();
+        let cooldown_end = self.get_deal_cooldown_end(deal_id).as_u64();
+        let elapsed = current_timestamp.as_u64().min(cooldown_end).saturating_sub(matched_at) as u128;
+        let accrued = principal * self.deal_apr_bps(deal_id) as u128 * elapsed
+            / (10000 * 365 * SECONDS_PER_DAY as u128);
+        
+        Word::from([
+            lp_fee,
+            Felt::from_u64_unchecked(accrued as u64),
+            felt!(0),
+            felt!(0),
+        ])
+    }
+    
+    /// Check a deal is ready for `record_settlement`
+    /// Returns 1 if the deal exists with its advance still outstanding, isn't
+    /// settled or defaulted, and its cooldown has ended before its settlement
+    /// deadline; from the deadline on the deal goes down the default path
+    pub fn is_settleable(&self, deal_id: Felt, current_timestamp: Felt) -> Felt {
+        if deal_id.as_u64() >= self.get_deal_counter().as_u64() || !self.is_deal_open(deal_id) {
+            return felt!(0);
+        }
+    
+        let now = current_timestamp.as_u64();
+        if now < self.get_deal_cooldown_end(deal_id).as_u64()
+            || now >= self.get_deal_settlement_deadline(deal_id).as_u64()
+        {
+            return felt!(0);
+        }
+        felt!(1)
+    }
+    
+    // =========================================================================
+    // UTILIZATION HISTORY
+    // =========================================================================
+    
+    /// Share of the pool locked in matched deals, in basis points:
+    /// locked * 10000 / (locked + available)
+    /// Returns 0 for an empty pool
+    pub fn get_utilization_bps(&self) -> Felt {
+        let locked = self.get_locked_balance().as_u64() as u128;
+        let total = locked + self.get_usdc_balance().as_u64() as u128;
+        
+        if total == 0 {
+            return felt!(0);
+        }
+        Felt::from_u64_unchecked((locked * 10000 / total) as u64)
+    }
+    
+    /// Record the current utilization in the history ring buffer
+    /// Returns the buffer index written
+    pub fn record_utilization_snapshot(&self, timestamp: Felt) -> Felt {
+        let counter_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(6)]);
+        let count: Felt = self.balances.get(&counter_key);
+        self.balances.set(counter_key, count + felt!(1));
+        
+        let index = Felt::from_u64_unchecked(count.as_u64() % UTILIZATION_HISTORY_SIZE);
+        
+        let bps_key = Word::from([index, felt!(0), felt!(0), felt!(0)]);
+        self.utilization_history.set(bps_key, self.get_utilization_bps());
+        
+        let timestamp_key = Word::from([index, felt!(1), felt!(0), felt!(0)]);
+        self.utilization_history.set(timestamp_key, timestamp);
+        
+        index
+    }
+    
+    /// Get the utilization recorded at a buffer index
+    pub fn get_utilization_at(&self, index: Felt) -> Felt {
+        let key = Word::from([index, felt!(0), felt!(0), felt!(0)]);
+        self.utilization_history.get(&key)
+    }
+    
+    /// Get the timestamp of the snapshot at a buffer index
+    pub fn get_utilization_timestamp_at(&self, index: Felt) -> Felt {
+        let key = Word::from([index, felt!(1), felt!(0), felt!(0)]);
+        self.utilization_history.get(&key)
+    }
+    
+    /// Get how many snapshots have been recorded in total
+    pub fn get_utilization_snapshot_count(&self) -> Felt {
+        let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(6)]);
+        self.balances.get(&key)
+    }
+}
+
+impl VoileLpPool {
+    /// Mark an offer inactive and release its undrawn reservation
+    /// Returns false if the offer was already inactive
+    fn deactivate_offer(&self, offer_id: Felt) -> bool {
+        let active_key = Word::from([offer_id, felt!(3), felt!(0), felt!(0)]);
+        let is_active: Felt = self.active_offers.get(&active_key);
+        if is_active != felt!(1) {
+            return false;
+        }
+        self.active_offers.set(active_key, felt!(0));
+        
+        let active_count_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(16)]);
+        let active_count: Felt = self.balances.get(&active_count_key);
+        self.balances.set(active_count_key, active_count - felt!(1));
+        
+        let offer_reserved = self.get_offer_reserved(offer_id);
+        self.draw_offer_reservation(offer_id, offer_reserved);
+        true
+    }
+    
+    /// Take up to `amount` out of an offer's reservation and the pool total
+    fn draw_offer_reservation(&self, offer_id: Felt, amount: Felt) {
+        let offer_reserved_key = Word::from([offer_id, felt!(4), felt!(0), felt!(0)]);
+        let offer_reserved: Felt = self.active_offers.get(&offer_reserved_key);
+        let drawn = Felt::from_u64_unchecked(amount.as_u64().min(offer_reserved.as_u64()));
+        self.active_offers.set(offer_reserved_key, offer_reserved - drawn);
+        
+        let reserved_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(5)]);
+        let reserved: Felt = self.balances.get(&reserved_key);
+        self.balances.set(reserved_key, reserved - drawn);
+    }
+    
+    /// Whether the note being consumed was sent by the admin or the pool
+    /// account itself
+    fn is_admin_call(&self) -> bool {
+        let sender = note::get_sender();
+        let admin = self.get_admin();
+        let pool = account::get_id();
+        (sender.prefix == admin[0] && sender.suffix == admin[1])
+            || (sender.prefix == pool.prefix && sender.suffix == pool.suffix)
+    }
+    
+    /// Whether a deal still has its advance outstanding
+    /// Cancelled matches have their amount cleared
+    fn is_deal_open(&self, deal_id: Felt) -> bool {
+        self.get_deal_amount(deal_id) != felt!(0)
+            && self.is_deal_settled(deal_id) != felt!(1)
+            && self.is_deal_defaulted(deal_id) != felt!(1)
+    }
+    
+    /// Append a settlement's earnings credit to the event log
+    fn emit_earnings_event(&self, deal_id: Felt, fee_credited: Felt, interest_credited: Felt) {
+        let counter_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(15)]);
+        let index: Felt = self.balances.get(&counter_key);
+        self.balances.set(counter_key, index + felt!(1));
+        
+        let event_key = Word::from([index, felt!(0), felt!(0), felt!(0)]);
+        let total_earned = self.get_total_earned();
+        let event = Word::from([deal_id, fee_credited, interest_credited, total_earned]);
+        self.earnings_events.set(event_key, event);
+    }
+    
+    /// Raise the earnings high-water mark if total earned is a new peak
+    fn update_earnings_hwm(&self) {
+        let earned = self.get_total_earned();
+        if earned.as_u64() > self.get_earnings_hwm().as_u64() {
+            let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(13)]);
+            self.balances.set(key, earned);
+        }
+    }
+    
+    /// Principal a deal's advance was made against (advance plus the 5% fee)
+    fn deal_principal(&self, deal_id: Felt) -> u128 {
+        self.get_deal_amount(deal_id).as_u64() as u128 * 20 / 19
+    }
+    
+    /// LP's share of the fee withheld from a deal's advance
+    fn deal_lp_fee(&self, deal_id: Felt) -> Felt {
+        let advance = self.get_deal_amount(deal_id).as_u64() as u128;
+        let fee = (self.deal_principal(deal_id) - advance) as u64;
+        self.calculate_lp_fee(Felt::from_u64_unchecked(fee))
+    }
+    
+    /// APR a deal accrues interest at: its offer's, or the default
+    fn deal_apr_bps(&self, deal_id: Felt) -> u64 {
+        let offer_apr = self.get_offer_apr(self.get_deal_offer(deal_id)).as_u64();
+        if offer_apr == 0 { DEFAULT_APR_BPS } else { offer_apr }
+    }
+    
+    /// Available balance plus what's advanced out on open deals
+    fn principal(&self) -> u64 {
+        self.get_usdc_balance().as_u64() + self.get_outstanding().as_u64()
+    }
+    
+    /// Abort if the pool's accounting has gone wrong
+    ///
+    /// Checks that the available balance hasn't wrapped below zero and that
+    /// active offers don't reserve more than the pool has available; what
+    /// outstanding deals owe may never come back, so it can't back offers.
+    fn check_liquidity_invariant(&self) {
+        let balance = self.get_usdc_balance().as_u64();
+        let reserved = self.get_reserved().as_u64();
+        
+        assert!(!is_negative(balance));
+        assert!(reserved <= balance);
+    }
+}
+
+/// Annualize a return over `days` on `principal`, in basis points
+fn annualized_bps(amount: u64, principal: u64, days: u64) -> Felt {
+    if principal == 0 || days == 0 {
+        return felt!(0);
+    }
+    let bps = amount as u128 * 10000 * 365 / (principal as u128 * days as u128);
+    Felt::from_u64_unchecked(bps as u64)
+}
+
+/// A subtraction that went below zero wraps to the top of the field
+fn is_negative(value: u64) -> bool {
+    value >= 1 << 63
 }
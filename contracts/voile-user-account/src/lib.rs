@@ -2,7 +2,13 @@
 // Manages staked assets and generates private unlock requests
 #![no_std]
 
-use miden::{component, felt, Felt, StorageMap, StorageMapAccess, Word};
+extern crate alloc;
+
+use alloc::vec;
+
+use miden::{
+    account, assert_eq, component, felt, hash_elements, Felt, StorageMap, StorageMapAccess, Word,
+};
 
 /// Voile User Account - holds staked assets and manages private unlock requests
 /// 
@@ -12,10 +18,23 @@ use miden::{component, felt, Felt, StorageMap, StorageMapAccess, Word};
 ///   - [request_id, 1, 0, 0] -> LP commitment (when matched)
 ///   - [request_id, 2, 0, 0] -> locked amount
 ///   - [request_id, 3, 0, 0] -> settled flag (1 = settled)
+///   - [request_id, 4, 0, 0] -> cooldown end timestamp
+///   - [request_id, 5, 0, 0] -> settlement deadline (when matched)
 /// 
 /// Slot 1 (balances):
 ///   - [0, 0, 0, 0] -> staked asset balance
 ///   - [0, 0, 0, 1] -> request counter
+///   - [0, 0, 0, 2] -> total advance fees paid
+///   - [0, 0, 0, 3] -> request event counter
+/// 
+/// Slot 2 (nullifiers):
+///   - [nullifier, 0, 0, 0] -> spent flag (1 = used by a request)
+///   - [nullifier, 1, 0, 0] -> request_id + 1 of the request that used it
+///   - hash(secret) -> spent flag for the commitment secret (1 = used by a request)
+/// 
+/// Slot 3 (request_events), append-only log for off-chain indexers:
+///   - [event_index, 0, 0, 0] -> request id
+///   - [event_index, 1, 0, 0] -> new status (see REQUEST_EVENT_*)
 #[component]
 struct VoileUserAccount {
     #[storage(slot(0), description = "unlock request commitments")]
@@ -23,8 +42,48 @@ struct VoileUserAccount {
     
     #[storage(slot(1), description = "balances and counters")]
     balances: StorageMap,
+    
+    #[storage(slot(2), description = "spent request nullifiers")]
+    nullifiers: StorageMap,
+    
+    #[storage(slot(3), description = "request event log")]
+    request_events: StorageMap,
 }
 
+/// Request event status: created
+const REQUEST_EVENT_CREATED: u64 = 1;
+
+/// Request event status: cancelled
+const REQUEST_EVENT_CANCELLED: u64 = 2;
+
+/// Request event status: matched
+const REQUEST_EVENT_MATCHED: u64 = 3;
+
+/// Request status: no request with this id
+const REQUEST_STATUS_NONE: u64 = 0;
+
+/// Request status: created and waiting for a match
+const REQUEST_STATUS_OPEN: u64 = 1;
+
+/// Request status: matched with an LP, not yet settled
+const REQUEST_STATUS_MATCHED: u64 = 2;
+
+/// Request status: settled
+const REQUEST_STATUS_SETTLED: u64 = 3;
+
+/// Request status: cancelled
+const REQUEST_STATUS_CANCELLED: u64 = 4;
+
+/// APR obligations accrue interest at: 10%, the protocol default
+const DEFAULT_APR_BPS: u64 = 1000;
+
+/// Seconds in a day, for converting timestamps to whole days
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Time after a request's cooldown ends to settle it, matching the LP pool's
+/// grace before the deal can be defaulted
+const SETTLEMENT_GRACE_SECONDS: u64 = 3 * SECONDS_PER_DAY;
+
 #[component]
 impl VoileUserAccount {
     // =========================================================================
@@ -69,11 +128,34 @@ impl VoileUserAccount {
     /// Create a private unlock request
     /// Stores commitment and locks assets
     /// Returns new request_id
+    /// 
+    /// The nullifier, and a key derived from the secret, are checked and
+    /// marked spent before anything else happens, with no external call in
+    /// between, so two requests carrying the same nullifier or the same
+    /// secret can never both pass the check
+    /// 
+    /// The commitment is computed here as
+    /// `hash(amount, cooldown_end, secret, user_id)` rather than taken from
+    /// the caller, matching `voile_helpers::request_commitment`; `user_id` is
+    /// this account's own ID, so no caller can bind a request to another user
     pub fn create_unlock_request(
         &self,
         amount: Felt,
-        request_commitment: Word,
+        cooldown_end: Felt,
+        secret: Felt,
+        nullifier: Felt,
     ) -> Felt {
+        // Reject reused nullifier and mark it spent in one step
+        let nullifier_key = Word::from([nullifier, felt!(0), felt!(0), felt!(0)]);
+        let spent: Felt = self.nullifiers.get(&nullifier_key);
+        assert_eq(spent, felt!(0));
+        self.nullifiers.set(nullifier_key, felt!(1));
+        
+        let secret_key = secret_key(secret);
+        let secret_spent: Felt = self.nullifiers.get(&secret_key);
+        assert_eq(secret_spent, felt!(0));
+        self.nullifiers.set(secret_key, felt!(1));
+        
         // Lock assets (reduce balance)
         let balance_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(0)]);
         let current_balance: Felt = self.balances.get(&balance_key);
@@ -86,7 +168,20 @@ impl VoileUserAccount {
         let new_counter = request_id + felt!(1);
         self.balances.set(counter_key, new_counter);
         
+        // Index the request by nullifier so it can be found without its id
+        let request_index_key = Word::from([nullifier, felt!(1), felt!(0), felt!(0)]);
+        self.nullifiers.set(request_index_key, request_id + felt!(1));
+        
         // Store the commitment
+        let user_id = account::get_id();
+        let request_commitment: Word = hash_elements(vec![
+            amount,
+            cooldown_end,
+            secret,
+            user_id.prefix,
+            user_id.suffix,
+        ])
+        .into();
         let commitment_key = Word::from([request_id, felt!(0), felt!(0), felt!(0)]);
         self.unlock_requests.set(commitment_key, request_commitment[0]);
         
@@ -94,9 +189,22 @@ impl VoileUserAccount {
         let amount_key = Word::from([request_id, felt!(2), felt!(0), felt!(0)]);
         self.unlock_requests.set(amount_key, amount);
         
+        // Store the cooldown end so settlement can't claim an earlier one
+        let cooldown_key = Word::from([request_id, felt!(4), felt!(0), felt!(0)]);
+        self.unlock_requests.set(cooldown_key, cooldown_end);
+        
+        self.emit_request_event(request_id, REQUEST_EVENT_CREATED);
         request_id
     }
     
+    /// Check whether a request has used a nullifier
+    /// Returns 1 once `create_unlock_request` has marked `nullifier` spent,
+    /// even if that request was later cancelled
+    pub fn is_nullifier_spent(&self, nullifier: Felt) -> Felt {
+        let nullifier_key = Word::from([nullifier, felt!(0), felt!(0), felt!(0)]);
+        self.nullifiers.get(&nullifier_key)
+    }
+    
     /// Get an unlock request commitment by ID
     pub fn get_request_commitment(&self, request_id: Felt) -> Felt {
         let key = Word::from([request_id, felt!(0), felt!(0), felt!(0)]);
@@ -109,17 +217,51 @@ impl VoileUserAccount {
         self.unlock_requests.get(&key)
     }
     
+    /// Get the cooldown end recorded when the request was created
+    pub fn get_request_cooldown_end(&self, request_id: Felt) -> Felt {
+        let key = Word::from([request_id, felt!(4), felt!(0), felt!(0)]);
+        self.unlock_requests.get(&key)
+    }
+    
     /// Mark request as matched by storing LP commitment
+    /// The first time it is matched, the request's advance fee is added to
+    /// the account's fee total and its settlement deadline is set to the
+    /// cooldown end plus the settlement grace, as the LP pool sets the deal's
+    /// Aborts unless the request is open or already matched: one that was
+    /// never created, was cancelled or has settled can't be matched
     pub fn mark_request_matched(
         &self,
         request_id: Felt,
         lp_commitment: Word,
     ) -> Felt {
+        let status = self.get_request_status(request_id).as_u64();
+        assert!(status == REQUEST_STATUS_OPEN || status == REQUEST_STATUS_MATCHED);
+        
+        if self.is_request_matched(request_id) == felt!(0) {
+            let fee = self.calculate_fee(self.get_request_amount(request_id));
+            let fees_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(2)]);
+            let total_fees: Felt = self.balances.get(&fees_key);
+            self.balances.set(fees_key, total_fees + fee);
+            
+            let cooldown_end = self.get_request_cooldown_end(request_id).as_u64();
+            let deadline = Felt::from_u64_unchecked(cooldown_end + SETTLEMENT_GRACE_SECONDS);
+            let deadline_key = Word::from([request_id, felt!(5), felt!(0), felt!(0)]);
+            self.unlock_requests.set(deadline_key, deadline);
+        }
+        
         let lp_key = Word::from([request_id, felt!(1), felt!(0), felt!(0)]);
         self.unlock_requests.set(lp_key, lp_commitment[0]);
+        
+        self.emit_request_event(request_id, REQUEST_EVENT_MATCHED);
         felt!(1)
     }
     
+    /// Get the time a matched request must settle before (0 = not matched)
+    pub fn get_request_settlement_deadline(&self, request_id: Felt) -> Felt {
+        let key = Word::from([request_id, felt!(5), felt!(0), felt!(0)]);
+        self.unlock_requests.get(&key)
+    }
+    
     /// Get LP commitment for a matched request
     pub fn get_lp_commitment(&self, request_id: Felt) -> Felt {
         let key = Word::from([request_id, felt!(1), felt!(0), felt!(0)]);
@@ -137,31 +279,196 @@ impl VoileUserAccount {
     }
     
     /// Cancel an unmatched request and return assets
+    /// Clears every field stored for the request, from the commitment through
+    /// the settlement deadline, so nothing outlives it
+    /// Aborts if the request is matched: its assets back an advance
     pub fn cancel_request(&self, request_id: Felt) -> Felt {
+        assert_eq(self.is_request_matched(request_id), felt!(0));
+        
         // Get locked amount
         let amount = self.get_request_amount(request_id);
         
         // Clear the request
-        let commitment_key = Word::from([request_id, felt!(0), felt!(0), felt!(0)]);
-        self.unlock_requests.set(commitment_key, felt!(0));
-        
-        let amount_key = Word::from([request_id, felt!(2), felt!(0), felt!(0)]);
-        self.unlock_requests.set(amount_key, felt!(0));
+        for offset in 0..6 {
+            let offset = Felt::from_u64_unchecked(offset);
+            let key = Word::from([request_id, offset, felt!(0), felt!(0)]);
+            self.unlock_requests.set(key, felt!(0));
+        }
         
         // Return locked assets
         let balance_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(0)]);
         let current: Felt = self.balances.get(&balance_key);
         self.balances.set(balance_key, current + amount);
         
+        self.emit_request_event(request_id, REQUEST_EVENT_CANCELLED);
         felt!(1)
     }
     
+    /// Cancel up to four unmatched requests in one call, returning each one's
+    /// locked assets
+    /// `amounts[i]` must be the locked amount of `request_ids[i]`; a request
+    /// that's matched, settled, already cancelled or given the wrong amount
+    /// is skipped rather than aborting the batch. Pad unused slots with a zero
+    /// amount
+    /// Returns a mask with element i set to 1 if `request_ids[i]` was cancelled
+    pub fn cancel_requests_batch(&self, request_ids: Word, amounts: Word) -> Word {
+        let mut cancelled = [felt!(0); 4];
+        
+        for i in 0..4 {
+            let request_id = request_ids[i];
+            let locked = self.get_request_amount(request_id);
+            if locked == felt!(0)
+                || locked != amounts[i]
+                || self.is_request_matched(request_id) == felt!(1)
+                || self.is_settled(request_id) == felt!(1)
+            {
+                continue;
+            }
+            cancelled[i] = self.cancel_request(request_id);
+        }
+        
+        Word::from(cancelled)
+    }
+    
+    /// Where a request is in its lifecycle: 0 = none, 1 = open, 2 = matched,
+    /// 3 = settled, 4 = cancelled
+    pub fn get_request_status(&self, request_id: Felt) -> Felt {
+        let status = if request_id.as_u64() >= self.get_request_counter().as_u64() {
+            REQUEST_STATUS_NONE
+        } else if self.get_request_commitment(request_id) == felt!(0) {
+            REQUEST_STATUS_CANCELLED
+        } else if self.is_settled(request_id) == felt!(1) {
+            REQUEST_STATUS_SETTLED
+        } else if self.is_request_matched(request_id) == felt!(1) {
+            REQUEST_STATUS_MATCHED
+        } else {
+            REQUEST_STATUS_OPEN
+        };
+        Felt::from_u64_unchecked(status)
+    }
+    
+    /// Cancel an unmatched request identified by its nullifier rather than id
+    /// For a user who kept the nullifier secret but lost the request id;
+    /// `amount` must be the request's locked amount
+    /// Returns 0 if no live request used the nullifier, the amount doesn't
+    /// match, or the request is already matched
+    pub fn cancel_request_by_nullifier(&self, nullifier: Felt, amount: Felt) -> Felt {
+        let request_index_key = Word::from([nullifier, felt!(1), felt!(0), felt!(0)]);
+        let request_index: Felt = self.nullifiers.get(&request_index_key);
+        if request_index == felt!(0) {
+            return felt!(0);
+        }
+        
+        let request_id = request_index - felt!(1);
+        let locked = self.get_request_amount(request_id);
+        if locked == felt!(0) || locked != amount || self.is_request_matched(request_id) == felt!(1) {
+            return felt!(0);
+        }
+        
+        self.cancel_request(request_id)
+    }
+    
+    /// Get the advance fees paid across all matched requests
+    pub fn total_fees_paid(&self) -> Felt {
+        let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(2)]);
+        self.balances.get(&key)
+    }
+    
+    /// Ratio of current collateral value to what a matched request owes, in
+    /// basis points; below 10000 the collateral no longer covers the obligation
+    /// The obligation is the locked amount (the net advance plus its fee) plus
+    /// default-APR interest for the whole days left until the cooldown ends,
+    /// as `voile_helpers::user_net_position` counts it
+    /// Returns 0 if the request has no outstanding obligation
+    pub fn get_health_factor_bps(
+        &self,
+        request_id: Felt,
+        collateral_value: Felt,
+        current_timestamp: Felt,
+    ) -> Felt {
+        if self.is_request_matched(request_id) == felt!(0) || self.is_settled(request_id) == felt!(1) {
+            return felt!(0);
+        }
+        
+        let amount = self.get_request_amount(request_id).as_u64() as u128;
+        if amount == 0 {
+            return felt!(0);
+        }
+        let remaining = self
+            .get_request_cooldown_end(request_id)
+            .as_u64()
+            .saturating_sub(current_timestamp.as_u64());
+        let days = remaining.div_ceil(SECONDS_PER_DAY);
+        let interest = amount * DEFAULT_APR_BPS as u128 * days as u128 / (10000 * 365);
+        
+        let obligation = amount + interest;
+        let health_bps = collateral_value.as_u64() as u128 * 10000 / obligation;
+        Felt::from_u64_unchecked(health_bps as u64)
+    }
+    
+    // =========================================================================
+    // REQUEST EVENTS
+    // =========================================================================
+    
+    /// Get the number of request events logged
+    pub fn get_request_event_count(&self) -> Felt {
+        let key = Word::from([felt!(0), felt!(0), felt!(0), felt!(3)]);
+        self.balances.get(&key)
+    }
+    
+    /// Get a logged request event as [request_id, status, 0, 0]
+    /// Indexers page through the log from the last index they saw
+    pub fn get_request_event(&self, event_index: Felt) -> Word {
+        let request_key = Word::from([event_index, felt!(0), felt!(0), felt!(0)]);
+        let status_key = Word::from([event_index, felt!(1), felt!(0), felt!(0)]);
+        Word::from([
+            self.request_events.get(&request_key),
+            self.request_events.get(&status_key),
+            felt!(0),
+            felt!(0),
+        ])
+    }
+    
     // =========================================================================
     // SETTLEMENT
     // =========================================================================
     
+    /// Authorize a settlement note against this account's record of the request
+    /// Aborts unless the request is matched and unsettled, the note's cooldown
+    /// end is the one stored with the request, and that time has passed but
+    /// the settlement deadline hasn't: from the deadline on, the LP pool no
+    /// longer settles the deal and may default it instead
+    pub fn authorize_settlement(
+        &self,
+        request_id: Felt,
+        cooldown_end: Felt,
+        current_timestamp: Felt,
+    ) -> Felt {
+        assert_eq(self.is_request_matched(request_id), felt!(1));
+        assert_eq(self.is_settled(request_id), felt!(0));
+        
+        // A note can't claim an earlier cooldown end to settle early
+        let stored_cooldown_end = self.get_request_cooldown_end(request_id);
+        assert_eq(cooldown_end, stored_cooldown_end);
+        assert!(current_timestamp.as_u64() >= stored_cooldown_end.as_u64());
+        
+        let deadline = self.get_request_settlement_deadline(request_id);
+        assert!(current_timestamp.as_u64() < deadline.as_u64());
+        
+        felt!(1)
+    }
+    
     /// Mark request as settled
-    pub fn mark_settled(&self, request_id: Felt) -> Felt {
+    /// Aborts unless `authorize_settlement` passes for the settlement note's
+    /// `cooldown_end` at `current_timestamp`
+    pub fn mark_settled(
+        &self,
+        request_id: Felt,
+        cooldown_end: Felt,
+        current_timestamp: Felt,
+    ) -> Felt {
+        self.authorize_settlement(request_id, cooldown_end, current_timestamp);
+        
         let settled_key = Word::from([request_id, felt!(3), felt!(0), felt!(0)]);
         self.unlock_requests.set(settled_key, felt!(1));
         felt!(1)
@@ -179,12 +486,11 @@ impl VoileUserAccount {
     // =========================================================================
     
     /// Calculate 5% advance fee
-    /// fee = amount * 5 / 100
+    /// fee = amount * 5 / 100, rounded down
     pub fn calculate_fee(&self, amount: Felt) -> Felt {
-        // Simple calculation: (amount * 5) / 100
-        // Since we can't do division directly, we approximate
-        // For 5%, we do amount / 20
-        amount / felt!(20)
+        // Integer division on the u64 value: `Felt` division multiplies by
+        // the field inverse, which is only right for multiples of 20
+        Felt::from_u64_unchecked((amount.as_u64() as u128 * 5 / 100) as u64)
     }
     
     /// Calculate net advance after 5% fee
@@ -192,4 +498,22 @@ impl VoileUserAccount {
         let fee = self.calculate_fee(amount);
         amount - fee
     }
+    
+    /// Append a request status change to the event log
+    fn emit_request_event(&self, request_id: Felt, status: u64) {
+        let counter_key = Word::from([felt!(0), felt!(0), felt!(0), felt!(3)]);
+        let event_index: Felt = self.balances.get(&counter_key);
+        self.balances.set(counter_key, event_index + felt!(1));
+        
+        let request_key = Word::from([event_index, felt!(0), felt!(0), felt!(0)]);
+        self.request_events.set(request_key, request_id);
+        
+        let status_key = Word::from([event_index, felt!(1), felt!(0), felt!(0)]);
+        self.request_events.set(status_key, Felt::from_u64_unchecked(status));
+    }
+}
+
+/// Nullifier registry key marking a commitment secret as used: hash(secret)
+fn secret_key(secret: Felt) -> Word {
+    hash_elements(vec![secret]).into()
 }
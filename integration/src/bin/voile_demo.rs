@@ -11,11 +11,13 @@ use integration::helpers::{
     create_basic_wallet_account, setup_client, AccountCreationConfig, ClientSetup,
 };
 use integration::voile_helpers::{
-    cooldown_end_timestamp, current_timestamp, LpOffer, MatchingEngine, UnlockRequest,
-    DEFAULT_COOLDOWN_SECONDS, ONE_USDC,
+    advance_note_config, check_note_matches_deal, cooldown_end_timestamp, current_timestamp,
+    format_display, settlement_note_config, LpOffer, MatchingEngine, RequestIdAllocator,
+    UnlockRequest, VoileNoteKind, DEFAULT_COOLDOWN_SECONDS, ONE_USDC,
 };
 
 use anyhow::Result;
+use miden_client::Felt;
 use rand::{rngs::StdRng, SeedableRng};
 
 fn print_header(text: &str) {
@@ -90,8 +92,8 @@ async fn main() -> Result<()> {
     println!("✓ LP offer created");
     println!(
         "  Range: {} - {} USDC",
-        lp_offer.min_amount / ONE_USDC,
-        lp_offer.max_amount / ONE_USDC
+        lp_offer.min_amount() / ONE_USDC,
+        lp_offer.max_amount() / ONE_USDC
     );
     println!("  APR: 9%");
 
@@ -103,8 +105,10 @@ async fn main() -> Result<()> {
     let request_amount = 25_000 * ONE_USDC; // $25,000
     let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
 
+    // A fresh account assigns request IDs from zero
+    let mut request_ids = RequestIdAllocator::new();
     let unlock_request = UnlockRequest::new(
-        1,
+        request_ids.next_id(),
         request_amount,
         cooldown_end,
         user_account.id(),
@@ -128,9 +132,16 @@ async fn main() -> Result<()> {
 
     println!();
     println!("  Pricing:");
-    println!("  ├─ Advance fee (5%): {} USDC", fee / ONE_USDC);
-    println!("  ├─ Net advance: {} USDC", net / ONE_USDC);
-    println!("  └─ APR interest (14d): {} USDC", interest / ONE_USDC);
+    let decimals = unlock_request.decimals;
+    println!(
+        "  ├─ Advance fee (5%): {} USDC",
+        format_display(fee, decimals)
+    );
+    println!("  ├─ Net advance: {} USDC", format_display(net, decimals));
+    println!(
+        "  └─ APR interest (14d): {} USDC",
+        format_display(interest, decimals)
+    );
 
     // =========================================================================
     // STEP 5: Off-chain matching
@@ -143,16 +154,13 @@ async fn main() -> Result<()> {
     println!("🔍 Finding best LP match...");
 
     let matched_deal = matching_engine
-        .match_request(unlock_request.clone(), &mut rng)
+        .match_request(unlock_request.clone(), current_timestamp(), &mut rng)
         .expect("Failed to match request");
 
     println!("✓ Match found!");
     println!("  Deal ID: {:?}", matched_deal.deal_id[0]);
     println!("  LP: {:?}", lp_offer.lp_account_id);
-    println!(
-        "  Advance: {} USDC",
-        matched_deal.advance_amount / ONE_USDC
-    );
+    println!("  Advance: {} USDC", matched_deal.advance_display());
 
     let (lp_fee_share, lp_interest) = matched_deal.lp_earnings(14);
     println!();
@@ -160,6 +168,30 @@ async fn main() -> Result<()> {
     println!("  ├─ Fee share (80%): {} USDC", lp_fee_share / ONE_USDC);
     println!("  └─ Interest: {} USDC", lp_interest / ONE_USDC);
 
+    // =========================================================================
+    // STEP 6: Build and check the deal's notes
+    // =========================================================================
+    print_step(6, "Building Deal Notes");
+
+    // The pool assigns its own deal ID in accept_match; the first deal gets 0
+    let advance_config = advance_note_config(
+        Felt::new(matched_deal.advance_amount),
+        Felt::new(0),
+        Felt::new(matched_deal.offer.offer_id),
+        matched_deal.request.commitment[0],
+    );
+    check_note_matches_deal(&advance_config, &matched_deal, VoileNoteKind::Advance)?;
+    println!("✓ Advance note inputs match the deal");
+
+    let settlement_config = settlement_note_config(
+        Felt::new(matched_deal.request.request_id),
+        Felt::new(matched_deal.request.amount),
+        Felt::new(matched_deal.request.cooldown_end_timestamp),
+        matched_deal.deal_id[0],
+    );
+    check_note_matches_deal(&settlement_config, &matched_deal, VoileNoteKind::Settlement)?;
+    println!("✓ Settlement note inputs match the deal");
+
     // =========================================================================
     // SUMMARY
     // =========================================================================
@@ -170,11 +202,17 @@ async fn main() -> Result<()> {
         "  ├─ Staked assets locked: {} tokens",
         request_amount / ONE_USDC
     );
-    println!("  ├─ Fee paid: {} USDC", fee / ONE_USDC);
-    println!("  └─ USDC received NOW: {} USDC", net / ONE_USDC);
+    println!("  ├─ Fee paid: {} USDC", matched_deal.fee_display());
+    println!(
+        "  └─ USDC received NOW: {} USDC",
+        matched_deal.advance_display()
+    );
 
     println!("\n  LP OUTCOME (after 14 days):");
-    println!("  ├─ USDC advanced: {} USDC", net / ONE_USDC);
+    println!(
+        "  ├─ USDC advanced: {} USDC",
+        matched_deal.advance_display()
+    );
     println!("  ├─ Fee earned: {} USDC", lp_fee_share / ONE_USDC);
     println!("  ├─ Interest earned: {} USDC", lp_interest / ONE_USDC);
     println!(
@@ -1,12 +1,14 @@
 //! Common helper functions for scripts and tests
 
-use std::{path::Path, sync::Arc};
+#[cfg(feature = "testing")]
+use std::sync::Mutex;
+use std::{fmt, path::Path, sync::Arc};
 
-use anyhow::{bail, Context, Result};
+use anyhow::{bail, Context};
 use cargo_miden::{run, OutputType};
 use miden_client::{
     account::{
-        component::{AuthFalcon512Rpo, BasicWallet},
+        component::{AuthFalcon512Rpo, BasicWallet, NoAuth},
         Account, AccountBuilder, AccountComponent, AccountId, AccountStorageMode, AccountType,
         StorageSlot,
     },
@@ -20,11 +22,56 @@ use miden_client::{
     },
     rpc::{Endpoint, GrpcClient},
     utils::Deserializable,
-    Client, Felt,
+    Client, Felt, Word,
 };
 use miden_client_sqlite_store::ClientBuilderSqliteExt;
 use rand::RngCore;
 
+/// Failure categories surfaced by the helpers
+///
+/// Each variant wraps the underlying error chain so callers can match on
+/// the kind of failure and still print the full context
+#[derive(Debug)]
+pub enum VoileError {
+    /// Client, RPC or keystore setup failed
+    Client(anyhow::Error),
+    /// Compiling a contract project failed
+    Build(anyhow::Error),
+    /// Building an account or its components failed
+    Account(anyhow::Error),
+    /// Building a note failed
+    Note(anyhow::Error),
+}
+
+impl VoileError {
+    fn inner(&self) -> &anyhow::Error {
+        match self {
+            Self::Client(err) | Self::Build(err) | Self::Account(err) | Self::Note(err) => err,
+        }
+    }
+}
+
+impl fmt::Display for VoileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            Self::Client(_) => "client error",
+            Self::Build(_) => "build error",
+            Self::Account(_) => "account error",
+            Self::Note(_) => "note error",
+        };
+        write!(f, "{kind}: {:#}", self.inner())
+    }
+}
+
+impl std::error::Error for VoileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.inner().as_ref())
+    }
+}
+
+/// Result type returned by the helpers
+pub type Result<T> = std::result::Result<T, VoileError>;
+
 /// Test setup configuration containing initialized client and keystore
 pub struct ClientSetup {
     pub client: Client<FilesystemKeyStore>,
@@ -37,9 +84,13 @@ pub struct ClientSetup {
 /// A `ClientSetup` containing the initialized client and keystore
 ///
 /// # Errors
-/// Returns an error if RPC connection fails, keystore initialization fails,
-/// or client building fails
+/// Returns `VoileError::Client` if RPC connection fails, keystore initialization
+/// fails, or client building fails
 pub async fn setup_client() -> Result<ClientSetup> {
+    init_client().await.map_err(VoileError::Client)
+}
+
+async fn init_client() -> anyhow::Result<ClientSetup> {
     // Initialize RPC connection
     let endpoint = Endpoint::testnet();
     let timeout_ms = 10_000;
@@ -76,8 +127,13 @@ pub async fn setup_client() -> Result<ClientSetup> {
 /// The compiled `Library`
 ///
 /// # Errors
-/// Returns an error if compilation fails or if the output is not in the expected format
+/// Returns `VoileError::Build` if compilation fails or if the output is not in the
+/// expected format
 pub fn build_project_in_dir(dir: &Path, release: bool) -> Result<Library> {
+    build_project(dir, release).map_err(VoileError::Build)
+}
+
+fn build_project(dir: &Path, release: bool) -> anyhow::Result<Library> {
     let profile = if release { "--release" } else { "--debug" };
     let manifest_path = dir.join("Cargo.toml");
     let manifest_arg = manifest_path.to_string_lossy();
@@ -139,15 +195,14 @@ impl Default for AccountCreationConfig {
 /// An `AccountComponent` configured according to the provided config
 ///
 /// # Errors
-/// Returns an error if the component creation fails
+/// Returns `VoileError::Account` if the component creation fails
 pub fn account_component_from_library(
     library: Library,
     config: &AccountCreationConfig,
 ) -> Result<AccountComponent> {
-    let component = AccountComponent::new(library, config.storage_slots.clone())
-        .context("Failed to create account component")?;
-
-    Ok(component)
+    AccountComponent::new(library, config.storage_slots.clone())
+        .context("Failed to create account component")
+        .map_err(VoileError::Account)
 }
 
 /// Creates an account with a custom component from a compiled library
@@ -161,14 +216,23 @@ pub fn account_component_from_library(
 /// The created `Account`
 ///
 /// # Errors
-/// Returns an error if account creation or client operations fail
+/// Returns `VoileError::Account` if account creation or client operations fail
 pub async fn create_account_from_library(
     client: &mut Client<FilesystemKeyStore>,
     library: Library,
     config: AccountCreationConfig,
 ) -> Result<Account> {
-    let account_component = account_component_from_library(library, &config)
-        .context("Failed to create account component from library")?;
+    create_component_account(client, library, config)
+        .await
+        .map_err(VoileError::Account)
+}
+
+async fn create_component_account(
+    client: &mut Client<FilesystemKeyStore>,
+    library: Library,
+    config: AccountCreationConfig,
+) -> anyhow::Result<Account> {
+    let account_component = account_component_from_library(library, &config)?;
 
     let mut init_seed = [0_u8; 32];
     client.rng().fill_bytes(&mut init_seed);
@@ -211,6 +275,10 @@ impl Default for NoteCreationConfig {
 
 /// Creates a note from a compiled library
 ///
+/// Each note gets a fresh serial number drawn from the client's RNG, so notes
+/// built from the same script, inputs and assets still have distinct IDs and
+/// nullifiers
+///
 /// # Arguments
 /// * `client` - The Miden client instance
 /// * `library` - The compiled library containing the note script
@@ -221,7 +289,7 @@ impl Default for NoteCreationConfig {
 /// The created `Note`
 ///
 /// # Errors
-/// Returns an error if note creation fails
+/// Returns `VoileError::Note` if note creation fails
 pub fn create_note_from_library(
     client: &mut Client<FilesystemKeyStore>,
     note_script: NoteScript,
@@ -229,14 +297,117 @@ pub fn create_note_from_library(
     config: NoteCreationConfig,
 ) -> Result<Note> {
     let serial_num = client.rng().draw_word();
-    let note_inputs = NoteInputs::new(config.inputs).context("Failed to create note inputs")?;
-    let recipient = NoteRecipient::new(serial_num, note_script, note_inputs);
+    build_note(note_script, sender_id, serial_num, config)
+}
 
+/// Builds a note with the given serial number
+fn build_note(
+    note_script: NoteScript,
+    sender_id: AccountId,
+    serial_num: Word,
+    config: NoteCreationConfig,
+) -> Result<Note> {
+    let note_inputs = NoteInputs::new(config.inputs)
+        .context("Failed to create note inputs")
+        .map_err(VoileError::Note)?;
+    let recipient = NoteRecipient::new(serial_num, note_script, note_inputs);
     let metadata = NoteMetadata::new(sender_id, config.note_type, config.tag);
 
     Ok(Note::new(config.assets, metadata, recipient))
 }
 
+/// Serial numbers already given to testing notes in this process
+#[cfg(feature = "testing")]
+static TESTING_NOTE_SERIALS: Mutex<Vec<Word>> = Mutex::new(Vec::new());
+
+/// Creates an account from a compiled library for a local `MockChain`
+///
+/// The account is built as already deployed, with a no-op auth component,
+/// so tests can execute transactions against it without a client or keys
+///
+/// # Arguments
+/// * `library` - The compiled library containing the account component
+/// * `config` - Configuration for account creation
+///
+/// # Returns
+/// The created `Account`
+///
+/// # Errors
+/// Returns `VoileError::Account` if the component or account can't be built
+pub fn create_testing_account_from_library(
+    library: Library,
+    config: AccountCreationConfig,
+) -> Result<Account> {
+    let account_component = account_component_from_library(library, &config)?;
+
+    let mut init_seed = [0_u8; 32];
+    rand::rng().fill_bytes(&mut init_seed);
+
+    AccountBuilder::new(init_seed)
+        .account_type(config.account_type)
+        .storage_mode(config.storage_mode)
+        .with_component(account_component)
+        .with_auth_component(NoAuth)
+        .build_existing()
+        .context("Failed to build testing account")
+        .map_err(VoileError::Account)
+}
+
+/// Extracts the note script from a compiled note library
+///
+/// # Errors
+/// Returns `VoileError::Note` if the library has no note script entrypoint
+pub fn note_script_from_library(library: &Library) -> Result<NoteScript> {
+    NoteScript::from_library(library)
+        .context("Failed to extract note script from library")
+        .map_err(VoileError::Note)
+}
+
+/// Creates a note for a local `MockChain`, without a client
+///
+/// The serial number is drawn at random, so notes built from the same
+/// script, inputs and assets still have distinct IDs and nullifiers
+///
+/// # Errors
+/// Returns `VoileError::Note` if note creation fails
+pub fn create_testing_note(
+    note_script: NoteScript,
+    sender_id: AccountId,
+    config: NoteCreationConfig,
+) -> Result<Note> {
+    let mut rng = rand::rng();
+    let serial_num = Word::from([(); 4].map(|_| Felt::new(rng.next_u64())));
+    build_note(note_script, sender_id, serial_num, config)
+}
+
+/// Creates a note for a local `MockChain` with a chosen serial number
+///
+/// Only built with the `testing` feature, since it keeps a process-wide
+/// registry of the serial numbers used
+///
+/// # Errors
+/// Returns `VoileError::Note` if another testing note in this process already
+/// used `serial_num`, since the two would collide on nullifier, or if note
+/// creation fails
+#[cfg(feature = "testing")]
+pub fn create_testing_note_with_serial(
+    note_script: NoteScript,
+    sender_id: AccountId,
+    serial_num: Word,
+    config: NoteCreationConfig,
+) -> Result<Note> {
+    let mut used = TESTING_NOTE_SERIALS.lock().unwrap();
+    if used.contains(&serial_num) {
+        return Err(VoileError::Note(anyhow::anyhow!(
+            "Serial number {serial_num:?} is already used by another note"
+        )));
+    }
+
+    let note = build_note(note_script, sender_id, serial_num, config)?;
+    used.push(serial_num);
+    Ok(note)
+}
+
 /// Creates a basic wallet account with authentication
 ///
 /// # Arguments
@@ -248,12 +419,23 @@ pub fn create_note_from_library(
 /// The created `Account` with basic wallet functionality
 ///
 /// # Errors
-/// Returns an error if account creation, key generation, or keystore operations fail
+/// Returns `VoileError::Account` if account creation, key generation, or keystore
+/// operations fail
 pub async fn create_basic_wallet_account(
     client: &mut Client<FilesystemKeyStore>,
     keystore: Arc<FilesystemKeyStore>,
     config: AccountCreationConfig,
 ) -> Result<Account> {
+    create_wallet_account(client, keystore, config)
+        .await
+        .map_err(VoileError::Account)
+}
+
+async fn create_wallet_account(
+    client: &mut Client<FilesystemKeyStore>,
+    keystore: Arc<FilesystemKeyStore>,
+    config: AccountCreationConfig,
+) -> anyhow::Result<Account> {
     let mut init_seed = [0_u8; 32];
     client.rng().fill_bytes(&mut init_seed);
 
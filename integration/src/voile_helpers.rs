@@ -1,14 +1,25 @@
 //! Voile Protocol - Integration Helpers
 //! Extended helpers for Voile-specific testing and deployment
 
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, ensure, Context, Result};
 use miden_client::{
-    account::AccountId,
-    note::{NoteAssets, NoteTag, NoteType},
-    Felt, Word,
+    account::{Account, AccountId},
+    asset::FungibleAsset,
+    crypto::Rpo256,
+    keystore::FilesystemKeyStore,
+    note::{NoteAssets, NoteScript, NoteTag, NoteType},
+    transaction::{OutputNote, TransactionRequest, TransactionRequestBuilder},
+    Client, Felt, Word,
 };
-use rand::RngCore;
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
 
-use crate::helpers::NoteCreationConfig;
+use crate::helpers::{create_note_from_library, NoteCreationConfig};
 
 // ============================================================================
 // VOILE PROTOCOL CONSTANTS
@@ -23,18 +34,69 @@ pub const DEFAULT_APR_BPS: u64 = 1000;
 /// Default cooldown: 14 days in seconds
 pub const DEFAULT_COOLDOWN_SECONDS: u64 = 14 * 24 * 60 * 60;
 
+/// Shortest cooldown accepted by default: 1 day in seconds
+pub const DEFAULT_MIN_COOLDOWN_SECONDS: u64 = 24 * 60 * 60;
+
+/// Longest cooldown accepted by default: 30 days in seconds
+pub const DEFAULT_MAX_COOLDOWN_SECONDS: u64 = 30 * 24 * 60 * 60;
+
+/// Time after the cooldown ends to settle a deal before it can be defaulted: 3 days
+pub const SETTLEMENT_GRACE_SECONDS: u64 = 3 * 24 * 60 * 60;
+
+/// How long the matching engine holds capacity for an unconfirmed match: 5 minutes
+pub const DEFAULT_HOLD_TIMEOUT_SECONDS: u64 = 5 * 60;
+
 /// Protocol fee split: 20% to Voile
 pub const PROTOCOL_FEE_BPS: u64 = 2000;
 
 /// LP fee split: 80% to LP
 pub const LP_FEE_BPS: u64 = 8000;
 
+/// Lowest APR recommended to LPs: 5%, taken as an LP's cost of capital
+pub const MIN_MARGIN_APR_BPS: u64 = 500;
+
 /// USDC decimals
 pub const USDC_DECIMALS: u64 = 6;
 
 /// 1 USDC in raw units
 pub const ONE_USDC: u64 = 1_000_000;
 
+// ============================================================================
+// PROTOCOL CONFIG
+// ============================================================================
+
+/// Protocol-wide bounds applied when building requests
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProtocolConfig {
+    /// Shortest cooldown a request may have, in seconds
+    pub min_cooldown_seconds: u64,
+    /// Longest cooldown a request may have, in seconds
+    pub max_cooldown_seconds: u64,
+}
+
+impl ProtocolConfig {
+    /// Check a cooldown length against the configured bounds (inclusive)
+    pub fn validate_cooldown(&self, cooldown_seconds: u64) -> Result<()> {
+        ensure!(
+            (self.min_cooldown_seconds..=self.max_cooldown_seconds).contains(&cooldown_seconds),
+            "Cooldown of {}s is outside the allowed range {}s..={}s",
+            cooldown_seconds,
+            self.min_cooldown_seconds,
+            self.max_cooldown_seconds
+        );
+        Ok(())
+    }
+}
+
+impl Default for ProtocolConfig {
+    fn default() -> Self {
+        Self {
+            min_cooldown_seconds: DEFAULT_MIN_COOLDOWN_SECONDS,
+            max_cooldown_seconds: DEFAULT_MAX_COOLDOWN_SECONDS,
+        }
+    }
+}
+
 // ============================================================================
 // UNLOCK REQUEST TYPES
 // ============================================================================
@@ -55,6 +117,12 @@ pub struct UnlockRequest {
     pub user_account_id: AccountId,
     /// Request commitment (public hash)
     pub commitment: Word,
+    /// Highest APR (basis points) the user will accept, or no cap
+    pub max_apr_bps: Option<u64>,
+    /// Faucet of the staked asset locked as collateral, if known
+    pub staked_asset_faucet_id: Option<AccountId>,
+    /// Decimals `amount` is expressed in (raw units)
+    pub decimals: u8,
 }
 
 impl UnlockRequest {
@@ -69,8 +137,57 @@ impl UnlockRequest {
         let mut nullifier_secret = [0u8; 32];
         rng.fill_bytes(&mut nullifier_secret);
         
-        // Compute commitment = hash(amount, cooldown_end, nullifier_secret, user_id)
-        let commitment = Self::compute_commitment(
+        Self::reconstruct(
+            request_id,
+            amount,
+            cooldown_end_timestamp,
+            nullifier_secret,
+            user_account_id,
+        )
+    }
+    
+    /// Declare the decimals of the staked asset, if not USDC's 6
+    pub fn with_decimals(mut self, decimals: u8) -> Self {
+        self.decimals = decimals;
+        self
+    }
+    
+    /// Format a raw amount in this request's units
+    pub fn format_amount(&self, raw: u64) -> String {
+        format_units(raw, self.decimals)
+    }
+    
+    /// Create a new unlock request whose cooldown starts now, rejecting a
+    /// cooldown outside the protocol's bounds
+    pub fn try_new(
+        config: &ProtocolConfig,
+        request_id: u64,
+        amount: u64,
+        cooldown_seconds: u64,
+        user_account_id: AccountId,
+        rng: &mut impl RngCore,
+    ) -> Result<Self> {
+        config.validate_cooldown(cooldown_seconds)?;
+        Ok(Self::new(
+            request_id,
+            amount,
+            cooldown_end_timestamp(cooldown_seconds),
+            user_account_id,
+            rng,
+        ))
+    }
+    
+    /// Rebuild a request from its nullifier secret and remembered values,
+    /// e.g. after restoring a wallet
+    /// Commitment = hash(amount, cooldown_end, nullifier_secret, user_id)
+    pub fn reconstruct(
+        request_id: u64,
+        amount: u64,
+        cooldown_end_timestamp: u64,
+        nullifier_secret: [u8; 32],
+        user_account_id: AccountId,
+    ) -> Self {
+        let commitment = request_commitment(
             amount,
             cooldown_end_timestamp,
             &nullifier_secret,
@@ -84,24 +201,64 @@ impl UnlockRequest {
             nullifier_secret,
             user_account_id,
             commitment,
+            max_apr_bps: None,
+            staked_asset_faucet_id: None,
+            decimals: USDC_DECIMALS as u8,
         }
     }
     
-    /// Compute request commitment
-    fn compute_commitment(
-        amount: u64,
-        cooldown_end: u64,
-        nullifier: &[u8; 32],
-        _user_id: AccountId,
-    ) -> Word {
-        // Simplified commitment: in production, use proper hash
-        let nullifier_felt = u64::from_le_bytes(nullifier[0..8].try_into().unwrap());
-        Word::from([
-            Felt::new(amount),
-            Felt::new(cooldown_end),
-            Felt::new(nullifier_felt),
-            Felt::new(0), // Placeholder for user_id
-        ])
+    /// Check this request against what the user account stored for it, read
+    /// through a synced client or from the account itself
+    /// True if both the commitment and the locked amount match
+    pub async fn matches_onchain(
+        &self,
+        backend: &mut impl RequestBackend,
+        account_id: AccountId,
+    ) -> Result<bool> {
+        let stored_commitment = backend
+            .request_commitment(account_id, self.request_id)
+            .await
+            .context("Failed to read stored request commitment")?;
+        let stored_amount = backend
+            .request_amount(account_id, self.request_id)
+            .await
+            .context("Failed to read stored request amount")?;
+        
+        Ok(stored_commitment == self.commitment[0]
+            && felt_to_u64_checked(stored_amount) == Some(self.amount))
+    }
+    
+    /// Record which staked asset backs this request
+    pub fn with_staked_asset(mut self, faucet_id: AccountId) -> Self {
+        self.staked_asset_faucet_id = Some(faucet_id);
+        self
+    }
+    
+    /// Cap the APR this request is willing to match at
+    pub fn with_max_apr(mut self, max_apr_bps: u64) -> Self {
+        self.max_apr_bps = Some(max_apr_bps);
+        self
+    }
+    
+    /// Check the request against the user's available staked balance
+    /// Catches an oversized request before `create_unlock_request` rejects
+    /// it on-chain
+    pub fn validate_against_balance(&self, staked_balance: u64) -> Result<()> {
+        ensure!(
+            self.amount <= staked_balance,
+            "Unlock request {} for {} exceeds available staked balance {}",
+            self.request_id,
+            self.amount,
+            staked_balance
+        );
+        Ok(())
+    }
+    
+    /// Element of the nullifier secret folded into the commitment
+    /// Passed privately to `create_unlock_request` so the account can
+    /// recompute the commitment itself
+    pub fn commitment_secret(&self) -> Felt {
+        commitment_secret(&self.nullifier_secret)
     }
     
     /// Calculate net advance amount after fees
@@ -119,12 +276,270 @@ impl UnlockRequest {
     pub fn apr_interest(&self, cooldown_days: u64) -> u64 {
         (self.amount * DEFAULT_APR_BPS * cooldown_days) / (10000 * 365)
     }
+    
+    /// Nullifier registered on-chain when the request is created
+    /// Derived from a different part of the secret than the commitment
+    pub fn nullifier(&self) -> Felt {
+        Felt::new(u64::from_le_bytes(self.nullifier_secret[8..16].try_into().unwrap()))
+    }
+    
+    /// Whole days left until cooldown ends, rounded up
+    pub fn cooldown_days(&self, now: u64) -> u64 {
+        self.cooldown_end_timestamp.saturating_sub(now).div_ceil(24 * 60 * 60)
+    }
+}
+
+/// Read access to a user account's stored unlock requests
+/// Implemented for a synced client and for an `Account` read directly
+pub trait RequestBackend {
+    /// Stored commitment element (`get_request_commitment`)
+    fn request_commitment(
+        &mut self,
+        account_id: AccountId,
+        request_id: u64,
+    ) -> impl Future<Output = Result<Felt>>;
+    
+    /// Stored locked amount (`get_request_amount`)
+    fn request_amount(
+        &mut self,
+        account_id: AccountId,
+        request_id: u64,
+    ) -> impl Future<Output = Result<Felt>>;
+    
+    /// ID the account will give its next request (`get_request_counter`)
+    fn request_counter(&mut self, account_id: AccountId) -> impl Future<Output = Result<u64>>;
+    
+    /// Read one request's stored state
+    fn request(
+        &mut self,
+        account_id: AccountId,
+        request_id: u64,
+    ) -> impl Future<Output = Result<RequestView>>;
+}
+
+/// An unlock request as stored in the user account's `unlock_requests` map
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RequestView {
+    /// Locked amount (`get_request_amount`), zero once cancelled
+    pub amount: Felt,
+    /// Cooldown end (`get_request_cooldown_end`)
+    pub cooldown_end: Felt,
+    /// Matched flag (`is_request_matched`)
+    pub is_matched: bool,
+    /// Settled flag (`is_settled`)
+    pub is_settled: bool,
+}
+
+/// A user's collateral value less everything they owe on open deals, read
+/// through a synced client or from the user account itself
+///
+/// Each matched, unsettled request owes its locked amount (the net advance
+/// plus fee) and the default-APR interest for the whole days left until its
+/// cooldown ends at `now`. `collateral_value` is the caller's current
+/// valuation of the user's staked assets, so slashing or a price drop can
+/// push the result negative
+pub async fn user_net_position(
+    backend: &mut impl RequestBackend,
+    user_account_id: AccountId,
+    collateral_value: u64,
+    now: u64,
+) -> Result<i64> {
+    let request_count = backend
+        .request_counter(user_account_id)
+        .await
+        .context("Failed to read request counter")?;
+    
+    let mut obligations: u64 = 0;
+    for request_id in 0..request_count {
+        let request = backend
+            .request(user_account_id, request_id)
+            .await
+            .with_context(|| format!("Failed to read request {request_id}"))?;
+        if !request.is_matched || request.is_settled {
+            continue;
+        }
+        
+        let amount = felt_to_u64_checked(request.amount)
+            .with_context(|| format!("Request {request_id} amount is out of range"))?;
+        let days = request.cooldown_end.as_int().saturating_sub(now).div_ceil(24 * 60 * 60);
+        obligations = amount
+            .checked_add(PricingCalculator::apr_interest(amount, days))
+            .and_then(|owed| obligations.checked_add(owed))
+            .context("Obligations overflow u64")?;
+    }
+    
+    let collateral = i64::try_from(collateral_value).context("Collateral value overflows i64")?;
+    let obligations = i64::try_from(obligations).context("Obligations overflow i64")?;
+    Ok(collateral - obligations)
+}
+
+/// Hands out request IDs in the order `create_unlock_request` assigns them
+/// Requests built with these IDs line up with the account's storage instead
+/// of colliding on a hardcoded ID
+#[derive(Clone, Debug, Default)]
+pub struct RequestIdAllocator {
+    next_id: u64,
+}
+
+impl RequestIdAllocator {
+    /// Allocator for a fresh account, whose first request is ID 0
+    pub fn new() -> Self {
+        Self::starting_at(0)
+    }
+    
+    /// Allocator continuing from an account's request counter
+    pub fn starting_at(request_counter: u64) -> Self {
+        Self { next_id: request_counter }
+    }
+    
+    /// Allocator continuing from the account's on-chain request counter, read
+    /// through a synced client or from the account itself
+    pub async fn from_account(
+        backend: &mut impl RequestBackend,
+        account_id: AccountId,
+    ) -> Result<Self> {
+        let counter = backend
+            .request_counter(account_id)
+            .await
+            .context("Failed to read request counter")?;
+        Ok(Self::starting_at(counter))
+    }
+    
+    /// Take the next request ID
+    pub fn next_id(&mut self) -> u64 {
+        let request_id = self.next_id;
+        self.next_id += 1;
+        request_id
+    }
+}
+
+/// Request status change logged by the user account
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RequestEventKind {
+    /// `create_unlock_request`
+    Created,
+    /// `cancel_request`
+    Cancelled,
+    /// `mark_request_matched`
+    Matched,
+}
+
+impl RequestEventKind {
+    /// Decode the status code the contract logs
+    pub fn from_code(code: u64) -> Result<Self> {
+        match code {
+            1 => Ok(Self::Created),
+            2 => Ok(Self::Cancelled),
+            3 => Ok(Self::Matched),
+            _ => bail!("Unknown request event status {code}"),
+        }
+    }
+}
+
+/// One entry of a user account's request event log
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RequestEvent {
+    /// Position in the account's log
+    pub event_index: u64,
+    /// Request the status change applies to
+    pub request_id: u64,
+    /// New status
+    pub kind: RequestEventKind,
+}
+
+/// Read access to a user account's request event log
+/// Implemented for a synced client and for an `Account` read directly
+pub trait RequestEventBackend {
+    /// Number of events logged (`get_request_event_count`)
+    fn event_count(&mut self, account_id: AccountId) -> impl Future<Output = Result<u64>>;
+    
+    /// Logged event as `[request_id, status, 0, 0]` (`get_request_event`)
+    fn event(
+        &mut self,
+        account_id: AccountId,
+        event_index: u64,
+    ) -> impl Future<Output = Result<Word>>;
+}
+
+/// Read a user account's request events from `from_index` onward
+/// Indexers pass the count they saw last time to pick up only new events
+pub async fn fetch_request_events(
+    backend: &mut impl RequestEventBackend,
+    account_id: AccountId,
+    from_index: u64,
+) -> Result<Vec<RequestEvent>> {
+    let count = backend.event_count(account_id).await.context("Failed to read event count")?;
+    let mut events = Vec::new();
+    
+    for event_index in from_index..count {
+        let word = backend
+            .event(account_id, event_index)
+            .await
+            .with_context(|| format!("Failed to read request event {event_index}"))?;
+        events.push(RequestEvent {
+            event_index,
+            request_id: word[0].as_int(),
+            kind: RequestEventKind::from_code(word[1].as_int())?,
+        });
+    }
+    Ok(events)
+}
+
+/// Canonical unlock request commitment
+///
+/// `hash(amount, cooldown_end, secret, user_id)` over RPO, the same hash
+/// `create_unlock_request` computes on-chain, so a request's commitment can
+/// never be unrelated to its actual values
+pub fn request_commitment(
+    amount: u64,
+    cooldown_end: u64,
+    nullifier_secret: &[u8; 32],
+    user_id: AccountId,
+) -> Word {
+    let [user_prefix, user_suffix] = account_id_felts(user_id);
+    Rpo256::hash_elements(&[
+        Felt::new(amount),
+        Felt::new(cooldown_end),
+        commitment_secret(nullifier_secret),
+        user_prefix,
+        user_suffix,
+    ])
+}
+
+/// Check a batch of claimed commitments against their requests
+/// Returns one flag per pair, in input order
+pub fn verify_commitments(requests: &[(UnlockRequest, Word)]) -> Vec<bool> {
+    requests
+        .iter()
+        .map(|(request, claimed)| {
+            request_commitment(
+                request.amount,
+                request.cooldown_end_timestamp,
+                &request.nullifier_secret,
+                request.user_account_id,
+            ) == *claimed
+        })
+        .collect()
+}
+
+fn commitment_secret(nullifier_secret: &[u8; 32]) -> Felt {
+    Felt::new(u64::from_le_bytes(nullifier_secret[0..8].try_into().unwrap()))
 }
 
 // ============================================================================
 // LP OFFER TYPES
 // ============================================================================
 
+/// Asset an LP offer's amounts are quoted in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OfferDenomination {
+    /// USDC the LP advances
+    #[default]
+    Usdc,
+    /// Staked asset the LP wants to receive as collateral
+    StakedAsset,
+}
+
 /// LP offer for providing liquidity
 #[derive(Clone, Debug)]
 pub struct LpOffer {
@@ -132,16 +547,22 @@ pub struct LpOffer {
     pub offer_id: u64,
     /// LP pool account ID
     pub lp_account_id: AccountId,
-    /// Maximum USDC to advance
-    pub max_amount: u64,
-    /// Minimum USDC to advance
-    pub min_amount: u64,
+    /// Maximum USDC to advance; set through `update` so the commitment follows
+    max_amount: u64,
+    /// Minimum USDC to advance; set through `update` so the commitment follows
+    min_amount: u64,
     /// Custom APR (basis points), or use default
     pub custom_apr_bps: Option<u64>,
-    /// Offer commitment (public hash)
-    pub commitment: Word,
+    /// USDC still available to advance from this offer, never over the max
+    remaining_amount: u64,
+    /// Offer commitment (public hash), computed on first use
+    commitment: OnceCell<Word>,
     /// Is offer currently active
     pub is_active: bool,
+    /// Decimals the offer's amounts are expressed in (raw units)
+    pub decimals: u8,
+    /// Asset the offer's max, min and remaining amounts are quoted in
+    pub denomination: OfferDenomination,
 }
 
 impl LpOffer {
@@ -153,60 +574,310 @@ impl LpOffer {
         min_amount: u64,
         custom_apr_bps: Option<u64>,
     ) -> Self {
-        let commitment = Self::compute_commitment(
-            offer_id,
-            lp_account_id,
-            max_amount,
-            min_amount,
-        );
-        
         Self {
             offer_id,
             lp_account_id,
             max_amount,
             min_amount,
             custom_apr_bps,
-            commitment,
+            remaining_amount: max_amount,
+            commitment: OnceCell::new(),
             is_active: true,
+            decimals: USDC_DECIMALS as u8,
+            denomination: OfferDenomination::Usdc,
+        }
+    }
+    
+    /// Declare the decimals of the offered asset, if not USDC's 6
+    pub fn with_decimals(mut self, decimals: u8) -> Self {
+        self.decimals = decimals;
+        self
+    }
+    
+    /// Declare the asset the offer's amounts are quoted in, if not USDC
+    pub fn with_denomination(mut self, denomination: OfferDenomination) -> Self {
+        self.denomination = denomination;
+        self
+    }
+    
+    /// This offer with its amounts in USDC terms
+    /// Staked-asset amounts are valued at `staked_price_bps` (USDC per staked
+    /// unit, 10000 = 1:1); the commitment is kept, as it's what the LP published
+    pub fn in_usdc(&self, staked_price_bps: u64) -> LpOffer {
+        if self.denomination == OfferDenomination::Usdc {
+            return self.clone();
+        }
+        
+        let to_usdc = |amount: u64| (amount as u128 * staked_price_bps as u128 / 10000) as u64;
+        LpOffer {
+            max_amount: to_usdc(self.max_amount),
+            min_amount: to_usdc(self.min_amount),
+            remaining_amount: to_usdc(self.remaining_amount),
+            decimals: USDC_DECIMALS as u8,
+            denomination: OfferDenomination::Usdc,
+            ..self.clone()
         }
     }
     
+    /// Format a raw amount in this offer's units
+    pub fn format_amount(&self, raw: u64) -> String {
+        format_units(raw, self.decimals)
+    }
+    
+    /// Offer commitment (public hash)
+    /// Computed once and cached until the offer's amounts change
+    pub fn commitment(&self) -> Word {
+        *self.commitment.get_or_init(|| {
+            Self::compute_commitment(
+                self.offer_id,
+                self.lp_account_id,
+                self.max_amount,
+                self.min_amount,
+            )
+        })
+    }
+    
+    /// Maximum USDC to advance
+    pub fn max_amount(&self) -> u64 {
+        self.max_amount
+    }
+    
+    /// Minimum USDC to advance
+    pub fn min_amount(&self) -> u64 {
+        self.min_amount
+    }
+    
+    /// USDC still available to advance from this offer
+    pub fn remaining_amount(&self) -> u64 {
+        self.remaining_amount
+    }
+    
+    /// Set what's left to advance, capped at the offer's max amount
+    pub fn set_remaining_amount(&mut self, remaining_amount: u64) {
+        self.remaining_amount = remaining_amount.min(self.max_amount);
+    }
+    
+    /// Change the offer's range, recomputing the commitment on next use
+    /// Remaining capacity over the new max is cut back to it
+    pub fn update(&mut self, max_amount: u64, min_amount: u64) {
+        self.max_amount = max_amount;
+        self.min_amount = min_amount;
+        self.remaining_amount = self.remaining_amount.min(max_amount);
+        self.commitment.take();
+    }
+    
     /// Compute offer commitment
+    /// `hash(offer_id, lp_id, max_amount, min_amount)`, so the same terms from
+    /// two LPs never share a commitment
     fn compute_commitment(
         offer_id: u64,
-        _lp_id: AccountId,
+        lp_id: AccountId,
         max_amount: u64,
         min_amount: u64,
     ) -> Word {
-        Word::from([
+        let [lp_prefix, lp_suffix] = account_id_felts(lp_id);
+        Rpo256::hash_elements(&[
             Felt::new(offer_id),
-            Felt::new(0), // Placeholder for lp_id
+            lp_prefix,
+            lp_suffix,
             Felt::new(max_amount),
             Felt::new(min_amount),
         ])
     }
     
+    /// Effective APR in basis points (custom or default)
+    pub fn apr_bps(&self) -> u64 {
+        self.custom_apr_bps.unwrap_or(DEFAULT_APR_BPS)
+    }
+    
     /// Check if offer can match a request
     pub fn can_match(&self, request_amount: u64) -> bool {
         self.is_active && 
         request_amount >= self.min_amount && 
-        request_amount <= self.max_amount
+        request_amount <= self.max_amount &&
+        PricingCalculator::net_advance(request_amount) <= self.remaining_amount
+    }
+    
+    /// Smallest principal whose LP earnings (fee share plus interest at
+    /// `apr_bps` over `days`) exceed a fixed per-settlement cost
+    /// Useful as a floor for `min_amount`; `u64::MAX` if no principal is
+    pub fn min_profitable_amount(fixed_cost: u64, apr_bps: u64, days: u64) -> u64 {
+        let profitable = |principal| PricingCalculator::lp_earnings(principal, apr_bps, days) > fixed_cost;
+        
+        // Find an upper bound, then binary search for the threshold
+        let mut high = 1;
+        while !profitable(high) {
+            match high.checked_mul(2) {
+                Some(doubled) => high = doubled,
+                None => return u64::MAX,
+            }
+        }
+        let mut low = 0;
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if profitable(mid) {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+        low
+    }
+    
+    /// Largest principal up to `amount` this offer can fund from its
+    /// remaining capacity, or 0 if that falls below the offer's minimum
+    pub fn max_fillable(&self, amount: u64) -> u64 {
+        if !self.is_active {
+            return 0;
+        }
+        let capacity = u128::from(self.remaining_amount) * 10000
+            / u128::from(10000 - DEFAULT_ADVANCE_FEE_BPS);
+        let capacity = u64::try_from(capacity).unwrap_or(u64::MAX);
+        let mut principal = amount.min(self.max_amount).min(capacity);
+        // Fee rounding can leave the net advance a unit over capacity
+        while principal > 0 && PricingCalculator::net_advance(principal) > self.remaining_amount {
+            principal -= 1;
+        }
+        if principal < self.min_amount {
+            0
+        } else {
+            principal
+        }
+    }
+    
+    /// Check if offer can match a request, respecting the user's APR cap
+    pub fn can_match_request(&self, request: &UnlockRequest) -> bool {
+        self.can_match(request.amount) &&
+        request.max_apr_bps.is_none_or(|max_apr| self.apr_bps() <= max_apr)
+    }
+    
+    /// Check if another active offer from the same LP covers an overlapping range
+    pub fn overlaps(&self, other: &LpOffer) -> bool {
+        self.is_active &&
+        other.is_active &&
+        self.lp_account_id == other.lp_account_id &&
+        self.min_amount <= other.max_amount &&
+        other.min_amount <= self.max_amount
+    }
+    
+    /// Widen this offer's range to cover another offer's range
+    /// Keeps this offer's id and APR
+    pub fn merge_range(&mut self, other: &LpOffer) {
+        self.update(
+            self.max_amount.max(other.max_amount),
+            self.min_amount.min(other.min_amount),
+        );
+        self.set_remaining_amount(self.remaining_amount.max(other.remaining_amount));
+    }
+}
+
+// ============================================================================
+// OFFER BOOK COMMITMENT
+// ============================================================================
+
+/// Merkle tree over offer commitments
+/// LPs publish only the root; an offer's inclusion is shown with a proof
+/// without revealing the rest of the book
+#[derive(Clone, Debug)]
+pub struct OfferTree {
+    /// Offer ID of each leaf, in leaf order
+    offer_ids: Vec<u64>,
+    /// Tree nodes by level, leaves first and the root last
+    levels: Vec<Vec<Word>>,
+}
+
+/// Path from an offer's leaf to the tree root
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleProof {
+    /// Position of the offer's leaf
+    pub leaf_index: u64,
+    /// Sibling at each level, from the leaf up
+    pub siblings: Vec<Word>,
+}
+
+impl OfferTree {
+    /// Root committing to every offer in the tree
+    pub fn root(&self) -> Word {
+        self.levels[self.levels.len() - 1][0]
+    }
+    
+    /// Prove the offer with `offer_id` is in the tree
+    pub fn prove(&self, offer_id: u64) -> Result<MerkleProof> {
+        let Some(position) = self.offer_ids.iter().position(|id| *id == offer_id) else {
+            bail!("Offer {offer_id} is not in the tree");
+        };
+        
+        let mut index = position;
+        let mut siblings = Vec::with_capacity(self.levels.len() - 1);
+        for level in &self.levels[..self.levels.len() - 1] {
+            siblings.push(level[index ^ 1]);
+            index /= 2;
+        }
+        
+        Ok(MerkleProof { leaf_index: position as u64, siblings })
+    }
+}
+
+/// Build a Merkle tree over the offers' commitments
+/// Leaves are in input order, padded with empty words to a power of two
+pub fn build_offer_tree(offers: &[LpOffer]) -> (Word, OfferTree) {
+    let mut leaves: Vec<Word> = offers.iter().map(LpOffer::commitment).collect();
+    let empty_leaf = Word::from([Felt::new(0); 4]);
+    leaves.resize(offers.len().max(1).next_power_of_two(), empty_leaf);
+    
+    let mut levels = vec![leaves];
+    while levels[levels.len() - 1].len() > 1 {
+        let parents = levels[levels.len() - 1]
+            .chunks(2)
+            .map(|pair| Rpo256::merge(&[pair[0], pair[1]]))
+            .collect();
+        levels.push(parents);
+    }
+    
+    let tree = OfferTree {
+        offer_ids: offers.iter().map(|offer| offer.offer_id).collect(),
+        levels,
+    };
+    (tree.root(), tree)
+}
+
+/// Check a proof that `offer_commitment` is a leaf of the tree with `root`
+pub fn verify_offer_proof(root: Word, proof: &MerkleProof, offer_commitment: Word) -> bool {
+    if proof.siblings.len() >= 64 || proof.leaf_index >> proof.siblings.len() != 0 {
+        return false;
+    }
+    
+    let mut node = offer_commitment;
+    let mut index = proof.leaf_index;
+    for sibling in &proof.siblings {
+        node = if index % 2 == 0 {
+            Rpo256::merge(&[node, *sibling])
+        } else {
+            Rpo256::merge(&[*sibling, node])
+        };
+        index /= 2;
     }
+    node == root
 }
 
 // ============================================================================
 // MATCHED DEAL TYPES
 // ============================================================================
 
+/// Off-chain identifier of a matched deal
+pub type DealId = Word;
+
 /// A matched deal between user and LP
 #[derive(Clone, Debug)]
 pub struct MatchedDeal {
     /// Unique deal ID
-    pub deal_id: Word,
-    /// The unlock request
+    pub deal_id: DealId,
+    /// The unlock request, exactly as committed on-chain
     pub request: UnlockRequest,
     /// The matched LP offer
     pub offer: LpOffer,
+    /// Principal the deal funds; below the request's amount for a partial fill
+    pub principal: u64,
     /// Net USDC advance amount
     pub advance_amount: u64,
     /// Settlement note hash
@@ -236,12 +907,14 @@ impl MatchedDeal {
             Felt::new(u64::from_le_bytes(deal_id_bytes[24..32].try_into().unwrap())),
         ]);
         
+        let principal = request.amount;
         let advance_amount = request.net_advance();
         
         Self {
             deal_id,
             request,
             offer,
+            principal,
             advance_amount,
             settlement_note_hash: Word::default(),
             advance_note_hash: Word::default(),
@@ -250,86 +923,867 @@ impl MatchedDeal {
         }
     }
     
+    /// Fund only `principal` of the request, leaving its commitment as is
+    fn with_principal(mut self, principal: u64) -> Self {
+        self.principal = principal;
+        self.advance_amount = PricingCalculator::net_advance(principal);
+        self
+    }
+    
+    /// Record when the deal was matched
+    fn with_matched_at(mut self, matched_at: u64) -> Self {
+        self.matched_at = matched_at;
+        self
+    }
+    
+    /// Advance fee on the funded principal
+    pub fn advance_fee(&self) -> u64 {
+        PricingCalculator::advance_fee(self.principal)
+    }
+    
     /// Calculate LP earnings
     pub fn lp_earnings(&self, cooldown_days: u64) -> (u64, u64) {
-        let fee = self.request.advance_fee();
-        let interest = self.request.apr_interest(cooldown_days);
+        let fee = self.advance_fee();
+        let interest = PricingCalculator::apr_interest(self.principal, cooldown_days);
         let lp_fee = (fee * LP_FEE_BPS) / 10000;
         (lp_fee, interest)
     }
     
     /// Calculate protocol earnings
     pub fn protocol_earnings(&self) -> u64 {
-        let fee = self.request.advance_fee();
+        let fee = self.advance_fee();
         (fee * PROTOCOL_FEE_BPS) / 10000
     }
-}
-
-// ============================================================================
-// OFF-CHAIN MATCHING ENGINE
-// ============================================================================
-
-/// Private off-chain matching engine
-/// All matching happens locally without broadcasting intent
-pub struct MatchingEngine {
-    /// Available LP offers (would be fetched privately in production)
-    pub offers: Vec<LpOffer>,
-}
-
-impl MatchingEngine {
-    /// Create a new matching engine
-    pub fn new() -> Self {
-        Self { offers: Vec::new() }
+    
+    /// Timestamped cash flows for every party, signed from that party's side
+    ///
+    /// At `start` the LP advances the gross principal, keeping its fee share
+    /// and passing the protocol's on, and the user receives it less the fee.
+    /// At the end of the cooldown the principal comes back to the LP with
+    /// interest. Flows at each timestamp sum to zero.
+    pub fn cash_flows(&self, start: u64) -> Vec<(u64, i64, &'static str)> {
+        let principal = self.principal as i64;
+        let fee = self.advance_fee() as i64;
+        let lp_fee = PricingCalculator::lp_fee_share(fee as u64) as i64;
+        let protocol_fee = PricingCalculator::protocol_fee_share(fee as u64) as i64;
+        let days = self.request.cooldown_days(start);
+        let interest = PricingCalculator::apr_interest(self.principal, days) as i64;
+        let settled_at = self.request.cooldown_end_timestamp;
+        
+        vec![
+            (start, principal, "user: advance"),
+            (start, -fee, "user: advance fee"),
+            (start, -principal, "lp: advance"),
+            (start, lp_fee, "lp: fee share"),
+            (start, protocol_fee, "protocol: fee share"),
+            (settled_at, -principal, "user: principal repaid"),
+            (settled_at, -interest, "user: interest"),
+            (settled_at, principal, "lp: principal returned"),
+            (settled_at, interest, "lp: interest"),
+        ]
     }
     
-    /// Add an LP offer to the engine
-    pub fn add_offer(&mut self, offer: LpOffer) {
-        self.offers.push(offer);
+    /// Advance fee in display units, e.g. `"150.00"`
+    pub fn fee_display(&self) -> String {
+        format_display(self.advance_fee(), self.offer.decimals)
     }
     
-    /// Find matching offers for a request
-    /// Returns offers sorted by best terms (lowest APR)
-    pub fn find_matches(&self, request: &UnlockRequest) -> Vec<&LpOffer> {
-        let mut matches: Vec<&LpOffer> = self.offers
-            .iter()
-            .filter(|offer| offer.can_match(request.amount))
-            .collect();
-        
-        // Sort by APR (lower is better for user)
-        matches.sort_by(|a, b| {
-            let apr_a = a.custom_apr_bps.unwrap_or(DEFAULT_APR_BPS);
-            let apr_b = b.custom_apr_bps.unwrap_or(DEFAULT_APR_BPS);
-            apr_a.cmp(&apr_b)
-        });
-        
-        matches
+    /// APR interest over `days` in display units
+    pub fn interest_display(&self, days: u64) -> String {
+        format_display(PricingCalculator::apr_interest(self.principal, days), self.offer.decimals)
     }
     
-    /// Match a request with the best offer
-    pub fn match_request(
-        &self,
-        request: UnlockRequest,
-        rng: &mut impl RngCore,
-    ) -> Option<MatchedDeal> {
-        let matches = self.find_matches(&request);
+    /// Net advance in display units
+    pub fn advance_display(&self) -> String {
+        format_display(self.advance_amount, self.offer.decimals)
+    }
+    
+    /// Receipt of the deal's terms and amounts, with interest over the
+    /// cooldown as seen at `matched_at`
+    pub fn receipt(&self, matched_at: u64) -> DealReceipt {
+        let apr_bps = self.offer.apr_bps();
+        let cooldown_days = self.request.cooldown_days(matched_at);
+        let fee = self.advance_fee();
+        let interest = PricingCalculator::apr_interest_at(self.principal, apr_bps, cooldown_days);
         
-        if let Some(best_offer) = matches.first() {
-            Some(MatchedDeal::new(request, (*best_offer).clone(), rng))
-        } else {
-            None
+        DealReceipt {
+            deal_id: self.deal_id,
+            principal: self.principal,
+            apr_bps,
+            cooldown_days,
+            advance_amount: self.advance_amount,
+            fee,
+            interest,
+            lp_fee_share: PricingCalculator::lp_fee_share(fee),
+            protocol_fee_share: PricingCalculator::protocol_fee_share(fee),
         }
     }
 }
 
-impl Default for MatchingEngine {
-    fn default() -> Self {
-        Self::new()
-    }
+/// Record of a deal's agreed terms and every amount derived from them,
+/// kept by both parties for dispute resolution
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DealReceipt {
+    /// Off-chain deal ID
+    pub deal_id: DealId,
+    /// Staked amount the advance was made against
+    pub principal: u64,
+    /// Agreed APR in basis points
+    pub apr_bps: u64,
+    /// Whole days of cooldown interest is charged for
+    pub cooldown_days: u64,
+    /// Net USDC advanced
+    pub advance_amount: u64,
+    /// Advance fee
+    pub fee: u64,
+    /// Interest over the cooldown
+    pub interest: u64,
+    /// LP share of the fee
+    pub lp_fee_share: u64,
+    /// Protocol share of the fee
+    pub protocol_fee_share: u64,
 }
 
+/// Receipt field that doesn't reconcile with the principal and terms
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisputeFinding {
+    /// Fee isn't the protocol fee rate on the principal
+    FeeMismatch { recorded: u64, expected: u64 },
+    /// Advance isn't the principal less the fee
+    AdvanceMismatch { recorded: u64, expected: u64 },
+    /// Interest isn't the agreed APR on the principal over the cooldown
+    InterestMismatch { recorded: u64, expected: u64 },
+    /// Fee shares don't split the fee at the protocol rates
+    FeeSplitMismatch {
+        recorded_lp: u64,
+        recorded_protocol: u64,
+        expected_lp: u64,
+        expected_protocol: u64,
+    },
+}
+
+impl DealReceipt {
+    /// Recompute every amount from the principal and terms
+    /// Fields are checked in the order they're derived, so the finding names
+    /// the first one that doesn't reconcile
+    pub fn validate(&self) -> Result<(), DisputeFinding> {
+        let fee = PricingCalculator::advance_fee(self.principal);
+        if self.fee != fee {
+            return Err(DisputeFinding::FeeMismatch { recorded: self.fee, expected: fee });
+        }
+        
+        let advance_amount = self.principal - fee;
+        if self.advance_amount != advance_amount {
+            return Err(DisputeFinding::AdvanceMismatch {
+                recorded: self.advance_amount,
+                expected: advance_amount,
+            });
+        }
+        
+        let interest =
+            PricingCalculator::apr_interest_at(self.principal, self.apr_bps, self.cooldown_days);
+        if self.interest != interest {
+            return Err(DisputeFinding::InterestMismatch {
+                recorded: self.interest,
+                expected: interest,
+            });
+        }
+        
+        let expected_lp = PricingCalculator::lp_fee_share(fee);
+        let expected_protocol = PricingCalculator::protocol_fee_share(fee);
+        if self.lp_fee_share != expected_lp || self.protocol_fee_share != expected_protocol {
+            return Err(DisputeFinding::FeeSplitMismatch {
+                recorded_lp: self.lp_fee_share,
+                recorded_protocol: self.protocol_fee_share,
+                expected_lp,
+                expected_protocol,
+            });
+        }
+        Ok(())
+    }
+}
+
+// ============================================================================
+// OFF-CHAIN MATCHING ENGINE
+// ============================================================================
+
+/// How the engine treats a new offer whose range overlaps an active offer
+/// from the same LP
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverlapPolicy {
+    /// Keep every offer as posted
+    #[default]
+    Allow,
+    /// Merge the new range into the existing offer so capacity isn't counted twice
+    Coalesce,
+    /// Drop the new offer
+    Reject,
+}
+
+/// Quote for a would-be deal, computed without touching engine state
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DealPreview {
+    /// Offer the request would be matched with
+    pub offer_id: u64,
+    /// Net USDC the user would receive
+    pub advance_amount: u64,
+    /// Advance fee charged up front
+    pub fee: u64,
+    /// Interest at the offer's APR over the remaining cooldown
+    pub interest: u64,
+}
+
+/// Full cost breakdown for a request against the current offer book
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Quote {
+    /// Offer the request would be matched with
+    pub offer_id: u64,
+    /// APR of the chosen offer, in basis points
+    pub apr_bps: u64,
+    /// Net USDC the user would receive
+    pub advance_amount: u64,
+    /// Advance fee charged up front
+    pub fee: u64,
+    /// Interest at the offer's APR over the remaining cooldown
+    pub interest: u64,
+    /// LP's share of the fee
+    pub lp_fee_share: u64,
+    /// Protocol's share of the fee
+    pub protocol_fee_share: u64,
+    /// Fee plus interest
+    pub total_cost: u64,
+    /// Timestamp the deal is expected to settle
+    pub settlement_eta: u64,
+}
+
+/// Why a request could not be matched
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoMatchReason {
+    /// No active offer covers the requested amount
+    NoMatchingOffer,
+    /// Offers cover the amount but all exceed the user's APR cap
+    AllTooExpensive,
+    /// Fee plus interest over the cooldown would use up the whole principal
+    NotEconomical,
+}
+
+/// An offer as stored in the LP pool's `active_offers` map
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OfferView {
+    /// LP account that created the offer
+    pub lp_account_id: AccountId,
+    /// Maximum USDC to advance
+    pub max_amount: Felt,
+    /// Minimum USDC to advance
+    pub min_amount: Felt,
+    /// Custom APR in basis points (0 = protocol default)
+    pub apr_bps: Felt,
+    /// Reservation not yet drawn by matched deals (`get_offer_reserved`)
+    pub remaining_amount: Felt,
+    /// Active flag
+    pub is_active: bool,
+}
+
+/// Read access to an LP pool's offers
+/// Implemented for a synced client and for an `Account` read directly
+pub trait OfferBackend {
+    /// Number of offers the pool has created (`get_offer_counter`)
+    fn offer_count(&mut self, pool_account_id: AccountId) -> impl Future<Output = Result<u64>>;
+    
+    /// Protocol share of advance fees in basis points (`get_fee_split`)
+    fn fee_split(&mut self, pool_account_id: AccountId) -> impl Future<Output = Result<u64>>;
+    
+    /// Read one offer's stored fields
+    fn offer(
+        &mut self,
+        pool_account_id: AccountId,
+        offer_id: u64,
+    ) -> impl Future<Output = Result<OfferView>>;
+}
+
+/// Private off-chain matching engine
+/// All matching happens locally without broadcasting intent
+#[derive(Clone)]
+pub struct MatchingEngine {
+    /// Available LP offers (would be fetched privately in production)
+    pub offers: Vec<LpOffer>,
+    /// Handling of overlapping offers from the same LP
+    pub overlap_policy: OverlapPolicy,
+    /// Deals drawn from offer capacity by `match_request_mut`, awaiting settlement
+    pub open_deals: Vec<MatchedDeal>,
+    /// USDC value of one staked unit in basis points (10000 = 1:1), used to
+    /// convert offers quoted in the staked asset
+    pub staked_price_bps: u64,
+    /// How long `hold` keeps capacity before it lapses back to the book
+    pub hold_timeout_seconds: u64,
+    /// Protocol share of advance fees in basis points, as the pool's
+    /// `get_fee_split` reports; quotes split fees by it
+    pub protocol_fee_bps: u64,
+    /// Capacity held for requests awaiting confirmation
+    holds: Vec<CapacityHold>,
+    /// Next hold token to hand out
+    next_hold: u64,
+}
+
+/// Handle on capacity held by `MatchingEngine::hold`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HoldToken(u64);
+
+/// Offer capacity held for one request
+#[derive(Clone, Debug)]
+struct CapacityHold {
+    token: HoldToken,
+    request: UnlockRequest,
+    offer_id: u64,
+    lp_account_id: AccountId,
+    advance_amount: u64,
+    expires_at: u64,
+}
+
+impl MatchingEngine {
+    /// Create a new matching engine
+    pub fn new() -> Self {
+        Self::with_overlap_policy(OverlapPolicy::Allow)
+    }
+    
+    /// Create a matching engine that coalesces or rejects overlapping
+    /// offers from the same LP
+    pub fn with_overlap_policy(overlap_policy: OverlapPolicy) -> Self {
+        Self {
+            offers: Vec::new(),
+            overlap_policy,
+            open_deals: Vec::new(),
+            staked_price_bps: 10000,
+            hold_timeout_seconds: DEFAULT_HOLD_TIMEOUT_SECONDS,
+            protocol_fee_bps: PROTOCOL_FEE_BPS,
+            holds: Vec::new(),
+            next_hold: 0,
+        }
+    }
+    
+    /// Value the staked asset at `staked_price_bps` USDC per unit when
+    /// converting offers quoted in it
+    pub fn with_staked_price(mut self, staked_price_bps: u64) -> Self {
+        self.staked_price_bps = staked_price_bps;
+        self
+    }
+    
+    /// Let holds lapse after `hold_timeout_seconds` instead of the default
+    pub fn with_hold_timeout(mut self, hold_timeout_seconds: u64) -> Self {
+        self.hold_timeout_seconds = hold_timeout_seconds;
+        self
+    }
+    
+    /// Split advance fees with `protocol_fee_bps` to the protocol instead of
+    /// the default, matching a pool configured through `set_fee_split`
+    pub fn with_fee_split(mut self, protocol_fee_bps: u64) -> Self {
+        self.protocol_fee_bps = protocol_fee_bps;
+        self
+    }
+    
+    /// Add an LP offer to the engine
+    /// Offers quoted in the staked asset are converted to USDC at the engine's
+    /// staked price, so the book is compared against requests in one unit
+    /// Returns false if the offer was rejected by the overlap policy
+    pub fn add_offer(&mut self, offer: LpOffer) -> bool {
+        let offer = offer.in_usdc(self.staked_price_bps);
+        if self.overlap_policy == OverlapPolicy::Allow {
+            self.offers.push(offer);
+            return true;
+        }
+        
+        let existing = self
+            .offers
+            .iter_mut()
+            .find(|existing| existing.overlaps(&offer));
+        
+        match (existing, self.overlap_policy) {
+            (None, _) => {
+                self.offers.push(offer);
+                true
+            }
+            (Some(existing), OverlapPolicy::Coalesce) => {
+                existing.merge_range(&offer);
+                true
+            }
+            (Some(_), _) => false,
+        }
+    }
+    
+    /// Replace the offer book with the pool's active offers, each seeded with
+    /// its remaining capacity after prior fills rather than its original max
+    /// The pool's fee split replaces the engine's too
+    pub async fn sync_from_pool(
+        &mut self,
+        backend: &mut impl OfferBackend,
+        pool_account_id: AccountId,
+    ) -> Result<()> {
+        let protocol_fee_bps = backend
+            .fee_split(pool_account_id)
+            .await
+            .context("Failed to read pool fee split")?;
+        
+        let offer_count = backend
+            .offer_count(pool_account_id)
+            .await
+            .context("Failed to read pool offer counter")?;
+        
+        let mut offers = Vec::new();
+        for offer_id in 0..offer_count {
+            let view = backend
+                .offer(pool_account_id, offer_id)
+                .await
+                .with_context(|| format!("Failed to read offer {offer_id}"))?;
+            if !view.is_active {
+                continue;
+            }
+            
+            let amount = |felt: Felt, field: &str| {
+                felt_to_u64_checked(felt)
+                    .with_context(|| format!("Offer {offer_id} {field} is out of range"))
+            };
+            let apr_bps = amount(view.apr_bps, "APR")?;
+            let mut offer = LpOffer::new(
+                offer_id,
+                view.lp_account_id,
+                amount(view.max_amount, "max amount")?,
+                amount(view.min_amount, "min amount")?,
+                (apr_bps != 0).then_some(apr_bps),
+            );
+            offer.set_remaining_amount(amount(view.remaining_amount, "remaining amount")?);
+            offers.push(offer);
+        }
+        
+        self.offers = offers;
+        self.protocol_fee_bps = protocol_fee_bps;
+        Ok(())
+    }
+    
+    /// Find matching offers for a request at `now`
+    /// Returns offers within the user's APR cap sorted by best terms (lowest APR)
+    /// Capacity held by holds that lapsed by `now` counts as available
+    /// Offers at the same APR are ordered by offer ID, so the order depends
+    /// only on the offers and not on how the book was assembled
+    pub fn find_matches(&self, request: &UnlockRequest, now: u64) -> Vec<&LpOffer> {
+        let mut matches: Vec<&LpOffer> = self.offers
+            .iter()
+            .filter(|offer| self.offer_at(offer, now).can_match_request(request))
+            .collect();
+        
+        // Sort by APR (lower is better for user), then offer ID
+        matches.sort_by_key(|offer| (offer.apr_bps(), offer.offer_id));
+        
+        matches
+    }
+    
+    /// Single offer that can fully fund a request at the lowest total cost
+    /// over its cooldown, for one-shot quotes
+    /// Only offers a match at `now` would take are considered: with holds
+    /// that lapsed by then given back, within the user's APR cap, and
+    /// economical for the user
+    pub fn cheapest_offer_for(&self, request: &UnlockRequest, now: u64) -> Option<&LpOffer> {
+        let days = request.cooldown_days(now);
+        self.find_matches(request, now)
+            .into_iter()
+            .filter(|offer| is_economical(request.amount, offer.apr_bps(), days))
+            .min_by_key(|offer| {
+                PricingCalculator::advance_fee(request.amount)
+                    + PricingCalculator::apr_interest_at(request.amount, offer.apr_bps(), days)
+            })
+    }
+    
+    /// Match a request with the best offer at `now`
+    pub fn match_request(
+        &self,
+        request: UnlockRequest,
+        now: u64,
+        rng: &mut impl RngCore,
+    ) -> Option<MatchedDeal> {
+        self.try_match_request(request, now, rng).ok()
+    }
+    
+    /// Match as much of a request as the book can fund when no offer covers
+    /// all of it
+    /// Returns the deal for the filled part and the unfilled remainder
+    pub fn match_best_effort(
+        &self,
+        request: UnlockRequest,
+        now: u64,
+        rng: &mut impl RngCore,
+    ) -> Option<(MatchedDeal, u64)> {
+        if let Some(deal) = self.match_request(request.clone(), now, rng) {
+            return Some((deal, 0));
+        }
+        
+        let days = request.cooldown_days(now);
+        let (offer, filled) = self
+            .offers
+            .iter()
+            .filter(|offer| request.max_apr_bps.is_none_or(|max_apr| offer.apr_bps() <= max_apr))
+            .map(|offer| (offer, self.offer_at(offer, now).max_fillable(request.amount)))
+            .filter(|(offer, filled)| *filled > 0 && is_economical(*filled, offer.apr_bps(), days))
+            .max_by_key(|(offer, filled)| (*filled, std::cmp::Reverse(offer.apr_bps())))?;
+        
+        let shortfall = request.amount - filled;
+        let deal = MatchedDeal::new(request, offer.clone(), rng)
+            .with_principal(filled)
+            .with_matched_at(now);
+        Some((deal, shortfall))
+    }
+    
+    /// Fewest offers a split match at `now` would touch to fund `amount`,
+    /// taking the offers that can fill the most first
+    /// Capacity held for unconfirmed matches isn't available until the hold
+    /// lapses. Returns `None` if the whole book can't cover it
+    pub fn min_offers_to_fill(&self, amount: u64, now: u64) -> Option<usize> {
+        let mut offers: Vec<Cow<LpOffer>> =
+            self.offers.iter().map(|offer| self.offer_at(offer, now)).collect();
+        offers.sort_by_key(|offer| std::cmp::Reverse(offer.max_fillable(amount)));
+        
+        let mut remaining = amount;
+        let mut count = 0;
+        for offer in offers {
+            if remaining == 0 {
+                break;
+            }
+            let filled = offer.max_fillable(remaining);
+            if filled > 0 {
+                remaining -= filled;
+                count += 1;
+            }
+        }
+        
+        (remaining == 0).then_some(count)
+    }
+    
+    /// Match a request using a deal ID drawn from a caller-provided seed
+    /// The same seed always yields the same deal ID, so deals can be
+    /// reproduced in tests and audits
+    pub fn match_request_seeded(
+        &self,
+        request: UnlockRequest,
+        now: u64,
+        seed: [u8; 32],
+    ) -> Option<MatchedDeal> {
+        self.match_request(request, now, &mut StdRng::from_seed(seed))
+    }
+    
+    /// Match a request with the best offer within the user's APR cap
+    /// Returns the reason when no deal can be made
+    pub fn try_match_request(
+        &self,
+        request: UnlockRequest,
+        now: u64,
+        rng: &mut impl RngCore,
+    ) -> Result<MatchedDeal, NoMatchReason> {
+        let best_offer = self.select_offer(&request, now)?;
+        Ok(MatchedDeal::new(request, best_offer.clone(), rng).with_matched_at(now))
+    }
+    
+    /// Best offer for a request at `now`, shared by every path that picks one
+    /// so a preview, quote or hold is never given on terms a match refuses
+    /// Rejects deals where the advance fee plus interest over the cooldown
+    /// would reach the principal, leaving the user owing more than they got
+    fn select_offer(&self, request: &UnlockRequest, now: u64) -> Result<&LpOffer, NoMatchReason> {
+        let Some(best_offer) = self.find_matches(request, now).first().copied() else {
+            let in_range = self
+                .offers
+                .iter()
+                .any(|offer| self.offer_at(offer, now).can_match(request.amount));
+            return Err(if in_range {
+                NoMatchReason::AllTooExpensive
+            } else {
+                NoMatchReason::NoMatchingOffer
+            });
+        };
+        
+        // The best offer has the lowest APR, so if it isn't economical none is
+        if !is_economical(request.amount, best_offer.apr_bps(), request.cooldown_days(now)) {
+            return Err(NoMatchReason::NotEconomical);
+        }
+        Ok(best_offer)
+    }
+    
+    /// IDs of active offers whose APR is more than `tolerance_bps` away
+    /// from a reference market rate
+    pub fn stale_offers(&self, reference_apr_bps: u64, tolerance_bps: u64) -> Vec<u64> {
+        self.offers
+            .iter()
+            .filter(|offer| offer.is_active)
+            .filter(|offer| offer.apr_bps().abs_diff(reference_apr_bps) > tolerance_bps)
+            .map(|offer| offer.offer_id)
+            .collect()
+    }
+    
+    /// Quote the deal a request would get without consuming capacity
+    /// or generating a deal ID
+    pub fn preview_match(&self, request: &UnlockRequest, now: u64) -> Option<DealPreview> {
+        let best_offer = self.select_offer(request, now).ok()?;
+        
+        let days = request.cooldown_days(now);
+        
+        Some(DealPreview {
+            offer_id: best_offer.offer_id,
+            advance_amount: request.net_advance(),
+            fee: request.advance_fee(),
+            interest: PricingCalculator::apr_interest_at(request.amount, best_offer.apr_bps(), days),
+        })
+    }
+    
+    /// Everything needed to decide on a request in one call: the chosen
+    /// offer, amounts, fee split, total cost and when it settles
+    pub fn quote(&self, request: &UnlockRequest, now: u64) -> Option<Quote> {
+        let best_offer = self.select_offer(request, now).ok()?;
+        
+        let days = request.cooldown_days(now);
+        let fee = request.advance_fee();
+        let interest = PricingCalculator::apr_interest_at(request.amount, best_offer.apr_bps(), days);
+        let split = self.protocol_fee_bps;
+        
+        Some(Quote {
+            offer_id: best_offer.offer_id,
+            apr_bps: best_offer.apr_bps(),
+            advance_amount: request.net_advance(),
+            fee,
+            interest,
+            lp_fee_share: PricingCalculator::lp_fee_share_at(fee, split),
+            protocol_fee_share: PricingCalculator::protocol_fee_share_at(fee, split),
+            total_cost: fee + interest,
+            settlement_eta: request.cooldown_end_timestamp,
+        })
+    }
+    
+    /// Match a request with the best offer and draw the advance from
+    /// that offer's remaining capacity
+    /// Holds that lapsed by `now` are released first
+    pub fn match_request_mut(
+        &mut self,
+        request: UnlockRequest,
+        now: u64,
+        rng: &mut impl RngCore,
+    ) -> Option<MatchedDeal> {
+        self.expire_holds(now);
+        let deal = self.match_request(request, now, rng)?;
+        
+        if let Some(offer) = self.offers.iter_mut().find(|offer| {
+            offer.offer_id == deal.offer.offer_id
+                && offer.lp_account_id == deal.offer.lp_account_id
+        }) {
+            offer.remaining_amount -= deal.advance_amount;
+        }
+        
+        self.open_deals.push(deal.clone());
+        Some(deal)
+    }
+    
+    /// Hold the best offer's capacity for a request while its user confirms
+    /// Held capacity is out of reach of other matches until the hold is
+    /// released, committed, or lapses after `hold_timeout_seconds`
+    /// Returns `None` if no offer can fund the request at `now`
+    pub fn hold(&mut self, request: UnlockRequest, now: u64) -> Option<HoldToken> {
+        self.expire_holds(now);
+        
+        let offer = self.select_offer(&request, now).ok()?;
+        let (offer_id, lp_account_id) = (offer.offer_id, offer.lp_account_id);
+        let advance_amount = request.net_advance();
+        self.offer_mut(offer_id, lp_account_id)?.remaining_amount -= advance_amount;
+        
+        let token = HoldToken(self.next_hold);
+        self.next_hold += 1;
+        self.holds.push(CapacityHold {
+            token,
+            request,
+            offer_id,
+            lp_account_id,
+            advance_amount,
+            expires_at: now + self.hold_timeout_seconds,
+        });
+        Some(token)
+    }
+    
+    /// Give held capacity back to its offer
+    /// Returns false if the hold was already released, committed or lapsed
+    pub fn release(&mut self, token: HoldToken) -> bool {
+        let Some(index) = self.holds.iter().position(|hold| hold.token == token) else {
+            return false;
+        };
+        let hold = self.holds.swap_remove(index);
+        if let Some(offer) = self.offer_mut(hold.offer_id, hold.lp_account_id) {
+            offer.remaining_amount += hold.advance_amount;
+        }
+        true
+    }
+    
+    /// Turn a hold into a deal on the offer it holds capacity from
+    /// Returns `None` if the hold was released or lapsed before `now`
+    pub fn commit(
+        &mut self,
+        token: HoldToken,
+        now: u64,
+        rng: &mut impl RngCore,
+    ) -> Option<MatchedDeal> {
+        self.expire_holds(now);
+        
+        let index = self.holds.iter().position(|hold| hold.token == token)?;
+        let hold = self.holds.swap_remove(index);
+        let offer = self.offer_mut(hold.offer_id, hold.lp_account_id)?.clone();
+        
+        let deal = MatchedDeal::new(hold.request, offer, rng).with_matched_at(now);
+        self.open_deals.push(deal.clone());
+        Some(deal)
+    }
+    
+    /// Release every hold that has lapsed by `now`
+    fn expire_holds(&mut self, now: u64) {
+        let lapsed: Vec<HoldToken> = self
+            .holds
+            .iter()
+            .filter(|hold| hold.expires_at <= now)
+            .map(|hold| hold.token)
+            .collect();
+        for token in lapsed {
+            self.release(token);
+        }
+    }
+    
+    /// An offer with the capacity of holds that lapsed by `now` given back,
+    /// for read-only paths that can't release them
+    fn offer_at<'a>(&self, offer: &'a LpOffer, now: u64) -> Cow<'a, LpOffer> {
+        let lapsed: u64 = self
+            .holds
+            .iter()
+            .filter(|hold| hold.expires_at <= now)
+            .filter(|hold| {
+                hold.offer_id == offer.offer_id && hold.lp_account_id == offer.lp_account_id
+            })
+            .map(|hold| hold.advance_amount)
+            .sum();
+        if lapsed == 0 {
+            return Cow::Borrowed(offer);
+        }
+        
+        let mut offer = offer.clone();
+        offer.remaining_amount += lapsed;
+        Cow::Owned(offer)
+    }
+    
+    /// Offer in the book with the given ID from the given LP
+    fn offer_mut(&mut self, offer_id: u64, lp_account_id: AccountId) -> Option<&mut LpOffer> {
+        self.offers
+            .iter_mut()
+            .find(|offer| offer.offer_id == offer_id && offer.lp_account_id == lp_account_id)
+    }
+    
+    /// APR `additional_amount` of new capital would earn against a flow of
+    /// requests, in basis points
+    /// Requests the current book can't fill are served highest APR first, each
+    /// at its APR cap (the default APR if uncapped). Capital left idle earns
+    /// nothing, so the more the book already covers the flow, the lower this is
+    /// The flow is matched against the book as it stands at `now`
+    pub fn marginal_apr_bps(
+        &self,
+        additional_amount: u64,
+        request_flow: &[UnlockRequest],
+        now: u64,
+    ) -> u64 {
+        if additional_amount == 0 {
+            return 0;
+        }
+        
+        let mut book = self.clone();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut unmet: Vec<(u64, u64)> = request_flow
+            .iter()
+            .filter(|request| book.match_request_mut((*request).clone(), now, &mut rng).is_none())
+            .map(|request| (request.max_apr_bps.unwrap_or(DEFAULT_APR_BPS), request.net_advance()))
+            .collect();
+        unmet.sort_unstable_by_key(|(apr_bps, _)| std::cmp::Reverse(*apr_bps));
+        
+        let mut remaining = additional_amount;
+        let mut weighted_apr: u128 = 0;
+        for (apr_bps, advance) in unmet {
+            let deployed = advance.min(remaining);
+            weighted_apr += apr_bps as u128 * deployed as u128;
+            remaining -= deployed;
+        }
+        (weighted_apr / additional_amount as u128) as u64
+    }
+    
+    /// Earliest time an offer could fund `amount`, counting capacity freed as
+    /// open deals settle at the end of their cooldown and as holds lapse
+    /// Returns `now` if an offer can fund it already, or `None` if no offer
+    /// ever could
+    pub fn time_to_liquidity(&self, amount: u64, now: u64) -> Option<u64> {
+        let needed = PricingCalculator::net_advance(amount);
+        
+        self.offers
+            .iter()
+            .filter(|offer| {
+                offer.is_active && amount >= offer.min_amount && amount <= offer.max_amount
+            })
+            .filter_map(|offer| {
+                let mut settlements: Vec<(u64, u64)> = self
+                    .open_deals
+                    .iter()
+                    .filter(|deal| {
+                        deal.offer.offer_id == offer.offer_id
+                            && deal.offer.lp_account_id == offer.lp_account_id
+                    })
+                    .map(|deal| (deal.request.cooldown_end_timestamp.max(now), deal.advance_amount))
+                    .chain(
+                        self.holds
+                            .iter()
+                            .filter(|hold| {
+                                hold.expires_at > now
+                                    && hold.offer_id == offer.offer_id
+                                    && hold.lp_account_id == offer.lp_account_id
+                            })
+                            .map(|hold| (hold.expires_at, hold.advance_amount)),
+                    )
+                    .collect();
+                settlements.sort_unstable();
+                
+                let mut available = self.offer_at(offer, now).remaining_amount;
+                if available >= needed {
+                    return Some(now);
+                }
+                settlements.into_iter().find_map(|(settles_at, freed)| {
+                    available += freed;
+                    (available >= needed).then_some(settles_at)
+                })
+            })
+            .min()
+    }
+}
+
+impl Default for MatchingEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether the advance fee plus interest at `apr_bps` over `days` stays
+/// below the principal
+fn is_economical(principal: u64, apr_bps: u64, days: u64) -> bool {
+    let interest = PricingCalculator::apr_interest_at(principal, apr_bps, days);
+    PricingCalculator::advance_fee(principal) + interest < principal
+}
+
+// ============================================================================
+// PRICING HELPERS
 // ============================================================================
-// PRICING HELPERS
-// ============================================================================
+
+/// Day-count convention for turning a period into a fraction of a year
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DayCount {
+    /// Actual days over a 365-day year, as the protocol charges
+    #[default]
+    Actual365,
+    /// Actual days over a 360-day year
+    Actual360,
+    /// 30-day months over a 360-day year (US 30/360)
+    Thirty360,
+}
 
 /// Calculate all pricing components for a deal
 pub struct PricingCalculator;
@@ -347,7 +1801,64 @@ impl PricingCalculator {
     
     /// Calculate APR interest
     pub fn apr_interest(principal: u64, days: u64) -> u64 {
-        (principal * DEFAULT_APR_BPS * days) / (10000 * 365)
+        Self::apr_interest_at(principal, DEFAULT_APR_BPS, days)
+    }
+    
+    /// Calculate interest at a specific APR
+    pub fn apr_interest_at(principal: u64, apr_bps: u64, days: u64) -> u64 {
+        (principal * apr_bps * days) / (10000 * 365)
+    }
+    
+    /// Interest at `apr_bps` between two Unix timestamps under a day-count
+    /// convention
+    /// Actual conventions count whole days, rounding a partial day up like
+    /// cooldowns do; 30/360 counts from the UTC calendar dates
+    pub fn apr_interest_with_convention(
+        principal: u64,
+        start: u64,
+        end: u64,
+        apr_bps: u64,
+        convention: DayCount,
+    ) -> u64 {
+        let actual_days = end.saturating_sub(start).div_ceil(24 * 60 * 60);
+        let (days, year_days) = match convention {
+            DayCount::Actual365 => (actual_days, 365),
+            DayCount::Actual360 => (actual_days, 360),
+            DayCount::Thirty360 => (thirty_360_days(start, end), 360),
+        };
+        (principal as u128 * apr_bps as u128 * days as u128 / (10000 * year_days)) as u64
+    }
+    
+    /// Total cost to the user of advancing `principal` over `days`
+    ///
+    /// Sums three components:
+    /// - advance fee charged up front
+    /// - borrow interest at the default APR
+    /// - staking rewards forgone on the locked collateral at `staking_apr_bps`
+    pub fn total_user_cost(principal: u64, days: u64, staking_apr_bps: u64) -> u64 {
+        let fee = Self::advance_fee(principal);
+        let interest = Self::apr_interest(principal, days);
+        let forgone_rewards = Self::apr_interest_at(principal, staking_apr_bps, days);
+        fee + interest + forgone_rewards
+    }
+    
+    /// How much borrowing against `principal` for `days` saves over selling
+    /// it now at `liquidation_discount_bps` below value
+    /// Positive when borrowing is cheaper, negative when selling is
+    pub fn savings_vs_liquidation(
+        principal: u64,
+        days: u64,
+        liquidation_discount_bps: u64,
+    ) -> i64 {
+        let borrow_cost = Self::advance_fee(principal) + Self::apr_interest(principal, days);
+        let liquidation_loss = (principal * liquidation_discount_bps) / 10000;
+        liquidation_loss as i64 - borrow_cost as i64
+    }
+    
+    /// LP earnings on a principal: fee share plus interest at `apr_bps`
+    pub fn lp_earnings(principal: u64, apr_bps: u64, days: u64) -> u64 {
+        Self::lp_fee_share(Self::advance_fee(principal))
+            + Self::apr_interest_at(principal, apr_bps, days)
     }
     
     /// Calculate LP share of fee
@@ -360,6 +1871,18 @@ impl PricingCalculator {
         (total_fee * PROTOCOL_FEE_BPS) / 10000
     }
     
+    /// Protocol share of a fee under a pool's configured split, rounded down
+    /// like the pool's `calculate_protocol_fee`
+    pub fn protocol_fee_share_at(total_fee: u64, protocol_bps: u64) -> u64 {
+        (total_fee as u128 * protocol_bps as u128 / 10000) as u64
+    }
+    
+    /// LP share of a fee under a pool's configured split: whatever the
+    /// protocol doesn't take, like the pool's `calculate_lp_fee`
+    pub fn lp_fee_share_at(total_fee: u64, protocol_bps: u64) -> u64 {
+        total_fee - Self::protocol_fee_share_at(total_fee, protocol_bps)
+    }
+    
     /// Convert USDC display amount to raw (6 decimals)
     pub fn usdc_to_raw(display: u64) -> u64 {
         display * ONE_USDC
@@ -371,6 +1894,82 @@ impl PricingCalculator {
     }
 }
 
+/// APR an LP should quote to win flow without giving away margin
+///
+/// Heuristic:
+/// - competitors at or below `MIN_MARGIN_APR_BPS` are ignored, since matching
+///   them wouldn't clear the LP's cost of capital
+/// - undercut the median of the remaining competitors by one basis point,
+///   or use the default APR if none remain
+/// - go no higher than the APR cap that still admits at least half of the
+///   historical request volume, so most of that flow stays fillable
+/// - never go below `MIN_MARGIN_APR_BPS`
+pub fn optimal_apr_bps(request_history: &[UnlockRequest], competitor_aprs: &[u64]) -> u64 {
+    let mut viable: Vec<u64> = competitor_aprs
+        .iter()
+        .copied()
+        .filter(|apr| *apr > MIN_MARGIN_APR_BPS)
+        .collect();
+    viable.sort_unstable();
+    let undercut = viable.get(viable.len() / 2).map_or(DEFAULT_APR_BPS, |median| median - 1);
+    
+    // Walk caps from the most permissive down until half the volume is admitted
+    let mut caps: Vec<(u64, u64)> = request_history
+        .iter()
+        .map(|request| (request.max_apr_bps.unwrap_or(u64::MAX), request.amount))
+        .collect();
+    caps.sort_unstable_by(|a, b| b.cmp(a));
+    let total_volume: u64 = caps.iter().map(|(_, amount)| amount).sum();
+    let mut admitted = 0;
+    let volume_cap = caps
+        .into_iter()
+        .find(|(_, amount)| {
+            admitted += amount;
+            admitted * 2 >= total_volume
+        })
+        .map_or(u64::MAX, |(cap, _)| cap);
+    
+    undercut.min(volume_cap).max(MIN_MARGIN_APR_BPS)
+}
+
+/// Days between two timestamps counting every month as 30 days (US 30/360)
+fn thirty_360_days(start: u64, end: u64) -> u64 {
+    if end <= start {
+        return 0;
+    }
+    let (y1, m1, d1) = civil_date(start);
+    let (y2, m2, d2) = civil_date(end);
+    let d1 = d1.min(30);
+    let d2 = if d1 == 30 { d2.min(30) } else { d2 };
+    let days = 360 * (y2 - y1) + 30 * (m2 - m1) + (d2 - d1);
+    days.max(0) as u64
+}
+
+/// UTC (year, month, day) of a Unix timestamp
+fn civil_date(timestamp: u64) -> (i64, i64, i64) {
+    // Days-to-civil conversion over 400-year eras, with years starting in March
+    let days = (timestamp / (24 * 60 * 60)) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Insurance fund reserve needed to cover expected losses on `outstanding`
+///
+/// Expected loss is the share expected to default (`expected_default_bps`)
+/// less what collateral recovers on those defaults (`recovery_bps`)
+pub fn insurance_fund_target(outstanding: u64, expected_default_bps: u64, recovery_bps: u64) -> u64 {
+    let loss_bps = 10000u64.saturating_sub(recovery_bps);
+    (outstanding as u128 * expected_default_bps as u128 * loss_bps as u128 / (10000 * 10000)) as u64
+}
+
 // ============================================================================
 // TIMESTAMP HELPERS
 // ============================================================================
@@ -393,6 +1992,62 @@ pub fn is_cooldown_ended(cooldown_end: u64) -> bool {
     current_timestamp() >= cooldown_end
 }
 
+// ============================================================================
+// ACCOUNT ID HELPERS
+// ============================================================================
+
+/// Split an account ID into its `[prefix, suffix]` felts
+/// This is how an account ID goes into commitments and storage words
+pub fn account_id_felts(account_id: AccountId) -> [Felt; 2] {
+    [account_id.prefix().as_felt(), account_id.suffix()]
+}
+
+/// Encode an account ID as the `[prefix, suffix, 0, 0]` word the contracts store
+pub fn account_id_word(account_id: AccountId) -> Word {
+    let [prefix, suffix] = account_id_felts(account_id);
+    Word::from([prefix, suffix, Felt::new(0), Felt::new(0)])
+}
+
+/// Decode an account ID from the `[prefix, suffix, 0, 0]` word the contracts store
+pub fn account_id_from_word(word: Word) -> Result<AccountId> {
+    AccountId::try_from([word[0], word[1]]).context("Stored word is not an account ID")
+}
+
+// ============================================================================
+// UNIT HELPERS
+// ============================================================================
+
+/// Format a raw amount with `decimals` fractional digits, e.g. `"1.500000"`
+pub fn format_units(raw: u64, decimals: u8) -> String {
+    if decimals == 0 {
+        return raw.to_string();
+    }
+    let scale = 10u64.pow(decimals as u32);
+    format!("{}.{:0width$}", raw / scale, raw % scale, width = decimals as usize)
+}
+
+/// Format a raw amount to whole cents, rounding half up, e.g. `"11.51"`
+pub fn format_display(raw: u64, decimals: u8) -> String {
+    let cents = if decimals <= 2 {
+        raw * 10u64.pow(2 - decimals as u32)
+    } else {
+        let scale = 10u64.pow(decimals as u32 - 2);
+        (raw + scale / 2) / scale
+    };
+    format!("{}.{:02}", cents / 100, cents % 100)
+}
+
+/// Convert a `Felt` amount read from a note or contract into a `u64`
+///
+/// Every canonical field element fits in a `u64`, but an amount the contracts
+/// subtracted below zero wraps to the top of the field and would otherwise
+/// read back as a huge balance. Values in the upper half of the field are
+/// treated as wrapped, matching the pool's own invariant check.
+pub fn felt_to_u64_checked(f: Felt) -> Option<u64> {
+    let value = f.as_int();
+    (value < 1 << 63).then_some(value)
+}
+
 // ============================================================================
 // NOTE CREATION HELPERS
 // ============================================================================
@@ -412,6 +2067,48 @@ pub fn settlement_note_config(
     }
 }
 
+/// Create settlement note configuration carrying the deal's staked collateral
+///
+/// The attached asset must come from the faucet recorded on the request and
+/// cover exactly the locked amount
+pub fn settlement_note_config_with_assets(
+    deal: &MatchedDeal,
+    staked_asset: FungibleAsset,
+) -> Result<NoteCreationConfig> {
+    let collateral_faucet_id = deal
+        .request
+        .staked_asset_faucet_id
+        .context("Deal has no recorded collateral type")?;
+    ensure!(
+        staked_asset.faucet_id() == collateral_faucet_id,
+        "Staked asset faucet {} does not match deal collateral {}",
+        staked_asset.faucet_id(),
+        collateral_faucet_id
+    );
+    ensure!(
+        staked_asset.amount() == deal.request.amount,
+        "Staked asset amount {} does not match locked amount {}",
+        staked_asset.amount(),
+        deal.request.amount
+    );
+    
+    let assets = NoteAssets::new(vec![staked_asset.into()])
+        .context("Failed to create settlement note assets")?;
+    
+    Ok(NoteCreationConfig {
+        assets,
+        ..settlement_note_config(
+            Felt::new(deal.request.request_id),
+            Felt::new(deal.request.amount),
+            Felt::new(deal.request.cooldown_end_timestamp),
+            deal.deal_id[0],
+        )
+    })
+}
+
+/// Position of the user commitment among the advance note inputs
+pub const ADVANCE_NOTE_USER_COMMITMENT_INPUT: usize = 3;
+
 /// Create advance note configuration
 pub fn advance_note_config(
     advance_amount: Felt,
@@ -427,6 +2124,1233 @@ pub fn advance_note_config(
     }
 }
 
+/// Create match note configuration, for the LP pool to accept `deal` with
+/// repayment in `settlement_asset_id`
+pub fn match_note_config(deal: &MatchedDeal, settlement_asset_id: AccountId) -> NoteCreationConfig {
+    let mut inputs = vec![Felt::new(deal.offer.offer_id)];
+    inputs.extend(deal.request.commitment.as_elements());
+    inputs.extend([Felt::new(deal.advance_amount), Felt::new(deal.request.cooldown_end_timestamp)]);
+    inputs.extend(account_id_word(settlement_asset_id).as_elements());
+    
+    NoteCreationConfig {
+        note_type: NoteType::Private,
+        tag: NoteTag::new(3),
+        assets: NoteAssets::default(),
+        inputs,
+    }
+}
+
+/// Create cancel match note configuration, for the LP pool to release a
+/// deal whose advance was never delivered
+pub fn cancel_match_note_config(pool_deal_id: Felt) -> NoteCreationConfig {
+    NoteCreationConfig {
+        note_type: NoteType::Private,
+        tag: NoteTag::new(4),
+        assets: NoteAssets::default(),
+        inputs: vec![pool_deal_id],
+    }
+}
+
+/// Commitment an advance note for this deal is bound to
+///
+/// The note carries the first element as its `user_commitment` input, so a
+/// wallet can check an incoming advance note against its own request before
+/// paying to consume it
+pub fn expected_advance_recipient(deal: &MatchedDeal) -> Word {
+    deal.request.commitment
+}
+
+/// Which Voile note script a configuration is for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VoileNoteKind {
+    /// LP pool to user, carrying the USDC advance
+    Advance,
+    /// User to LP pool, carrying the staked collateral
+    Settlement,
+}
+
+/// Check a note configuration's inputs against the deal it's for before
+/// publishing, instead of finding a mismatch when the note executes
+///
+/// The advance note's deal ID input is the one the pool assigned in
+/// `accept_match`, which the off-chain deal doesn't know, so it isn't checked
+pub fn check_note_matches_deal(
+    config: &NoteCreationConfig,
+    deal: &MatchedDeal,
+    kind: VoileNoteKind,
+) -> Result<()> {
+    let expected: [(&str, Option<Felt>); 4] = match kind {
+        VoileNoteKind::Advance => [
+            ("advance amount", Some(Felt::new(deal.advance_amount))),
+            ("deal id", None),
+            ("offer id", Some(Felt::new(deal.offer.offer_id))),
+            ("user commitment", Some(deal.request.commitment[0])),
+        ],
+        VoileNoteKind::Settlement => [
+            ("request id", Some(Felt::new(deal.request.request_id))),
+            ("amount", Some(Felt::new(deal.request.amount))),
+            ("cooldown end", Some(Felt::new(deal.request.cooldown_end_timestamp))),
+            ("deal id", Some(deal.deal_id[0])),
+        ],
+    };
+    ensure!(
+        config.inputs.len() == expected.len(),
+        "{kind:?} note has {} inputs, expected {}",
+        config.inputs.len(),
+        expected.len()
+    );
+    
+    for (index, (input, (field, expected))) in config.inputs.iter().zip(expected).enumerate() {
+        if let Some(expected) = expected {
+            ensure!(
+                *input == expected,
+                "{kind:?} note input {index} ({field}) is {input}, but the deal has {expected}"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Why consuming a settlement note failed, recognised from the check that
+/// aborted it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NoteFailure {
+    /// Settlement attempted before the cooldown ended
+    CooldownNotEnded,
+    /// Settlement attempted from the settlement deadline on
+    DeadlinePassed,
+    /// The request isn't matched, or is already settled
+    SettlementNotAuthorized,
+    /// The note's cooldown end isn't the one committed with the request
+    CommitmentMismatch,
+    /// Any other failure, with the original message
+    Other(String),
+}
+
+impl NoteFailure {
+    /// Whether the same note can succeed if consumed again later
+    /// Only an early settlement resolves itself; the rest need a new note
+    pub fn is_retryable(&self) -> bool {
+        *self == NoteFailure::CooldownNotEnded
+    }
+}
+
+/// How the VM reports a failed `assert` in a contract or note script
+const VM_ASSERTION_FAILED: &str = "assertion failed";
+
+/// Map a settlement note consumption error to the check that caused it
+///
+/// The VM reports only that an assertion failed, not which one, so the
+/// check is found the way `authorize_settlement` runs them: against
+/// `request` as the user account stores it, the note's `note_cooldown_end`
+/// and the time `now` the note was consumed at. Matches on the whole error
+/// chain, so context added by callers doesn't hide the VM's report
+pub fn classify_note_error(
+    err: &anyhow::Error,
+    request: &RequestView,
+    note_cooldown_end: u64,
+    now: u64,
+) -> NoteFailure {
+    let message = format!("{err:#}");
+    if !message.contains(VM_ASSERTION_FAILED) {
+        return NoteFailure::Other(message);
+    }
+    
+    let stored_cooldown_end = request.cooldown_end.as_int();
+    if !request.is_matched || request.is_settled {
+        NoteFailure::SettlementNotAuthorized
+    } else if note_cooldown_end != stored_cooldown_end {
+        NoteFailure::CommitmentMismatch
+    } else if now < stored_cooldown_end {
+        NoteFailure::CooldownNotEnded
+    } else if now >= stored_cooldown_end + SETTLEMENT_GRACE_SECONDS {
+        NoteFailure::DeadlinePassed
+    } else {
+        NoteFailure::Other(message)
+    }
+}
+
+// ============================================================================
+// TRANSACTION HELPERS
+// ============================================================================
+
+/// Pre-flight checks on a transaction request before `account_id` submits it
+/// Catches malformed requests locally instead of as an opaque rejection from
+/// the node: the request must consume or create at least one note, and every
+/// note it expects to create must be sent by the submitting account
+pub fn validate_transaction_request(
+    account_id: AccountId,
+    request: &TransactionRequest,
+) -> Result<()> {
+    let output_notes = request.expected_output_own_notes();
+    ensure!(
+        request.input_note_ids().next().is_some() || !output_notes.is_empty(),
+        "Transaction request has no input or output notes"
+    );
+    
+    for note in &output_notes {
+        let sender = note.metadata().sender();
+        ensure!(
+            sender == account_id,
+            "Output note {} is sent by {}, not the submitting account {}",
+            note.id(),
+            sender,
+            account_id
+        );
+    }
+    Ok(())
+}
+
+// ============================================================================
+// MATCH EXECUTION
+// ============================================================================
+
+/// Receipt for a match accepted by the pool with its advance note published
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MatchReceipt {
+    /// Off-chain deal ID
+    pub deal_id: Word,
+    /// Deal ID assigned by the pool's `accept_match`
+    pub pool_deal_id: Felt,
+    /// User receiving the advance
+    pub user_account_id: AccountId,
+    /// LP pool funding the advance
+    pub pool_account_id: AccountId,
+    /// Net USDC advanced
+    pub advance_amount: u64,
+    /// Hash of the published advance note
+    pub advance_note_hash: Word,
+}
+
+/// On-chain steps of a match
+/// Implemented over a Miden client by `ClientMatchBackend`
+pub trait MatchBackend {
+    /// Call `accept_match` on the pool, returning the pool's deal ID
+    fn accept_match(
+        &mut self,
+        pool_account_id: AccountId,
+        deal: &MatchedDeal,
+    ) -> impl Future<Output = Result<Felt>>;
+    
+    /// Publish the advance note from the pool to the user, returning its hash
+    fn publish_advance_note(
+        &mut self,
+        pool_account_id: AccountId,
+        user_account_id: AccountId,
+        config: NoteCreationConfig,
+    ) -> impl Future<Output = Result<Word>>;
+    
+    /// Call `cancel_match` on the pool to release an accepted match
+    fn cancel_match(
+        &mut self,
+        pool_account_id: AccountId,
+        pool_deal_id: Felt,
+    ) -> impl Future<Output = Result<()>>;
+}
+
+/// Accept a match on the pool and publish its advance note as one flow
+///
+/// If the advance note can't be published the match is cancelled so the
+/// pool's USDC isn't left locked against a deal the user never receives
+pub async fn execute_match(
+    backend: &mut impl MatchBackend,
+    user_account_id: AccountId,
+    pool_account_id: AccountId,
+    deal: &MatchedDeal,
+) -> Result<MatchReceipt> {
+    let pool_deal_id = backend
+        .accept_match(pool_account_id, deal)
+        .await
+        .context("Failed to accept match on LP pool")?;
+    
+    let config = advance_note_config(
+        Felt::new(deal.advance_amount),
+        pool_deal_id,
+        Felt::new(deal.offer.offer_id),
+        deal.request.commitment[0],
+    );
+    check_note_matches_deal(&config, deal, VoileNoteKind::Advance)?;
+    
+    let advance_note_hash = match backend
+        .publish_advance_note(pool_account_id, user_account_id, config)
+        .await
+    {
+        Ok(hash) => hash,
+        Err(publish_err) => {
+            backend
+                .cancel_match(pool_account_id, pool_deal_id)
+                .await
+                .context("Failed to cancel match after advance note publication failed")?;
+            return Err(publish_err.context("Failed to publish advance note; match cancelled"));
+        }
+    };
+    
+    Ok(MatchReceipt {
+        deal_id: deal.deal_id,
+        pool_deal_id,
+        user_account_id,
+        pool_account_id,
+        advance_amount: deal.advance_amount,
+        advance_note_hash,
+    })
+}
+
+/// Compiled note scripts a client submits to run a match
+#[derive(Clone)]
+pub struct MatchNoteScripts {
+    /// `match-note`, consumed by the pool to accept a match
+    pub match_note: NoteScript,
+    /// `cancel-match-note`, consumed by the pool to cancel one
+    pub cancel_match_note: NoteScript,
+    /// `advance-note`, sent from the pool to the user
+    pub advance_note: NoteScript,
+}
+
+/// Runs a match's transactions on the LP pool through a Miden client
+/// The client must hold the pool account and its keys
+pub struct ClientMatchBackend<'a> {
+    client: &'a mut Client<FilesystemKeyStore>,
+    scripts: MatchNoteScripts,
+    settlement_asset_id: AccountId,
+}
+
+impl<'a> ClientMatchBackend<'a> {
+    /// Backend whose deals are repaid in assets from `settlement_asset_id`
+    pub fn new(
+        client: &'a mut Client<FilesystemKeyStore>,
+        scripts: MatchNoteScripts,
+        settlement_asset_id: AccountId,
+    ) -> Self {
+        Self { client, scripts, settlement_asset_id }
+    }
+    
+    /// Submit `request` as a pool transaction
+    async fn submit(
+        &mut self,
+        pool_account_id: AccountId,
+        request: TransactionRequest,
+    ) -> Result<()> {
+        validate_transaction_request(pool_account_id, &request)?;
+        self.client
+            .submit_new_transaction(pool_account_id, request)
+            .await
+            .context("Failed to submit pool transaction")?;
+        Ok(())
+    }
+    
+    /// Have the pool consume a note built from `script`
+    async fn consume_on_pool(
+        &mut self,
+        pool_account_id: AccountId,
+        script: NoteScript,
+        config: NoteCreationConfig,
+    ) -> Result<()> {
+        let note = create_note_from_library(self.client, script, pool_account_id, config)?;
+        let request = TransactionRequestBuilder::new()
+            .unauthenticated_input_notes([(note, None)])
+            .build()
+            .context("Failed to build pool transaction")?;
+        self.submit(pool_account_id, request).await
+    }
+}
+
+impl MatchBackend for ClientMatchBackend<'_> {
+    async fn accept_match(
+        &mut self,
+        pool_account_id: AccountId,
+        deal: &MatchedDeal,
+    ) -> Result<Felt> {
+        // The match note aborts unless the deal gets the pool's next ID
+        let mut pool = synced_account(self.client, pool_account_id).await?;
+        let pool_deal_id = pool.deal_count(pool_account_id).await?;
+        
+        let config = match_note_config(deal, self.settlement_asset_id);
+        let script = self.scripts.match_note.clone();
+        self.consume_on_pool(pool_account_id, script, config).await?;
+        Ok(Felt::new(pool_deal_id))
+    }
+    
+    /// The note is private, so the user receives it from the pool operator
+    async fn publish_advance_note(
+        &mut self,
+        pool_account_id: AccountId,
+        _user_account_id: AccountId,
+        config: NoteCreationConfig,
+    ) -> Result<Word> {
+        let script = self.scripts.advance_note.clone();
+        let note = create_note_from_library(self.client, script, pool_account_id, config)?;
+        let request = TransactionRequestBuilder::new()
+            .own_output_notes(vec![OutputNote::Full(note.clone())])
+            .build()
+            .context("Failed to build advance note transaction")?;
+        self.submit(pool_account_id, request).await?;
+        Ok(note.id().as_word())
+    }
+    
+    async fn cancel_match(&mut self, pool_account_id: AccountId, pool_deal_id: Felt) -> Result<()> {
+        let script = self.scripts.cancel_match_note.clone();
+        let config = cancel_match_note_config(pool_deal_id);
+        self.consume_on_pool(pool_account_id, script, config).await
+    }
+}
+
+// ============================================================================
+// MATCH COORDINATION
+// ============================================================================
+
+/// Requests currently being matched, shared by every matcher in a process
+/// Stops one unlock request from being matched with two LPs before either
+/// deal is recorded on-chain
+#[derive(Clone, Debug)]
+pub struct MatchLocks {
+    /// Locked request commitments and when each lock lapses
+    held: Arc<Mutex<Vec<(Word, u64)>>>,
+    /// How long a lock is held if its guard is never released
+    timeout_seconds: u64,
+}
+
+impl MatchLocks {
+    /// Create an empty lock registry
+    pub fn new(timeout_seconds: u64) -> Self {
+        Self {
+            held: Arc::default(),
+            timeout_seconds,
+        }
+    }
+    
+    /// Check whether a request is locked at `now`
+    pub fn is_locked(&self, request_commitment: Word, now: u64) -> bool {
+        self.held
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(commitment, expires_at)| *commitment == request_commitment && *expires_at > now)
+    }
+}
+
+/// Lock on a request for the duration of its match
+/// Released when dropped, e.g. once the deal is finalized
+#[derive(Debug)]
+pub struct MatchGuard {
+    locks: MatchLocks,
+    request_commitment: Word,
+    expires_at: u64,
+}
+
+impl MatchGuard {
+    /// Commitment of the locked request
+    pub fn request_commitment(&self) -> Word {
+        self.request_commitment
+    }
+}
+
+impl Drop for MatchGuard {
+    fn drop(&mut self) {
+        self.locks
+            .held
+            .lock()
+            .unwrap()
+            .retain(|lock| *lock != (self.request_commitment, self.expires_at));
+    }
+}
+
+/// Lock a request for matching
+/// Fails while another match holds an unexpired lock on the same request
+pub fn lock_request_for_matching(
+    locks: &MatchLocks,
+    request_commitment: Word,
+    now: u64,
+) -> Result<MatchGuard> {
+    let mut held = locks.held.lock().unwrap();
+    held.retain(|(_, expires_at)| *expires_at > now);
+    if held.iter().any(|(commitment, _)| *commitment == request_commitment) {
+        bail!("Request is already being matched");
+    }
+    let expires_at = now + locks.timeout_seconds;
+    held.push((request_commitment, expires_at));
+    drop(held);
+    
+    Ok(MatchGuard {
+        locks: locks.clone(),
+        request_commitment,
+        expires_at,
+    })
+}
+
+// ============================================================================
+// DEAL LEDGER
+// ============================================================================
+
+/// A deal as stored in the LP pool's `matched_deals` map, read in one query
+/// with each field named rather than by position
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DealView {
+    /// First element of the user's request commitment
+    pub user_commitment: Felt,
+    /// Net USDC advanced
+    pub advance_amount: Felt,
+    /// Offer the deal was matched from
+    pub offer_id: Felt,
+    /// APR the deal accrues interest at (`get_deal_apr_bps`)
+    pub apr_bps: Felt,
+    /// When the deal was matched (`get_deal_matched_at`)
+    pub matched_at: Felt,
+    /// When the user's cooldown ends (`get_deal_cooldown_end`)
+    pub cooldown_end: Felt,
+    /// Settled flag
+    pub is_settled: bool,
+    /// When the deal settled, 0 until then (`get_deal_settled_at`)
+    pub settled_at: Felt,
+    /// LP earnings recorded at settlement, 0 until then (`get_deal_earned`)
+    pub earned: Felt,
+    /// Defaulted flag
+    pub is_defaulted: bool,
+}
+
+/// Read access to an LP pool's deals
+/// Implemented for a synced client and for an `Account` read directly
+pub trait LedgerBackend {
+    /// Number of deals the pool has created (`get_deal_counter`)
+    fn deal_count(&mut self, pool_account_id: AccountId) -> impl Future<Output = Result<u64>>;
+    
+    /// Read one deal's stored fields
+    fn deal(
+        &mut self,
+        pool_account_id: AccountId,
+        deal_id: u64,
+    ) -> impl Future<Output = Result<DealView>>;
+    
+    /// Read what a settled deal's settlement recorded
+    fn settlement(
+        &mut self,
+        pool_account_id: AccountId,
+        deal_id: u64,
+    ) -> impl Future<Output = Result<SettlementView>>;
+}
+
+/// Amounts the LP pool records when a deal settles
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SettlementView {
+    /// Staked assets delivered (`get_deal_staked_received`)
+    pub staked_received: Felt,
+    /// LP fee share plus interest (`get_deal_earned`)
+    pub earned: Felt,
+    /// Interest part of the earnings (`get_deal_interest`)
+    pub interest: Felt,
+}
+
+/// One settled deal's line in an LP statement
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SettledDeal {
+    /// Pool deal ID
+    pub deal_id: u64,
+    /// Staked assets the pool received
+    pub staked_received: u64,
+    /// LP share of the advance fee
+    pub lp_fee: u64,
+    /// Interest earned
+    pub interest: u64,
+}
+
+/// Lifecycle state of a deal in the ledger export
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DealStatus {
+    /// Advanced and awaiting settlement
+    Open,
+    /// Collateral delivered to the pool
+    Settled,
+    /// Written off
+    Defaulted,
+}
+
+/// One row of the deal ledger export
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    /// Pool deal ID
+    pub deal_id: u64,
+    /// Offer the deal was matched from
+    pub offer_id: u64,
+    /// First element of the user's request commitment
+    pub user_commitment: u64,
+    /// Net USDC advanced
+    pub advance_amount: u64,
+    /// Advance fee implied by the advance at the default fee rate
+    pub advance_fee: u64,
+    /// APR the deal accrues interest at, in basis points
+    pub apr_bps: u64,
+    /// When the deal was matched
+    pub matched_at: u64,
+    /// When the user's cooldown ends
+    pub cooldown_end: u64,
+    /// When the deal settled, if it has
+    pub settled_at: Option<u64>,
+    /// LP fee share plus interest as the pool recorded them at settlement;
+    /// 0 until the deal settles
+    pub lp_earnings: u64,
+    /// Lifecycle state
+    pub status: DealStatus,
+}
+
+impl LedgerEntry {
+    fn from_deal_view(deal_id: u64, deal: &DealView) -> Result<Self> {
+        let amount = |felt: Felt, field: &str| {
+            felt_to_u64_checked(felt)
+                .with_context(|| format!("Deal {deal_id} {field} is out of range"))
+        };
+        let advance_amount = amount(deal.advance_amount, "advance amount")?;
+        
+        // advance = principal - fee, so fee = advance * fee_bps / (10000 - fee_bps)
+        let advance_fee =
+            advance_amount * DEFAULT_ADVANCE_FEE_BPS / (10000 - DEFAULT_ADVANCE_FEE_BPS);
+        let status = if deal.is_defaulted {
+            DealStatus::Defaulted
+        } else if deal.is_settled {
+            DealStatus::Settled
+        } else {
+            DealStatus::Open
+        };
+        let settled_at = if status == DealStatus::Settled {
+            Some(amount(deal.settled_at, "settlement time")?)
+        } else {
+            None
+        };
+        
+        Ok(Self {
+            deal_id,
+            offer_id: amount(deal.offer_id, "offer id")?,
+            user_commitment: deal.user_commitment.as_int(),
+            advance_amount,
+            advance_fee,
+            apr_bps: amount(deal.apr_bps, "APR")?,
+            matched_at: amount(deal.matched_at, "match time")?,
+            cooldown_end: amount(deal.cooldown_end, "cooldown end")?,
+            settled_at,
+            lp_earnings: amount(deal.earned, "earnings")?,
+            status,
+        })
+    }
+}
+
+/// Dump every deal in an LP pool as a JSON array for audit, read through a
+/// synced client or from the pool account itself
+/// An empty pool exports as `[]`
+pub async fn export_ledger(
+    backend: &mut impl LedgerBackend,
+    pool_account_id: AccountId,
+) -> Result<String> {
+    let deal_count = backend
+        .deal_count(pool_account_id)
+        .await
+        .context("Failed to read pool deal counter")?;
+    
+    let mut entries = Vec::new();
+    for deal_id in 0..deal_count {
+        let deal = backend
+            .deal(pool_account_id, deal_id)
+            .await
+            .with_context(|| format!("Failed to read deal {deal_id}"))?;
+        entries.push(LedgerEntry::from_deal_view(deal_id, &deal)?);
+    }
+    
+    serde_json::to_string(&entries).context("Failed to serialize deal ledger")
+}
+
+/// Every settled deal in an LP pool, in deal order, for building a statement,
+/// read through a synced client or from the pool account itself
+/// Open and defaulted deals are skipped
+pub async fn settled_deals(
+    backend: &mut impl LedgerBackend,
+    pool_account_id: AccountId,
+) -> Result<Vec<SettledDeal>> {
+    let deal_count = backend
+        .deal_count(pool_account_id)
+        .await
+        .context("Failed to read pool deal counter")?;
+    
+    let mut settled = Vec::new();
+    for deal_id in 0..deal_count {
+        let deal = backend
+            .deal(pool_account_id, deal_id)
+            .await
+            .with_context(|| format!("Failed to read deal {deal_id}"))?;
+        if !deal.is_settled {
+            continue;
+        }
+        
+        let settlement = backend
+            .settlement(pool_account_id, deal_id)
+            .await
+            .with_context(|| format!("Failed to read settlement of deal {deal_id}"))?;
+        let amount = |felt: Felt, field: &str| {
+            felt_to_u64_checked(felt)
+                .with_context(|| format!("Deal {deal_id} {field} is out of range"))
+        };
+        let earned = amount(settlement.earned, "earnings")?;
+        let interest = amount(settlement.interest, "interest")?;
+        let lp_fee = earned
+            .checked_sub(interest)
+            .with_context(|| format!("Deal {deal_id} interest exceeds its earnings"))?;
+        
+        settled.push(SettledDeal {
+            deal_id,
+            staked_received: amount(settlement.staked_received, "staked received")?,
+            lp_fee,
+            interest,
+        });
+    }
+    Ok(settled)
+}
+
+/// One earnings credit from the pool's event log, for reconciling off-chain
+/// accounting against every settlement
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EarningsEvent {
+    /// Pool deal ID that settled
+    pub deal_id: u64,
+    /// LP share of the advance fee credited
+    pub fee_credited: u64,
+    /// Interest credited
+    pub interest_credited: u64,
+    /// Pool's total earned right after the credit
+    pub total_earned: u64,
+}
+
+/// Decode an event logged by `record_settlement`, as read with
+/// `get_earnings_event`
+pub fn decode_earnings_event(event: Word) -> Result<EarningsEvent> {
+    let field = |index: usize, name: &str| {
+        felt_to_u64_checked(event[index])
+            .with_context(|| format!("Earnings event {name} is out of range"))
+    };
+    Ok(EarningsEvent {
+        deal_id: field(0, "deal id")?,
+        fee_credited: field(1, "fee")?,
+        interest_credited: field(2, "interest")?,
+        total_earned: field(3, "total earned")?,
+    })
+}
+
+// ============================================================================
+// SETTLEMENT SIMULATION
+// ============================================================================
+
+/// Group unsettled deals whose cooldown has ended by `now` into batches of
+/// at most `max_per_note`, the most a multi-deal settlement note can carry
+/// Deals keep their input order, so every batch but the last is full
+pub fn plan_settlement_batches(
+    deals: &[MatchedDeal],
+    max_per_note: usize,
+    now: u64,
+) -> Vec<Vec<DealId>> {
+    let due: Vec<DealId> = deals
+        .iter()
+        .filter(|deal| !deal.is_settled && now >= deal.request.cooldown_end_timestamp)
+        .map(|deal| deal.deal_id)
+        .collect();
+    
+    due.chunks(max_per_note.max(1)).map(<[DealId]>::to_vec).collect()
+}
+
+/// State changes a deal's settlement produces, computed off-chain
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SettlementOutcome {
+    /// Staked assets credited to the pool balance: the funded principal
+    pub pool_balance_delta: u64,
+    /// Advance no longer outstanding on the pool
+    pub outstanding_released: u64,
+    /// LP share of the advance fee added to the pool's total earned
+    pub lp_fee: u64,
+    /// Interest accrued at the offer's APR over the actual cooldown, capped
+    /// at what the deal's agreed cooldown allows
+    pub lp_interest: u64,
+    /// Protocol share of the advance fee
+    pub protocol_fee: u64,
+    /// Staked assets leaving the user's locked balance
+    pub user_locked_released: u64,
+}
+
+/// Compute what `record_settlement` and the user's `mark_settled` would do
+/// for a deal, so integrators can check figures before submitting
+/// `protocol_fee_bps` is the settling pool's `get_fee_split`
+/// A partial fill settles only its funded principal
+pub fn simulate_settlement(
+    deal: &MatchedDeal,
+    actual_cooldown_seconds: u64,
+    protocol_fee_bps: u64,
+) -> SettlementOutcome {
+    let fee = deal.advance_fee();
+    let days = actual_cooldown_seconds.div_ceil(24 * 60 * 60);
+    let interest = PricingCalculator::apr_interest_at(deal.principal, deal.offer.apr_bps(), days);
+    
+    SettlementOutcome {
+        pool_balance_delta: deal.principal,
+        outstanding_released: deal.advance_amount,
+        lp_fee: PricingCalculator::lp_fee_share_at(fee, protocol_fee_bps),
+        lp_interest: interest.min(max_deal_interest(deal)),
+        protocol_fee: PricingCalculator::protocol_fee_share_at(fee, protocol_fee_bps),
+        user_locked_released: deal.principal,
+    }
+}
+
+/// Most interest `record_settlement` accepts for a deal, as the pool's
+/// `get_max_deal_interest` computes it: the offer's APR from the match to the
+/// cooldown end, in whole days, on the advance grossed back up by the 5% fee
+fn max_deal_interest(deal: &MatchedDeal) -> u64 {
+    let principal = u128::from(deal.advance_amount) * 20 / 19;
+    let agreed_seconds = deal.request.cooldown_end_timestamp.saturating_sub(deal.matched_at);
+    let days = u128::from(agreed_seconds.div_ceil(24 * 60 * 60));
+    let interest = principal * u128::from(deal.offer.apr_bps()) * days / (10000 * 365);
+    u64::try_from(interest).unwrap_or(u64::MAX)
+}
+
+// ============================================================================
+// STRESS TESTING
+// ============================================================================
+
+/// One step of a liquidity stress scenario
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StressEvent {
+    /// A user asks for an advance on `amount` of staked assets
+    Request { amount: u64 },
+    /// The `deal`th filled deal defaults and its advance is lost
+    Default { deal: usize },
+}
+
+/// Sequence of requests and defaults to replay against an offer book
+#[derive(Clone, Debug)]
+pub struct StressScenario {
+    /// Account the scripted requests are made from
+    pub user_account_id: AccountId,
+    /// Time the scenario plays out at, which every request's cooldown runs from
+    pub start_timestamp: u64,
+    /// Cooldown every request is made with
+    pub cooldown_days: u64,
+    /// Events in the order they happen
+    pub events: Vec<StressEvent>,
+}
+
+/// How an offer book held up under a stress scenario
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StressResult {
+    /// Requests made
+    pub requests: u64,
+    /// Requests the book matched
+    pub filled: u64,
+    /// Share of requests matched, in basis points
+    pub fill_rate_bps: u64,
+    /// Highest share of starting capital out on live deals, in basis points
+    pub peak_utilization_bps: u64,
+    /// Advances lost to defaults
+    pub losses: u64,
+    /// Starting capital plus LP earnings on deals that settle, less losses
+    pub ending_capital: u64,
+}
+
+/// Replay a stress scenario against a copy of the engine's offer book
+/// Starting capital is the book's remaining capacity. Deals that don't
+/// default are assumed to settle in full at the end of their cooldown, and
+/// a default naming a deal that doesn't exist or already defaulted is ignored
+/// Request secrets and deal IDs come from a fixed seed and the scenario
+/// carries its own start time, so a scenario always replays the same way
+pub fn stress_test(engine: &MatchingEngine, scenario: &StressScenario) -> StressResult {
+    let mut engine = engine.clone();
+    let mut rng = StdRng::seed_from_u64(0);
+    let starting_capital: u64 = engine
+        .offers
+        .iter()
+        .filter(|offer| offer.is_active)
+        .map(|offer| offer.remaining_amount)
+        .sum();
+    let now = scenario.start_timestamp;
+    let cooldown_end = now + scenario.cooldown_days * 24 * 60 * 60;
+    
+    let mut deals: Vec<(MatchedDeal, bool)> = Vec::new();
+    let mut requests = 0;
+    let mut outstanding = 0;
+    let mut peak_utilization_bps = 0;
+    let mut losses = 0;
+    
+    for event in &scenario.events {
+        match *event {
+            StressEvent::Request { amount } => {
+                let request = UnlockRequest::new(
+                    requests,
+                    amount,
+                    cooldown_end,
+                    scenario.user_account_id,
+                    &mut rng,
+                );
+                requests += 1;
+                if let Some(deal) = engine.match_request_mut(request, now, &mut rng) {
+                    outstanding += deal.advance_amount;
+                    deals.push((deal, false));
+                }
+            }
+            StressEvent::Default { deal } => {
+                if let Some((deal, defaulted @ false)) = deals.get_mut(deal) {
+                    *defaulted = true;
+                    outstanding -= deal.advance_amount;
+                    losses += deal.advance_amount;
+                }
+            }
+        }
+        
+        if starting_capital > 0 {
+            let utilization_bps = outstanding * 10000 / starting_capital;
+            peak_utilization_bps = peak_utilization_bps.max(utilization_bps);
+        }
+    }
+    
+    let earnings: u64 = deals
+        .iter()
+        .filter(|(_, defaulted)| !defaulted)
+        .map(|(deal, _)| {
+            let lp_fee = PricingCalculator::lp_fee_share(deal.advance_fee());
+            lp_fee
+                + PricingCalculator::apr_interest_at(
+                    deal.principal,
+                    deal.offer.apr_bps(),
+                    scenario.cooldown_days,
+                )
+        })
+        .sum();
+    let filled = deals.len() as u64;
+    
+    StressResult {
+        requests,
+        filled,
+        fill_rate_bps: if requests == 0 { 0 } else { filled * 10000 / requests },
+        peak_utilization_bps,
+        losses,
+        ending_capital: (starting_capital + earnings).saturating_sub(losses),
+    }
+}
+
+// ============================================================================
+// LIQUIDITY PLANNING
+// ============================================================================
+
+/// Snapshot of a pool's liquidity at a point in time
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolHealth {
+    /// USDC available to advance (`get_usdc_balance`)
+    pub available: u64,
+    /// USDC out on open deals (`get_outstanding`)
+    pub outstanding: u64,
+    /// When the snapshot was taken
+    pub as_of: u64,
+}
+
+/// When the pool is projected to run out of USDC to advance
+///
+/// `schedule` lists open deals' settlements as `(timestamp, amount)`, each
+/// returning to the available balance when due. Each day from `as_of` the
+/// pool advances `expected_outflow_per_day`, and those new advances come back
+/// after the default cooldown. Returns the first day the pool can't cover
+/// that day's outflow, or `None` if it always can.
+pub fn liquidity_runway(
+    pool: &PoolHealth,
+    schedule: &[(u64, u64)],
+    expected_outflow_per_day: u64,
+) -> Option<u64> {
+    let day = 24 * 60 * 60;
+    let cooldown_days = DEFAULT_COOLDOWN_SECONDS.div_ceil(day);
+    
+    let mut inflows = schedule.to_vec();
+    inflows.sort_unstable();
+    let last_inflow = inflows.last().map_or(pool.as_of, |(at, _)| *at);
+    // Once every settlement is in and the first new advances come back,
+    // each day's returns match its outflow and the balance stops moving
+    let horizon = last_inflow.saturating_sub(pool.as_of).div_ceil(day) + cooldown_days;
+    
+    let mut available = pool.available;
+    let mut next_inflow = 0;
+    for elapsed_days in 0..=horizon {
+        let now = pool.as_of + elapsed_days * day;
+        while let Some((_, amount)) = inflows.get(next_inflow).filter(|(at, _)| *at <= now) {
+            available += amount;
+            next_inflow += 1;
+        }
+        if elapsed_days >= cooldown_days {
+            available += expected_outflow_per_day;
+        }
+        
+        if available < expected_outflow_per_day {
+            return Some(now);
+        }
+        available -= expected_outflow_per_day;
+    }
+    None
+}
+
+// ============================================================================
+// ACCOUNT STORAGE BACKENDS
+// ============================================================================
+
+/// LP pool `balances` map
+const POOL_BALANCES_SLOT: u8 = 0;
+
+/// LP pool `active_offers` map
+const POOL_OFFERS_SLOT: u8 = 1;
+
+/// LP pool `matched_deals` map
+const POOL_DEALS_SLOT: u8 = 2;
+
+/// User account `unlock_requests` map
+const USER_REQUESTS_SLOT: u8 = 0;
+
+/// User account `balances` map
+const USER_BALANCES_SLOT: u8 = 1;
+
+/// User account `request_events` map
+const USER_EVENTS_SLOT: u8 = 3;
+
+/// Fetch an account's state as of the client's last sync
+///
+/// The read backends decode storage from an `Account`, and the client
+/// implementations fetch the account and hand it over, so the same decoding
+/// runs against a deployed account and a `MockChain` one
+pub async fn synced_account(
+    client: &mut Client<FilesystemKeyStore>,
+    account_id: AccountId,
+) -> Result<Account> {
+    let record = client
+        .get_account(account_id)
+        .await
+        .context("Failed to read account from the client store")?
+        .with_context(|| format!("Account {account_id} is not tracked by the client"))?;
+    Ok(record.account().clone())
+}
+
+/// Check a read is aimed at the account being read
+fn ensure_account(account: &Account, account_id: AccountId) -> Result<()> {
+    ensure!(
+        account.id() == account_id,
+        "Read for account {account_id} given account {}",
+        account.id()
+    );
+    Ok(())
+}
+
+/// Key of a counter or setting in a `balances` map
+fn balance_key(index: u64) -> Word {
+    Word::from([Felt::new(0), Felt::new(0), Felt::new(0), Felt::new(index)])
+}
+
+/// Key of one field of a stored offer, deal or request
+fn field_key(id: u64, field: u64) -> Word {
+    Word::from([Felt::new(id), Felt::new(field), Felt::new(0), Felt::new(0)])
+}
+
+/// Read a word a Voile contract stored in one of its maps
+fn stored_word(account: &Account, slot: u8, key: Word) -> Result<Word> {
+    account
+        .storage()
+        .get_map_item(slot, key)
+        .with_context(|| format!("Failed to read storage slot {slot}"))
+}
+
+/// Read a `Felt` a Voile contract stored in one of its maps
+/// The SDK stores a `Felt` map value as `[0, 0, 0, value]`
+fn stored_felt(account: &Account, slot: u8, key: Word) -> Result<Felt> {
+    Ok(stored_word(account, slot, key)?[3])
+}
+
+/// Read a counter from a `balances` map
+fn stored_count(account: &Account, slot: u8, index: u64, name: &str) -> Result<u64> {
+    felt_to_u64_checked(stored_felt(account, slot, balance_key(index))?)
+        .with_context(|| format!("{name} is out of range"))
+}
+
+impl RequestBackend for Account {
+    async fn request_commitment(&mut self, account_id: AccountId, request_id: u64) -> Result<Felt> {
+        ensure_account(self, account_id)?;
+        stored_felt(self, USER_REQUESTS_SLOT, field_key(request_id, 0))
+    }
+    
+    async fn request_amount(&mut self, account_id: AccountId, request_id: u64) -> Result<Felt> {
+        ensure_account(self, account_id)?;
+        stored_felt(self, USER_REQUESTS_SLOT, field_key(request_id, 2))
+    }
+    
+    async fn request_counter(&mut self, account_id: AccountId) -> Result<u64> {
+        ensure_account(self, account_id)?;
+        stored_count(self, USER_BALANCES_SLOT, 1, "Request counter")
+    }
+    
+    async fn request(&mut self, account_id: AccountId, request_id: u64) -> Result<RequestView> {
+        ensure_account(self, account_id)?;
+        let account: &Account = self;
+        let field = |field| stored_felt(account, USER_REQUESTS_SLOT, field_key(request_id, field));
+        
+        // A request is matched once an LP commitment is stored for it
+        Ok(RequestView {
+            amount: field(2)?,
+            cooldown_end: field(4)?,
+            is_matched: field(1)? != Felt::new(0),
+            is_settled: field(3)? == Felt::new(1),
+        })
+    }
+}
+
+impl RequestBackend for Client<FilesystemKeyStore> {
+    async fn request_commitment(&mut self, account_id: AccountId, request_id: u64) -> Result<Felt> {
+        synced_account(self, account_id).await?.request_commitment(account_id, request_id).await
+    }
+    
+    async fn request_amount(&mut self, account_id: AccountId, request_id: u64) -> Result<Felt> {
+        synced_account(self, account_id).await?.request_amount(account_id, request_id).await
+    }
+    
+    async fn request_counter(&mut self, account_id: AccountId) -> Result<u64> {
+        synced_account(self, account_id).await?.request_counter(account_id).await
+    }
+    
+    async fn request(&mut self, account_id: AccountId, request_id: u64) -> Result<RequestView> {
+        synced_account(self, account_id).await?.request(account_id, request_id).await
+    }
+}
+
+impl RequestEventBackend for Account {
+    async fn event_count(&mut self, account_id: AccountId) -> Result<u64> {
+        ensure_account(self, account_id)?;
+        stored_count(self, USER_BALANCES_SLOT, 3, "Request event counter")
+    }
+    
+    async fn event(&mut self, account_id: AccountId, event_index: u64) -> Result<Word> {
+        ensure_account(self, account_id)?;
+        let request_id = stored_felt(self, USER_EVENTS_SLOT, field_key(event_index, 0))?;
+        let status = stored_felt(self, USER_EVENTS_SLOT, field_key(event_index, 1))?;
+        Ok(Word::from([request_id, status, Felt::new(0), Felt::new(0)]))
+    }
+}
+
+impl RequestEventBackend for Client<FilesystemKeyStore> {
+    async fn event_count(&mut self, account_id: AccountId) -> Result<u64> {
+        synced_account(self, account_id).await?.event_count(account_id).await
+    }
+    
+    async fn event(&mut self, account_id: AccountId, event_index: u64) -> Result<Word> {
+        synced_account(self, account_id).await?.event(account_id, event_index).await
+    }
+}
+
+impl OfferBackend for Account {
+    async fn offer_count(&mut self, pool_account_id: AccountId) -> Result<u64> {
+        ensure_account(self, pool_account_id)?;
+        stored_count(self, POOL_BALANCES_SLOT, 2, "Offer counter")
+    }
+    
+    async fn fee_split(&mut self, pool_account_id: AccountId) -> Result<u64> {
+        ensure_account(self, pool_account_id)?;
+        // Zero means the pool still uses the default split
+        match stored_count(self, POOL_BALANCES_SLOT, 17, "Fee split")? {
+            0 => Ok(PROTOCOL_FEE_BPS),
+            protocol_bps => Ok(protocol_bps),
+        }
+    }
+    
+    async fn offer(&mut self, pool_account_id: AccountId, offer_id: u64) -> Result<OfferView> {
+        ensure_account(self, pool_account_id)?;
+        let account: &Account = self;
+        let field = |field| stored_felt(account, POOL_OFFERS_SLOT, field_key(offer_id, field));
+        let lp_word = stored_word(account, POOL_OFFERS_SLOT, field_key(offer_id, 6))?;
+        
+        Ok(OfferView {
+            lp_account_id: account_id_from_word(lp_word)?,
+            max_amount: field(1)?,
+            min_amount: field(2)?,
+            apr_bps: field(7)?,
+            remaining_amount: field(4)?,
+            is_active: field(3)? == Felt::new(1),
+        })
+    }
+}
+
+impl OfferBackend for Client<FilesystemKeyStore> {
+    async fn offer_count(&mut self, pool_account_id: AccountId) -> Result<u64> {
+        synced_account(self, pool_account_id).await?.offer_count(pool_account_id).await
+    }
+    
+    async fn fee_split(&mut self, pool_account_id: AccountId) -> Result<u64> {
+        synced_account(self, pool_account_id).await?.fee_split(pool_account_id).await
+    }
+    
+    async fn offer(&mut self, pool_account_id: AccountId, offer_id: u64) -> Result<OfferView> {
+        synced_account(self, pool_account_id).await?.offer(pool_account_id, offer_id).await
+    }
+}
+
+impl LedgerBackend for Account {
+    async fn deal_count(&mut self, pool_account_id: AccountId) -> Result<u64> {
+        ensure_account(self, pool_account_id)?;
+        stored_count(self, POOL_BALANCES_SLOT, 3, "Deal counter")
+    }
+    
+    async fn deal(&mut self, pool_account_id: AccountId, deal_id: u64) -> Result<DealView> {
+        ensure_account(self, pool_account_id)?;
+        let account: &Account = self;
+        let field = |field| stored_felt(account, POOL_DEALS_SLOT, field_key(deal_id, field));
+        
+        // A deal accrues at its offer's APR, or the default if the offer set none
+        let offer_id = field(2)?;
+        let offer_apr = stored_felt(account, POOL_OFFERS_SLOT, field_key(offer_id.as_int(), 7))?;
+        let apr_bps = if offer_apr == Felt::new(0) {
+            Felt::new(DEFAULT_APR_BPS)
+        } else {
+            offer_apr
+        };
+        
+        Ok(DealView {
+            user_commitment: field(0)?,
+            advance_amount: field(1)?,
+            offer_id,
+            apr_bps,
+            matched_at: field(6)?,
+            cooldown_end: field(5)?,
+            is_settled: field(3)? == Felt::new(1),
+            settled_at: field(15)?,
+            earned: field(7)?,
+            is_defaulted: field(4)? == Felt::new(1),
+        })
+    }
+    
+    async fn settlement(
+        &mut self,
+        pool_account_id: AccountId,
+        deal_id: u64,
+    ) -> Result<SettlementView> {
+        ensure_account(self, pool_account_id)?;
+        let account: &Account = self;
+        let field = |field| stored_felt(account, POOL_DEALS_SLOT, field_key(deal_id, field));
+        
+        Ok(SettlementView {
+            staked_received: field(8)?,
+            earned: field(7)?,
+            interest: field(9)?,
+        })
+    }
+}
+
+impl LedgerBackend for Client<FilesystemKeyStore> {
+    async fn deal_count(&mut self, pool_account_id: AccountId) -> Result<u64> {
+        synced_account(self, pool_account_id).await?.deal_count(pool_account_id).await
+    }
+    
+    async fn deal(&mut self, pool_account_id: AccountId, deal_id: u64) -> Result<DealView> {
+        synced_account(self, pool_account_id).await?.deal(pool_account_id, deal_id).await
+    }
+    
+    async fn settlement(
+        &mut self,
+        pool_account_id: AccountId,
+        deal_id: u64,
+    ) -> Result<SettlementView> {
+        synced_account(self, pool_account_id).await?.settlement(pool_account_id, deal_id).await
+    }
+}
+
 // ============================================================================
 // TESTS
 // ============================================================================
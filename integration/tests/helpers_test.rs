@@ -0,0 +1,60 @@
+//! Helper Tests
+//! Tests error categorisation and note creation in the shared Miden helpers
+
+use std::path::Path;
+
+use integration::helpers::{
+    build_project_in_dir, create_testing_note, create_testing_note_with_serial,
+    note_script_from_library, NoteCreationConfig, VoileError,
+};
+use miden_client::{account::AccountId, Felt, Word};
+use miden_protocol::testing::account_id::ACCOUNT_ID_REGULAR_PUBLIC_ACCOUNT_IMMUTABLE_CODE;
+
+#[test]
+fn test_build_failure_maps_to_build_error() {
+    let result = build_project_in_dir(Path::new("../contracts/does-not-exist"), false);
+
+    match result {
+        Err(VoileError::Build(_)) => {}
+        Err(other) => panic!("expected build error, got {other}"),
+        Ok(_) => panic!("expected build of a missing project to fail"),
+    }
+}
+
+#[test]
+fn test_testing_notes_get_distinct_ids() -> anyhow::Result<()> {
+    let library = build_project_in_dir(Path::new("../contracts/increment-note"), true)?;
+    let note_script = note_script_from_library(&library)?;
+    let sender = AccountId::try_from(ACCOUNT_ID_REGULAR_PUBLIC_ACCOUNT_IMMUTABLE_CODE)?;
+
+    let first = create_testing_note(note_script.clone(), sender, NoteCreationConfig::default())?;
+    let second = create_testing_note(note_script, sender, NoteCreationConfig::default())?;
+
+    assert_ne!(first.id(), second.id());
+    assert_ne!(first.nullifier(), second.nullifier());
+    Ok(())
+}
+
+#[test]
+fn test_reused_testing_note_serial_is_rejected() -> anyhow::Result<()> {
+    let library = build_project_in_dir(Path::new("../contracts/increment-note"), true)?;
+    let note_script = note_script_from_library(&library)?;
+    let sender = AccountId::try_from(ACCOUNT_ID_REGULAR_PUBLIC_ACCOUNT_IMMUTABLE_CODE)?;
+    let serial_num = Word::from([Felt::new(17), Felt::new(0), Felt::new(0), Felt::new(0)]);
+
+    create_testing_note_with_serial(
+        note_script.clone(),
+        sender,
+        serial_num,
+        NoteCreationConfig::default(),
+    )?;
+    let reused = create_testing_note_with_serial(
+        note_script,
+        sender,
+        serial_num,
+        NoteCreationConfig::default(),
+    );
+
+    assert!(matches!(reused, Err(VoileError::Note(_))));
+    Ok(())
+}
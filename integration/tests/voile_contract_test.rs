@@ -0,0 +1,1407 @@
+//! Voile Contract Tests
+//! Executes the Voile contracts and notes on a local MockChain
+
+use std::{collections::BTreeMap, path::Path, sync::Mutex};
+
+use integration::helpers::{
+    build_project_in_dir, create_testing_account_from_library, create_testing_note,
+    note_script_from_library, AccountCreationConfig, NoteCreationConfig,
+};
+use integration::voile_helpers::{
+    account_id_word, cancel_match_note_config, classify_note_error, decode_earnings_event,
+    execute_match, export_ledger, fetch_request_events, match_note_config, settled_deals,
+    settlement_note_config, simulate_settlement, user_net_position, DealStatus, EarningsEvent,
+    LedgerBackend, LedgerEntry, LpOffer, MatchBackend, MatchedDeal, MatchingEngine, NoteFailure,
+    PricingCalculator, RequestEvent, RequestEventKind, RequestIdAllocator, RequestView,
+    SettledDeal, UnlockRequest, ONE_USDC, SETTLEMENT_GRACE_SECONDS,
+};
+use miden_client::{
+    account::{Account, AccountId, StorageMap, StorageSlot},
+    assembly::Library,
+    asset::FungibleAsset,
+    note::{Note, NoteAssets, NoteScript},
+    Felt, Word,
+};
+use miden_protocol::testing::account_id::{
+    ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET, ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET_1,
+};
+use miden_testing::{Auth, MockChain};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// Storage maps the LP pool component declares
+const POOL_STORAGE_SLOTS: u8 = 7;
+
+/// Storage maps the user account component declares
+const USER_STORAGE_SLOTS: u8 = 4;
+
+/// Procedures `user-call-note` can call, matching its constants
+mod user_call {
+    pub const DEPOSIT_STAKED_ASSETS: u64 = 1;
+    pub const CREATE_UNLOCK_REQUEST: u64 = 2;
+    pub const MARK_REQUEST_MATCHED: u64 = 3;
+    pub const CANCEL_REQUEST: u64 = 4;
+    pub const CANCEL_REQUESTS_BATCH: u64 = 5;
+    pub const CANCEL_REQUEST_BY_NULLIFIER: u64 = 6;
+    pub const AUTHORIZE_SETTLEMENT: u64 = 7;
+    pub const MARK_SETTLED: u64 = 8;
+    pub const GET_STAKED_BALANCE: u64 = 9;
+    pub const GET_REQUEST_STATUS: u64 = 10;
+    pub const GET_REQUEST_AMOUNT: u64 = 11;
+    pub const GET_REQUEST_COOLDOWN_END: u64 = 12;
+    pub const GET_REQUEST_COMMITMENT: u64 = 13;
+    pub const IS_NULLIFIER_SPENT: u64 = 14;
+    pub const TOTAL_FEES_PAID: u64 = 15;
+    pub const GET_HEALTH_FACTOR_BPS: u64 = 16;
+}
+
+/// Procedures `pool-call-note` can call, matching its constants
+mod pool_call {
+    pub const DEPOSIT_USDC: u64 = 1;
+    pub const CREATE_OFFER: u64 = 2;
+    pub const ACCEPT_MATCH: u64 = 3;
+    pub const RECORD_SETTLEMENT: u64 = 4;
+    pub const IS_DEAL_SETTLED: u64 = 5;
+    pub const GET_TOTAL_EARNED: u64 = 6;
+    pub const GET_DEAL_EARNED: u64 = 7;
+    pub const GET_DEAL_STAKED_RECEIVED: u64 = 8;
+    pub const GET_USDC_BALANCE: u64 = 9;
+    pub const GET_OUTSTANDING: u64 = 10;
+    pub const RECORD_DEFAULT: u64 = 11;
+    pub const IS_DEAL_DEFAULTED: u64 = 12;
+    pub const IS_SETTLEABLE: u64 = 13;
+    pub const SET_FEE_SPLIT: u64 = 14;
+    pub const GET_FEE_SPLIT: u64 = 15;
+    pub const GET_DEAL_SETTLED_AT: u64 = 16;
+    pub const GET_OFFER_REMAINING: u64 = 17;
+    pub const IS_OFFER_ACTIVE: u64 = 18;
+    pub const FUND_OFFER: u64 = 19;
+    pub const SET_APR_BOUNDS: u64 = 20;
+    pub const SET_OFFER_COOLDOWN: u64 = 21;
+    pub const SET_MIN_RESERVE: u64 = 22;
+    pub const MAX_SAFE_OFFER: u64 = 23;
+    pub const GET_DEAD_OFFERS: u64 = 24;
+    pub const SET_MAX_PER_TX_ADVANCE: u64 = 25;
+    pub const WITHDRAW_USDC: u64 = 26;
+}
+
+/// Cooldown end the test deals are matched with
+const COOLDOWN_END: u64 = 1_700_000_000;
+
+/// Settlement deadline the pool stores for the test deals: three days of
+/// grace after the cooldown
+const SETTLEMENT_DEADLINE: u64 = COOLDOWN_END + 3 * 24 * 60 * 60;
+
+/// Compile a contract project once per test run
+fn contract(name: &str) -> Library {
+    static BUILT: Mutex<BTreeMap<String, Library>> = Mutex::new(BTreeMap::new());
+    BUILT
+        .lock()
+        .unwrap()
+        .entry(name.to_string())
+        .or_insert_with(|| {
+            build_project_in_dir(&Path::new("../contracts").join(name), true)
+                .unwrap_or_else(|err| panic!("failed to build {name}: {err}"))
+        })
+        .clone()
+}
+
+fn note_script(name: &str) -> anyhow::Result<NoteScript> {
+    Ok(note_script_from_library(&contract(name))?)
+}
+
+fn key(index: u64) -> Word {
+    Word::from([Felt::new(0), Felt::new(0), Felt::new(0), Felt::new(index)])
+}
+
+/// Read a `Felt` the contracts stored in a map
+/// The SDK stores a `Felt` map value as `[0, 0, 0, value]`
+fn stored_felt(account: &Account, slot: u8, key: Word) -> anyhow::Result<Felt> {
+    Ok(account.storage().get_map_item(slot, key)?[3])
+}
+
+/// LP pool account whose deposits count assets from `usdc_faucet` as USDC,
+/// administered by `admin`
+fn pool_account(usdc_faucet: AccountId, admin: AccountId) -> anyhow::Result<Account> {
+    let balances = StorageMap::with_entries([
+        (key(18), account_id_word(usdc_faucet)),
+        (key(19), account_id_word(admin)),
+    ])?;
+    let mut storage_slots = vec![StorageSlot::Map(balances)];
+    storage_slots.extend((1..POOL_STORAGE_SLOTS).map(|_| StorageSlot::Map(StorageMap::new())));
+
+    let config = AccountCreationConfig { storage_slots, ..Default::default() };
+    Ok(create_testing_account_from_library(contract("voile-lp-pool"), config)?)
+}
+
+/// User account with empty storage
+fn user_account() -> anyhow::Result<Account> {
+    let storage_slots =
+        (0..USER_STORAGE_SLOTS).map(|_| StorageSlot::Map(StorageMap::new())).collect();
+    let config = AccountCreationConfig { storage_slots, ..Default::default() };
+    Ok(create_testing_account_from_library(contract("voile-user-account"), config)?)
+}
+
+/// Consume `note` with `account_id` and commit the transaction in a new block
+async fn consume(chain: &mut MockChain, account_id: AccountId, note: Note) -> anyhow::Result<()> {
+    let executed = chain.build_tx_context(account_id, &[], &[note])?.build()?.execute().await?;
+    chain.add_pending_executed_transaction(&executed)?;
+    chain.prove_next_block()?;
+    Ok(())
+}
+
+/// MockChain holding one Voile account, driven by call notes from a wallet
+struct Harness {
+    chain: MockChain,
+    account_id: AccountId,
+    sender_id: AccountId,
+    call_script: NoteScript,
+}
+
+impl Harness {
+    /// Deploy `account` and use the `call_note` project to call it
+    fn new(account: Account, call_note: &str) -> anyhow::Result<Self> {
+        Self::new_with(|_| Ok(account), call_note)
+    }
+
+    /// Deploy the account `account` builds for the wallet sending the call
+    /// notes, and use the `call_note` project to call it
+    fn new_with(
+        account: impl FnOnce(AccountId) -> anyhow::Result<Account>,
+        call_note: &str,
+    ) -> anyhow::Result<Self> {
+        let mut builder = MockChain::builder();
+        let sender = builder.add_existing_wallet(Auth::BasicAuth)?;
+        let account = account(sender.id())?;
+        let account_id = account.id();
+        builder.add_account(account)?;
+
+        Ok(Self {
+            chain: builder.build()?,
+            account_id,
+            sender_id: sender.id(),
+            call_script: note_script(call_note)?,
+        })
+    }
+
+    /// Call `procedure` with `args` in its own transaction
+    /// Fails if the procedure aborts or doesn't return `expected`
+    async fn call(&mut self, procedure: u64, expected: Word, args: &[Felt]) -> anyhow::Result<()> {
+        let mut inputs = vec![Felt::new(procedure)];
+        inputs.extend(expected.as_elements());
+        inputs.extend(args);
+
+        let note = create_testing_note(
+            self.call_script.clone(),
+            self.sender_id,
+            NoteCreationConfig { inputs, ..Default::default() },
+        )?;
+        consume(&mut self.chain, self.account_id, note).await
+    }
+
+    /// The account as of the last committed block
+    fn account(&self) -> anyhow::Result<&Account> {
+        self.chain.committed_account(self.account_id)
+    }
+}
+
+/// Runs a match's notes on a MockChain pool, the way `ClientMatchBackend`
+/// submits them through a client
+struct MockMatchBackend<'a> {
+    pool: &'a mut Harness,
+    settlement_asset_id: AccountId,
+    advance_notes: Vec<Note>,
+}
+
+impl MatchBackend for MockMatchBackend<'_> {
+    async fn accept_match(
+        &mut self,
+        pool_account_id: AccountId,
+        deal: &MatchedDeal,
+    ) -> anyhow::Result<Felt> {
+        let pool_deal_id = self.pool.account()?.clone().deal_count(pool_account_id).await?;
+        let config = match_note_config(deal, self.settlement_asset_id);
+        let note = create_testing_note(note_script("match-note")?, pool_account_id, config)?;
+        consume(&mut self.pool.chain, pool_account_id, note).await?;
+        Ok(Felt::new(pool_deal_id))
+    }
+
+    async fn publish_advance_note(
+        &mut self,
+        pool_account_id: AccountId,
+        _user_account_id: AccountId,
+        config: NoteCreationConfig,
+    ) -> anyhow::Result<Word> {
+        let note = create_testing_note(note_script("advance-note")?, pool_account_id, config)?;
+        let hash = note.id().as_word();
+        self.advance_notes.push(note);
+        Ok(hash)
+    }
+
+    async fn cancel_match(
+        &mut self,
+        pool_account_id: AccountId,
+        pool_deal_id: Felt,
+    ) -> anyhow::Result<()> {
+        let config = cancel_match_note_config(pool_deal_id);
+        let note = create_testing_note(note_script("cancel-match-note")?, pool_account_id, config)?;
+        consume(&mut self.pool.chain, pool_account_id, note).await
+    }
+}
+
+/// Expected result of a procedure returning a `Felt`
+fn returns(value: u64) -> Word {
+    Word::from([Felt::new(value), Felt::new(0), Felt::new(0), Felt::new(0)])
+}
+
+fn felts(values: &[u64]) -> Vec<Felt> {
+    values.iter().copied().map(Felt::new).collect()
+}
+
+/// Arguments for `create_unlock_request`
+fn unlock_request_args(amount: u64, cooldown_end: u64, secret: u64, nullifier: u64) -> Vec<Felt> {
+    felts(&[amount, cooldown_end, secret, nullifier])
+}
+
+/// Arguments for `create_offer`: a fully funded offer at the default APR
+/// that never expires
+fn offer_args(max_amount: u64, min_amount: u64, lp_id: AccountId, now: u64) -> Vec<Felt> {
+    custom_offer_args(max_amount, min_amount, max_amount, 0, lp_id, now)
+}
+
+/// Arguments for `create_offer`: an offer funded with `funded_amount` at
+/// `apr_bps` (0 = default) that never expires
+fn custom_offer_args(
+    max_amount: u64,
+    min_amount: u64,
+    funded_amount: u64,
+    apr_bps: u64,
+    lp_id: AccountId,
+    now: u64,
+) -> Vec<Felt> {
+    let mut args = felts(&[max_amount, min_amount, funded_amount, apr_bps, 1, 0, 0, 0, 0]);
+    args.extend(account_id_word(lp_id).as_elements());
+    args.extend(felts(&[0, 0, 0, 0, now]));
+    args
+}
+
+/// Arguments for `accept_match`, for a deal repaid in `settlement_asset`
+fn match_args(
+    offer_id: u64,
+    advance_amount: u64,
+    matched_at: u64,
+    settlement_asset: AccountId,
+) -> Vec<Felt> {
+    let mut args = felts(&[offer_id, 1, 0, 0, 0, advance_amount, COOLDOWN_END, matched_at]);
+    args.extend(account_id_word(settlement_asset).as_elements());
+    args
+}
+
+/// Arguments for `record_settlement` with no interest, settling at `now`
+fn settlement_args(
+    deal_id: u64,
+    settlement_asset: AccountId,
+    staked_received: u64,
+    fee: u64,
+    now: u64,
+) -> Vec<Felt> {
+    let mut args = felts(&[deal_id]);
+    args.extend(account_id_word(settlement_asset).as_elements());
+    args.extend(felts(&[staked_received, fee, 0, now]));
+    args
+}
+
+/// Pool administered by the sender, driven by `pool-call-note`
+fn pool_harness(usdc: AccountId) -> anyhow::Result<Harness> {
+    Harness::new_with(|sender| pool_account(usdc, sender), "pool-call-note")
+}
+
+/// Pool with 100,000 USDC deposited, offer 0 from the sender, and deal 0
+/// matched from it: a $9,500 advance on $10,000 repaid in `staked`
+async fn pool_with_deal(usdc: AccountId, staked: AccountId) -> anyhow::Result<Harness> {
+    let mut pool = pool_harness(usdc)?;
+    let lp_id = pool.sender_id;
+    let matched_at = COOLDOWN_END - 14 * 24 * 60 * 60;
+
+    let deposit = felts(&[100_000 * ONE_USDC]);
+    pool.call(pool_call::DEPOSIT_USDC, returns(100_000 * ONE_USDC), &deposit).await?;
+    let offer = offer_args(50_000 * ONE_USDC, 1_000 * ONE_USDC, lp_id, matched_at);
+    pool.call(pool_call::CREATE_OFFER, returns(0), &offer).await?;
+    let deal = match_args(0, 9_500 * ONE_USDC, matched_at, staked);
+    pool.call(pool_call::ACCEPT_MATCH, returns(0), &deal).await?;
+    Ok(pool)
+}
+
+#[tokio::test]
+async fn test_deposit_note_credits_only_usdc() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let other = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET_1)?;
+    let mut pool = Harness::new_with(|sender| pool_account(usdc, sender), "deposit-note")?;
+
+    let assets = NoteAssets::new(vec![
+        FungibleAsset::new(usdc, 1000 * ONE_USDC)?.into(),
+        FungibleAsset::new(other, 5 * ONE_USDC)?.into(),
+    ])?;
+    let deposit = create_testing_note(
+        note_script("deposit-note")?,
+        pool.sender_id,
+        NoteCreationConfig { assets, ..Default::default() },
+    )?;
+    consume(&mut pool.chain, pool.account_id, deposit).await?;
+
+    // Both assets reach the vault, but only the USDC is credited
+    let account = pool.account()?;
+    assert_eq!(account.vault().get_balance(usdc)?, 1000 * ONE_USDC);
+    assert_eq!(account.vault().get_balance(other)?, 5 * ONE_USDC);
+    assert_eq!(stored_felt(account, 0, key(0))?, Felt::new(1000 * ONE_USDC));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancel_request_clears_it_and_rejects_matched() -> anyhow::Result<()> {
+    let mut user = Harness::new(user_account()?, "user-call-note")?;
+    let cooldown_end = 1_700_000_000;
+
+    user.call(user_call::DEPOSIT_STAKED_ASSETS, returns(1000), &felts(&[1000])).await?;
+    let open = unlock_request_args(400, cooldown_end, 1, 11);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(0), &open).await?;
+    let matched = unlock_request_args(400, cooldown_end, 2, 12);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(1), &matched).await?;
+    let lp_commitment = felts(&[1, 7, 7, 7, 7]);
+    user.call(user_call::MARK_REQUEST_MATCHED, returns(1), &lp_commitment).await?;
+
+    // Cancelling the open request returns its assets and clears every field
+    user.call(user_call::CANCEL_REQUEST, returns(1), &felts(&[0])).await?;
+    user.call(user_call::GET_STAKED_BALANCE, returns(600), &[]).await?;
+    user.call(user_call::GET_REQUEST_STATUS, returns(4), &felts(&[0])).await?;
+    user.call(user_call::GET_REQUEST_AMOUNT, returns(0), &felts(&[0])).await?;
+    user.call(user_call::GET_REQUEST_COOLDOWN_END, returns(0), &felts(&[0])).await?;
+
+    // Cancelling the matched request aborts and leaves it matched
+    let cancel_matched = user.call(user_call::CANCEL_REQUEST, returns(1), &felts(&[1])).await;
+    assert!(cancel_matched.is_err());
+    user.call(user_call::GET_REQUEST_STATUS, returns(2), &felts(&[1])).await?;
+    user.call(user_call::GET_STAKED_BALANCE, returns(600), &[]).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_second_settlement_is_rejected() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let staked = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET_1)?;
+    let mut pool = pool_with_deal(usdc, staked).await?;
+
+    // The LP keeps 80% of the $500 fee
+    let settlement = settlement_args(0, staked, 10_000 * ONE_USDC, 500 * ONE_USDC, COOLDOWN_END);
+    pool.call(pool_call::RECORD_SETTLEMENT, returns(1), &settlement).await?;
+    pool.call(pool_call::GET_TOTAL_EARNED, returns(400 * ONE_USDC), &[]).await?;
+
+    // Settling again records nothing and leaves the total as it was
+    pool.call(pool_call::RECORD_SETTLEMENT, returns(0), &settlement).await?;
+    pool.call(pool_call::GET_TOTAL_EARNED, returns(400 * ONE_USDC), &[]).await?;
+    pool.call(pool_call::GET_DEAL_EARNED, returns(400 * ONE_USDC), &felts(&[0])).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_settlement_requires_an_open_deal() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let staked = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET_1)?;
+    let mut pool = pool_with_deal(usdc, staked).await?;
+
+    // A deal that was never matched can't be settled
+    let unknown = settlement_args(1, staked, 10_000 * ONE_USDC, 500 * ONE_USDC, COOLDOWN_END);
+    pool.call(pool_call::RECORD_SETTLEMENT, returns(0), &unknown).await?;
+    pool.call(pool_call::GET_TOTAL_EARNED, returns(0), &[]).await?;
+
+    let settlement = settlement_args(0, staked, 10_000 * ONE_USDC, 500 * ONE_USDC, COOLDOWN_END);
+    pool.call(pool_call::RECORD_SETTLEMENT, returns(1), &settlement).await?;
+    pool.call(pool_call::IS_DEAL_SETTLED, returns(1), &felts(&[0])).await?;
+
+    // A second settlement with other figures overwrites nothing
+    let resettlement =
+        settlement_args(0, staked, 20_000 * ONE_USDC, 1_000 * ONE_USDC, COOLDOWN_END);
+    pool.call(pool_call::RECORD_SETTLEMENT, returns(0), &resettlement).await?;
+    let received = returns(10_000 * ONE_USDC);
+    pool.call(pool_call::GET_DEAL_STAKED_RECEIVED, received, &felts(&[0])).await?;
+    pool.call(pool_call::GET_TOTAL_EARNED, returns(400 * ONE_USDC), &[]).await?;
+    pool.call(pool_call::GET_USDC_BALANCE, returns(100_500 * ONE_USDC), &[]).await?;
+    pool.call(pool_call::GET_OUTSTANDING, returns(0), &[]).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_health_factor_counts_accrued_interest() -> anyhow::Result<()> {
+    let mut user = Harness::new(user_account()?, "user-call-note")?;
+    let amount = 10_000 * ONE_USDC;
+
+    let deposit = felts(&[amount]);
+    user.call(user_call::DEPOSIT_STAKED_ASSETS, returns(amount), &deposit).await?;
+    let request = unlock_request_args(amount, COOLDOWN_END, 1, 11);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(0), &request).await?;
+    user.call(user_call::MARK_REQUEST_MATCHED, returns(1), &felts(&[0, 7, 7, 7, 7])).await?;
+
+    // 73 days out, 10% APR adds $200 to the $10,000 owed
+    let now = COOLDOWN_END - 73 * 24 * 60 * 60;
+    let covered = felts(&[0, 10_200 * ONE_USDC, now]);
+    user.call(user_call::GET_HEALTH_FACTOR_BPS, returns(10_000), &covered).await?;
+
+    // Slashing the collateral by 5% leaves the obligation uncovered
+    let slashed = felts(&[0, 9_690 * ONE_USDC, now]);
+    user.call(user_call::GET_HEALTH_FACTOR_BPS, returns(9_500), &slashed).await?;
+
+    // No interest is left to accrue once the cooldown has ended
+    let at_cooldown_end = felts(&[0, 10_200 * ONE_USDC, COOLDOWN_END]);
+    user.call(user_call::GET_HEALTH_FACTOR_BPS, returns(10_200), &at_cooldown_end).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_request_commitment_binds_the_executing_account() -> anyhow::Result<()> {
+    let mut user = Harness::new(user_account()?, "user-call-note")?;
+    let amount = 5_000 * ONE_USDC;
+    let request = UnlockRequest::reconstruct(0, amount, COOLDOWN_END, [3u8; 32], user.account_id);
+
+    let deposit = felts(&[amount]);
+    user.call(user_call::DEPOSIT_STAKED_ASSETS, returns(amount), &deposit).await?;
+    let mut args = felts(&[amount, COOLDOWN_END]);
+    args.extend([request.commitment_secret(), request.nullifier()]);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(0), &args).await?;
+
+    // The stored commitment is the one the user computes off-chain for
+    // their own account
+    let commitment = Word::from([request.commitment[0], Felt::new(0), Felt::new(0), Felt::new(0)]);
+    user.call(user_call::GET_REQUEST_COMMITMENT, commitment, &felts(&[0])).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_matches_onchain_reads_the_stored_request() -> anyhow::Result<()> {
+    let mut user = Harness::new(user_account()?, "user-call-note")?;
+    let amount = 5_000 * ONE_USDC;
+    let secret = [3u8; 32];
+    let request = UnlockRequest::reconstruct(0, amount, COOLDOWN_END, secret, user.account_id);
+
+    user.call(user_call::DEPOSIT_STAKED_ASSETS, returns(amount), &felts(&[amount])).await?;
+    let mut args = felts(&[amount, COOLDOWN_END]);
+    args.extend([request.commitment_secret(), request.nullifier()]);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(0), &args).await?;
+
+    let mut account = user.account()?.clone();
+    assert!(request.matches_onchain(&mut account, user.account_id).await?);
+
+    // A request remembered with the wrong amount, or rebuilt for another
+    // account, doesn't match what the account stored
+    let smaller = UnlockRequest::reconstruct(0, amount - 1, COOLDOWN_END, secret, user.account_id);
+    assert!(!smaller.matches_onchain(&mut account, user.account_id).await?);
+    let elsewhere = UnlockRequest::reconstruct(0, amount, COOLDOWN_END, secret, user.sender_id);
+    assert!(!elsewhere.matches_onchain(&mut account, user.account_id).await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_user_net_position_reads_the_accounts_requests() -> anyhow::Result<()> {
+    let mut user = Harness::new(user_account()?, "user-call-note")?;
+    let deposit = felts(&[20_000 * ONE_USDC]);
+    user.call(user_call::DEPOSIT_STAKED_ASSETS, returns(20_000 * ONE_USDC), &deposit).await?;
+
+    // Request 0 is matched; request 1 is still waiting and owes nothing yet
+    let matched = unlock_request_args(10_000 * ONE_USDC, COOLDOWN_END, 1, 11);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(0), &matched).await?;
+    let waiting = unlock_request_args(4_000 * ONE_USDC, COOLDOWN_END, 2, 12);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(1), &waiting).await?;
+    user.call(user_call::MARK_REQUEST_MATCHED, returns(1), &felts(&[0, 7, 7, 7, 7])).await?;
+
+    let mut account = user.account()?.clone();
+    let now = COOLDOWN_END - 14 * 24 * 60 * 60;
+    let collateral = 20_000 * ONE_USDC;
+    let position = user_net_position(&mut account, user.account_id, collateral, now).await?;
+
+    let owed = 10_000 * ONE_USDC + PricingCalculator::apr_interest(10_000 * ONE_USDC, 14);
+    assert_eq!(position, (collateral - owed) as i64);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_request_event_log_decodes_from_the_account() -> anyhow::Result<()> {
+    let mut user = Harness::new(user_account()?, "user-call-note")?;
+    user.call(user_call::DEPOSIT_STAKED_ASSETS, returns(1000), &felts(&[1000])).await?;
+    let first = unlock_request_args(400, COOLDOWN_END, 1, 11);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(0), &first).await?;
+    let second = unlock_request_args(400, COOLDOWN_END, 2, 12);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(1), &second).await?;
+    user.call(user_call::CANCEL_REQUEST, returns(1), &felts(&[0])).await?;
+    user.call(user_call::MARK_REQUEST_MATCHED, returns(1), &felts(&[1, 7, 7, 7, 7])).await?;
+
+    let mut account = user.account()?.clone();
+    let event = |event_index, request_id, kind| RequestEvent { event_index, request_id, kind };
+    let events = fetch_request_events(&mut account, user.account_id, 0).await?;
+    assert_eq!(
+        events,
+        vec![
+            event(0, 0, RequestEventKind::Created),
+            event(1, 1, RequestEventKind::Created),
+            event(2, 0, RequestEventKind::Cancelled),
+            event(3, 1, RequestEventKind::Matched),
+        ]
+    );
+
+    // An indexer that already saw the creations picks up only what followed
+    let newer = fetch_request_events(&mut account, user.account_id, 2).await?;
+    assert_eq!(newer, events[2..]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_request_ids_continue_from_the_accounts_counter() -> anyhow::Result<()> {
+    let mut user = Harness::new(user_account()?, "user-call-note")?;
+    user.call(user_call::DEPOSIT_STAKED_ASSETS, returns(1000), &felts(&[1000])).await?;
+    let first = unlock_request_args(400, COOLDOWN_END, 1, 11);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(0), &first).await?;
+    let second = unlock_request_args(400, COOLDOWN_END, 2, 12);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(1), &second).await?;
+
+    // A wallet restored after two requests hands out the ID the account
+    // gives the next one
+    let mut account = user.account()?.clone();
+    let mut allocator = RequestIdAllocator::from_account(&mut account, user.account_id).await?;
+    let request_id = allocator.next_id();
+    assert_eq!(request_id, 2);
+    let third = unlock_request_args(100, COOLDOWN_END, 3, 13);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(request_id), &third).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reused_nullifier_or_secret_is_rejected() -> anyhow::Result<()> {
+    let mut user = Harness::new(user_account()?, "user-call-note")?;
+    user.call(user_call::DEPOSIT_STAKED_ASSETS, returns(1000), &felts(&[1000])).await?;
+    let first = unlock_request_args(400, COOLDOWN_END, 1, 11);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(0), &first).await?;
+
+    user.call(user_call::IS_NULLIFIER_SPENT, returns(1), &felts(&[11])).await?;
+    user.call(user_call::IS_NULLIFIER_SPENT, returns(0), &felts(&[12])).await?;
+
+    // A request reusing either the nullifier or the secret aborts
+    let reused_nullifier = unlock_request_args(400, COOLDOWN_END, 2, 11);
+    let result = user.call(user_call::CREATE_UNLOCK_REQUEST, returns(1), &reused_nullifier).await;
+    assert!(result.is_err());
+    let reused_secret = unlock_request_args(400, COOLDOWN_END, 1, 12);
+    let result = user.call(user_call::CREATE_UNLOCK_REQUEST, returns(1), &reused_secret).await;
+    assert!(result.is_err());
+    user.call(user_call::GET_STAKED_BALANCE, returns(600), &[]).await?;
+
+    let fresh = unlock_request_args(400, COOLDOWN_END, 2, 12);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(1), &fresh).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_default_is_allowed_from_the_exact_deadline() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let staked = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET_1)?;
+    let mut pool = pool_with_deal(usdc, staked).await?;
+    let before = SETTLEMENT_DEADLINE - 1;
+
+    // Settleable up to the second before the deadline, defaultable from it
+    pool.call(pool_call::IS_SETTLEABLE, returns(1), &felts(&[0, before])).await?;
+    pool.call(pool_call::IS_SETTLEABLE, returns(0), &felts(&[0, SETTLEMENT_DEADLINE])).await?;
+    pool.call(pool_call::RECORD_DEFAULT, returns(0), &felts(&[0, before, before])).await?;
+
+    // A later grace deadline still holds the default back until it is reached
+    let grace = SETTLEMENT_DEADLINE + 1;
+    let early = felts(&[0, SETTLEMENT_DEADLINE, grace]);
+    pool.call(pool_call::RECORD_DEFAULT, returns(0), &early).await?;
+    pool.call(pool_call::IS_DEAL_DEFAULTED, returns(0), &felts(&[0])).await?;
+
+    let on_time = felts(&[0, SETTLEMENT_DEADLINE, SETTLEMENT_DEADLINE]);
+    pool.call(pool_call::RECORD_DEFAULT, returns(1), &on_time).await?;
+    pool.call(pool_call::IS_DEAL_DEFAULTED, returns(1), &felts(&[0])).await?;
+    pool.call(pool_call::GET_OUTSTANDING, returns(0), &[]).await?;
+
+    // A defaulted deal can't default again
+    pool.call(pool_call::RECORD_DEFAULT, returns(0), &on_time).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_only_the_admin_sets_the_fee_split() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let outsider = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET_1)?;
+
+    // Administered by another account, the sender's change aborts
+    let mut pool = Harness::new(pool_account(usdc, outsider)?, "pool-call-note")?;
+    let result = pool.call(pool_call::SET_FEE_SPLIT, returns(1), &felts(&[3000])).await;
+    assert!(result.is_err());
+    pool.call(pool_call::GET_FEE_SPLIT, returns(2000), &[]).await?;
+
+    let mut pool = pool_harness(usdc)?;
+    pool.call(pool_call::SET_FEE_SPLIT, returns(1), &felts(&[3000])).await?;
+    pool.call(pool_call::GET_FEE_SPLIT, returns(3000), &[]).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_settlement_only_within_its_window() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let staked = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET_1)?;
+    let mut pool = pool_with_deal(usdc, staked).await?;
+    let settle_at = |now| settlement_args(0, staked, 10_000 * ONE_USDC, 500 * ONE_USDC, now);
+
+    // Neither before the cooldown ends nor from the settlement deadline on
+    pool.call(pool_call::RECORD_SETTLEMENT, returns(0), &settle_at(COOLDOWN_END - 1)).await?;
+    pool.call(pool_call::RECORD_SETTLEMENT, returns(0), &settle_at(SETTLEMENT_DEADLINE)).await?;
+    pool.call(pool_call::IS_DEAL_SETTLED, returns(0), &felts(&[0])).await?;
+    pool.call(pool_call::GET_OUTSTANDING, returns(9_500 * ONE_USDC), &[]).await?;
+
+    // The last second before the deadline still settles
+    pool.call(pool_call::RECORD_SETTLEMENT, returns(1), &settle_at(SETTLEMENT_DEADLINE - 1)).await?;
+    pool.call(pool_call::IS_DEAL_SETTLED, returns(1), &felts(&[0])).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_classify_failed_settlement_authorization() -> anyhow::Result<()> {
+    let mut user = Harness::new(user_account()?, "user-call-note")?;
+    user.call(user_call::DEPOSIT_STAKED_ASSETS, returns(1000), &felts(&[1000])).await?;
+    let request = unlock_request_args(400, COOLDOWN_END, 1, 11);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(0), &request).await?;
+
+    let mut view = RequestView {
+        amount: Felt::new(400),
+        cooldown_end: Felt::new(COOLDOWN_END),
+        is_matched: false,
+        is_settled: false,
+    };
+    let authorize = |cooldown_end, now| felts(&[0, cooldown_end, now]);
+
+    // Classify what the VM actually reports for each failing check
+    let err = user
+        .call(user_call::AUTHORIZE_SETTLEMENT, returns(1), &authorize(COOLDOWN_END, COOLDOWN_END))
+        .await
+        .unwrap_err();
+    let failure = classify_note_error(&err, &view, COOLDOWN_END, COOLDOWN_END);
+    assert_eq!(failure, NoteFailure::SettlementNotAuthorized);
+
+    user.call(user_call::MARK_REQUEST_MATCHED, returns(1), &felts(&[0, 7, 7, 7, 7])).await?;
+    view.is_matched = true;
+
+    let early = COOLDOWN_END - 1;
+    let err = user
+        .call(user_call::AUTHORIZE_SETTLEMENT, returns(1), &authorize(early, COOLDOWN_END))
+        .await
+        .unwrap_err();
+    let failure = classify_note_error(&err, &view, early, COOLDOWN_END);
+    assert_eq!(failure, NoteFailure::CommitmentMismatch);
+
+    let err = user
+        .call(user_call::AUTHORIZE_SETTLEMENT, returns(1), &authorize(COOLDOWN_END, early))
+        .await
+        .unwrap_err();
+    let failure = classify_note_error(&err, &view, COOLDOWN_END, early);
+    assert_eq!(failure, NoteFailure::CooldownNotEnded);
+    assert!(failure.is_retryable());
+
+    // From the pool's settlement deadline on, the request can't settle either
+    let late = authorize(COOLDOWN_END, SETTLEMENT_DEADLINE);
+    let err = user.call(user_call::AUTHORIZE_SETTLEMENT, returns(1), &late).await.unwrap_err();
+    let failure = classify_note_error(&err, &view, COOLDOWN_END, SETTLEMENT_DEADLINE);
+    assert_eq!(failure, NoteFailure::DeadlinePassed);
+    assert!(!failure.is_retryable());
+
+    // In between, the same authorization goes through
+    let on_time = authorize(COOLDOWN_END, COOLDOWN_END);
+    user.call(user_call::AUTHORIZE_SETTLEMENT, returns(1), &on_time).await?;
+    let last_second = authorize(COOLDOWN_END, SETTLEMENT_DEADLINE - 1);
+    user.call(user_call::AUTHORIZE_SETTLEMENT, returns(1), &last_second).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_settlement_records_when_it_settled() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let staked = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET_1)?;
+    let mut pool = pool_with_deal(usdc, staked).await?;
+    pool.call(pool_call::GET_DEAL_SETTLED_AT, returns(0), &felts(&[0])).await?;
+
+    let settled_at = COOLDOWN_END + 60;
+    let settlement = settlement_args(0, staked, 10_000 * ONE_USDC, 500 * ONE_USDC, settled_at);
+    pool.call(pool_call::RECORD_SETTLEMENT, returns(1), &settlement).await?;
+    pool.call(pool_call::GET_DEAL_SETTLED_AT, returns(settled_at), &felts(&[0])).await?;
+    pool.call(pool_call::GET_DEAL_EARNED, returns(400 * ONE_USDC), &felts(&[0])).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_simulated_settlement_matches_the_pool() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let staked = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET_1)?;
+    let mut pool = pool_with_deal(usdc, staked).await?;
+    let day = 24 * 60 * 60;
+
+    // Deal 0 as the matching engine makes it: $10,000 from offer 0 at the
+    // default APR, 14 days before the cooldown ends
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut engine = MatchingEngine::new();
+    let lp_id = pool.sender_id;
+    engine.add_offer(LpOffer::new(0, lp_id, 50_000 * ONE_USDC, 1_000 * ONE_USDC, None));
+    let request = UnlockRequest::new(0, 10_000 * ONE_USDC, COOLDOWN_END, lp_id, &mut rng);
+    let deal = engine.match_request(request, COOLDOWN_END - 14 * day, &mut rng).unwrap();
+    assert_eq!(deal.advance_amount, 9_500 * ONE_USDC);
+
+    // Settled two days late, the simulated interest is capped as the pool
+    // caps it, so the pool accepts it
+    let outcome = simulate_settlement(&deal, 16 * day, 2000);
+    let mut settlement = felts(&[0]);
+    settlement.extend(account_id_word(staked).as_elements());
+    let settled_at = COOLDOWN_END + 2 * day;
+    let (received, fee) = (outcome.pool_balance_delta, deal.advance_fee());
+    settlement.extend(felts(&[received, fee, outcome.lp_interest, settled_at]));
+
+    let before = pool.account()?.clone();
+    pool.call(pool_call::RECORD_SETTLEMENT, returns(1), &settlement).await?;
+    let after = pool.account()?;
+    let stored = |account: &Account, index| -> anyhow::Result<u64> {
+        Ok(stored_felt(account, 0, key(index))?.as_int())
+    };
+
+    // Every figure matches what the pool's storage moved by
+    assert_eq!(stored(after, 0)? - stored(&before, 0)?, outcome.pool_balance_delta);
+    assert_eq!(stored(&before, 4)? - stored(after, 4)?, outcome.outstanding_released);
+    assert_eq!(stored(after, 1)? - stored(&before, 1)?, outcome.lp_fee + outcome.lp_interest);
+    let interest_key = Word::from([Felt::new(0), Felt::new(9), Felt::new(0), Felt::new(0)]);
+    assert_eq!(stored_felt(after, 2, interest_key)?.as_int(), outcome.lp_interest);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_settlement_logs_a_decodable_earnings_event() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let staked = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET_1)?;
+    let mut pool = pool_with_deal(usdc, staked).await?;
+
+    let settlement = settlement_args(0, staked, 10_000 * ONE_USDC, 500 * ONE_USDC, COOLDOWN_END);
+    pool.call(pool_call::RECORD_SETTLEMENT, returns(1), &settlement).await?;
+
+    // The pool logs the credit as event 0 in its earnings log
+    let account = pool.account()?;
+    assert_eq!(stored_felt(account, 0, key(15))?, Felt::new(1));
+    let event_key = Word::from([Felt::new(0), Felt::new(0), Felt::new(0), Felt::new(0)]);
+    let event = account.storage().get_map_item(6, event_key)?;
+
+    let earned = stored_felt(account, 0, key(1))?.as_int();
+    assert_eq!(
+        decode_earnings_event(event)?,
+        EarningsEvent {
+            deal_id: 0,
+            fee_credited: earned,
+            interest_credited: 0,
+            total_earned: earned,
+        }
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_settled_deals_reads_the_pools_settlements() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let staked = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET_1)?;
+    let mut pool = pool_with_deal(usdc, staked).await?;
+    let matched_at = COOLDOWN_END - 14 * 24 * 60 * 60;
+
+    // Deal 0 settles with $30 of interest; deal 1 stays open
+    let open = match_args(0, 1_900 * ONE_USDC, matched_at, staked);
+    pool.call(pool_call::ACCEPT_MATCH, returns(1), &open).await?;
+    let mut settlement = felts(&[0]);
+    settlement.extend(account_id_word(staked).as_elements());
+    settlement.extend(felts(&[10_000 * ONE_USDC, 500 * ONE_USDC, 30 * ONE_USDC, COOLDOWN_END]));
+    pool.call(pool_call::RECORD_SETTLEMENT, returns(1), &settlement).await?;
+
+    let mut account = pool.account()?.clone();
+    let settled = settled_deals(&mut account, pool.account_id).await?;
+    let expected = SettledDeal {
+        deal_id: 0,
+        staked_received: 10_000 * ONE_USDC,
+        lp_fee: 400 * ONE_USDC,
+        interest: 30 * ONE_USDC,
+    };
+    assert_eq!(settled, vec![expected]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sync_from_pool_reads_the_pools_offers() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let staked = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET_1)?;
+    let mut pool = pool_with_deal(usdc, staked).await?;
+    let lp_id = pool.sender_id;
+    let matched_at = COOLDOWN_END - 14 * 24 * 60 * 60;
+
+    // Offer 1 is drawn down to nothing and closes; offer 2 at 9% is untouched
+    let (max, min) = (5_000 * ONE_USDC, 1_000 * ONE_USDC);
+    let drained = custom_offer_args(max, min, max, 0, lp_id, matched_at);
+    pool.call(pool_call::CREATE_OFFER, returns(1), &drained).await?;
+    let deal = match_args(1, 5_000 * ONE_USDC, matched_at, staked);
+    pool.call(pool_call::ACCEPT_MATCH, returns(1), &deal).await?;
+    let cheaper = custom_offer_args(max, min, max, 900, lp_id, matched_at);
+    pool.call(pool_call::CREATE_OFFER, returns(2), &cheaper).await?;
+    pool.call(pool_call::SET_FEE_SPLIT, returns(1), &felts(&[3000])).await?;
+
+    let mut engine = MatchingEngine::new();
+    let mut account = pool.account()?.clone();
+    engine.sync_from_pool(&mut account, pool.account_id).await?;
+
+    assert_eq!(engine.protocol_fee_bps, 3000);
+    let offers: Vec<_> = engine
+        .offers
+        .iter()
+        .map(|offer| (offer.offer_id, offer.apr_bps(), offer.remaining_amount()))
+        .collect();
+    assert_eq!(offers, vec![(0, 1000, 40_500 * ONE_USDC), (2, 900, 5_000 * ONE_USDC)]);
+    assert!(engine.offers.iter().all(|offer| offer.lp_account_id == lp_id));
+
+    // Another pool's account is refused rather than read as this one
+    let mut user = user_account()?;
+    assert!(engine.sync_from_pool(&mut user, pool.account_id).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_export_ledger_reads_the_pools_deals() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let staked = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET_1)?;
+    let mut pool = pool_with_deal(usdc, staked).await?;
+    let matched_at = COOLDOWN_END - 14 * 24 * 60 * 60;
+
+    // Deal 0 settles; deal 1 stays open
+    let open = match_args(0, 1_900 * ONE_USDC, matched_at, staked);
+    pool.call(pool_call::ACCEPT_MATCH, returns(1), &open).await?;
+    let settlement = settlement_args(0, staked, 10_000 * ONE_USDC, 500 * ONE_USDC, COOLDOWN_END);
+    pool.call(pool_call::RECORD_SETTLEMENT, returns(1), &settlement).await?;
+
+    let mut account = pool.account()?.clone();
+    let ledger = export_ledger(&mut account, pool.account_id).await?;
+    let entries: Vec<LedgerEntry> = serde_json::from_str(&ledger)?;
+
+    let entry = |deal_id, advance_amount, advance_fee| LedgerEntry {
+        deal_id,
+        offer_id: 0,
+        user_commitment: 1,
+        advance_amount,
+        advance_fee,
+        apr_bps: 1000,
+        matched_at,
+        cooldown_end: COOLDOWN_END,
+        settled_at: None,
+        lp_earnings: 0,
+        status: DealStatus::Open,
+    };
+    let settled = LedgerEntry {
+        settled_at: Some(COOLDOWN_END),
+        lp_earnings: 400 * ONE_USDC,
+        status: DealStatus::Settled,
+        ..entry(0, 9_500 * ONE_USDC, 500 * ONE_USDC)
+    };
+    assert_eq!(entries, vec![settled, entry(1, 1_900 * ONE_USDC, 100 * ONE_USDC)]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_execute_match_records_the_deal_and_delivers_the_advance() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let staked = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET_1)?;
+    let mut pool = pool_harness(usdc)?;
+    let pool_id = pool.account_id;
+    // The sender's wallet is both the LP and the user taking the advance
+    let wallet_id = pool.sender_id;
+    let now = u64::from(pool.chain.latest_block_header().timestamp());
+
+    let deposit = felts(&[100_000 * ONE_USDC]);
+    pool.call(pool_call::DEPOSIT_USDC, returns(100_000 * ONE_USDC), &deposit).await?;
+    let offer = offer_args(50_000 * ONE_USDC, 1_000 * ONE_USDC, wallet_id, now);
+    pool.call(pool_call::CREATE_OFFER, returns(0), &offer).await?;
+
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut engine = MatchingEngine::new();
+    engine.add_offer(LpOffer::new(0, wallet_id, 50_000 * ONE_USDC, 1_000 * ONE_USDC, None));
+    let cooldown_end = now + 14 * 24 * 60 * 60;
+    let request = UnlockRequest::new(0, 10_000 * ONE_USDC, cooldown_end, wallet_id, &mut rng);
+    let deal = engine.match_request(request, now, &mut rng).unwrap();
+
+    let mut backend = MockMatchBackend {
+        pool: &mut pool,
+        settlement_asset_id: staked,
+        advance_notes: Vec::new(),
+    };
+    let receipt = execute_match(&mut backend, wallet_id, pool_id, &deal).await?;
+    let advance_note = backend.advance_notes.pop().unwrap();
+    assert_eq!(receipt.pool_deal_id, Felt::new(0));
+    assert_eq!(receipt.advance_note_hash, advance_note.id().as_word());
+
+    // The pool recorded the deal the engine made
+    let stored = pool.account()?.clone().deal(pool_id, 0).await?;
+    assert_eq!(stored.user_commitment, deal.request.commitment[0]);
+    assert_eq!(stored.advance_amount, Felt::new(deal.advance_amount));
+    assert_eq!(stored.offer_id, Felt::new(0));
+    assert_eq!(stored.cooldown_end, Felt::new(cooldown_end));
+    pool.call(pool_call::GET_OUTSTANDING, returns(deal.advance_amount), &[]).await?;
+
+    // and the user's wallet takes delivery of the advance
+    consume(&mut pool.chain, wallet_id, advance_note).await?;
+
+    // A match whose advance can't be delivered is released again
+    let mut backend = MockMatchBackend {
+        pool: &mut pool,
+        settlement_asset_id: staked,
+        advance_notes: Vec::new(),
+    };
+    let pool_deal_id = backend.accept_match(pool_id, &deal).await?;
+    assert_eq!(pool_deal_id, Felt::new(1));
+    backend.cancel_match(pool_id, pool_deal_id).await?;
+    pool.call(pool_call::GET_OUTSTANDING, returns(deal.advance_amount), &[]).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_matches_draw_down_an_offers_remaining_capacity() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let staked = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET_1)?;
+    let mut pool = pool_harness(usdc)?;
+    let lp_id = pool.sender_id;
+    let matched_at = COOLDOWN_END - 14 * 24 * 60 * 60;
+
+    let deposit = felts(&[100_000 * ONE_USDC]);
+    pool.call(pool_call::DEPOSIT_USDC, returns(100_000 * ONE_USDC), &deposit).await?;
+    let offer = offer_args(10_000 * ONE_USDC, 1_000 * ONE_USDC, lp_id, matched_at);
+    pool.call(pool_call::CREATE_OFFER, returns(0), &offer).await?;
+
+    let first = match_args(0, 6_000 * ONE_USDC, matched_at, staked);
+    pool.call(pool_call::ACCEPT_MATCH, returns(0), &first).await?;
+    pool.call(pool_call::GET_OFFER_REMAINING, returns(4_000 * ONE_USDC), &felts(&[0])).await?;
+
+    // The pool could fund it, but the offer has only $4,000 left
+    let too_large = match_args(0, 5_000 * ONE_USDC, matched_at, staked);
+    assert!(pool.call(pool_call::ACCEPT_MATCH, returns(1), &too_large).await.is_err());
+
+    let rest = match_args(0, 4_000 * ONE_USDC, matched_at, staked);
+    pool.call(pool_call::ACCEPT_MATCH, returns(1), &rest).await?;
+    pool.call(pool_call::GET_OFFER_REMAINING, returns(0), &felts(&[0])).await?;
+    pool.call(pool_call::IS_OFFER_ACTIVE, returns(0), &felts(&[0])).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_matches_are_capped_at_an_offers_funding() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let staked = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET_1)?;
+    let mut pool = pool_harness(usdc)?;
+    let lp_id = pool.sender_id;
+    let matched_at = COOLDOWN_END - 14 * 24 * 60 * 60;
+
+    let deposit = felts(&[100_000 * ONE_USDC]);
+    pool.call(pool_call::DEPOSIT_USDC, returns(100_000 * ONE_USDC), &deposit).await?;
+    let (max, min, funded) = (10_000 * ONE_USDC, 1_000 * ONE_USDC, 3_000 * ONE_USDC);
+    let offer = custom_offer_args(max, min, funded, 0, lp_id, matched_at);
+    pool.call(pool_call::CREATE_OFFER, returns(0), &offer).await?;
+    pool.call(pool_call::GET_OFFER_REMAINING, returns(3_000 * ONE_USDC), &felts(&[0])).await?;
+
+    // Within the offer's max, but beyond what's funded so far
+    let deal = match_args(0, 5_000 * ONE_USDC, matched_at, staked);
+    assert!(pool.call(pool_call::ACCEPT_MATCH, returns(0), &deal).await.is_err());
+
+    pool.call(pool_call::FUND_OFFER, returns(1), &felts(&[0, 2_000 * ONE_USDC])).await?;
+    pool.call(pool_call::GET_OFFER_REMAINING, returns(5_000 * ONE_USDC), &felts(&[0])).await?;
+    pool.call(pool_call::ACCEPT_MATCH, returns(0), &deal).await?;
+
+    // The unfunded $5,000 keeps the offer open for more funding
+    pool.call(pool_call::GET_OFFER_REMAINING, returns(0), &felts(&[0])).await?;
+    pool.call(pool_call::IS_OFFER_ACTIVE, returns(1), &felts(&[0])).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_only_the_admin_caps_advances_per_transaction() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let staked = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET_1)?;
+    let (cap, set_cap) = (5_000 * ONE_USDC, pool_call::SET_MAX_PER_TX_ADVANCE);
+
+    // Administered by another account, the sender's cap aborts
+    let mut pool = Harness::new(pool_account(usdc, staked)?, "pool-call-note")?;
+    assert!(pool.call(set_cap, returns(cap), &felts(&[cap])).await.is_err());
+
+    let mut pool = pool_harness(usdc)?;
+    let lp_id = pool.sender_id;
+    let matched_at = COOLDOWN_END - 14 * 24 * 60 * 60;
+    pool.call(set_cap, returns(cap), &felts(&[cap])).await?;
+
+    let deposit = felts(&[100_000 * ONE_USDC]);
+    pool.call(pool_call::DEPOSIT_USDC, returns(100_000 * ONE_USDC), &deposit).await?;
+    let offer = offer_args(50_000 * ONE_USDC, 1_000 * ONE_USDC, lp_id, matched_at);
+    pool.call(pool_call::CREATE_OFFER, returns(0), &offer).await?;
+
+    // The offer could cover it, but the cap can't
+    let over = match_args(0, cap + 1, matched_at, staked);
+    assert!(pool.call(pool_call::ACCEPT_MATCH, returns(0), &over).await.is_err());
+    let at_cap = match_args(0, cap, matched_at, staked);
+    pool.call(pool_call::ACCEPT_MATCH, returns(0), &at_cap).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_offer_apr_must_fall_within_bounds() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let mut pool = pool_harness(usdc)?;
+    let lp_id = pool.sender_id;
+    let now = COOLDOWN_END;
+
+    let deposit = felts(&[100_000 * ONE_USDC]);
+    pool.call(pool_call::DEPOSIT_USDC, returns(100_000 * ONE_USDC), &deposit).await?;
+    pool.call(pool_call::SET_APR_BOUNDS, returns(1), &felts(&[500, 3000])).await?;
+
+    let offer_at = |apr_bps| {
+        let (max, min) = (10_000 * ONE_USDC, 1_000 * ONE_USDC);
+        custom_offer_args(max, min, max, apr_bps, lp_id, now)
+    };
+    assert!(pool.call(pool_call::CREATE_OFFER, returns(0), &offer_at(100)).await.is_err());
+    assert!(pool.call(pool_call::CREATE_OFFER, returns(0), &offer_at(50_000)).await.is_err());
+
+    // Both bounds are inclusive
+    pool.call(pool_call::CREATE_OFFER, returns(0), &offer_at(500)).await?;
+    pool.call(pool_call::CREATE_OFFER, returns(1), &offer_at(3000)).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_only_the_admin_sets_apr_bounds() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let outsider = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET_1)?;
+    let mut pool = Harness::new(pool_account(usdc, outsider)?, "pool-call-note")?;
+    let lp_id = pool.sender_id;
+
+    let deposit = felts(&[100_000 * ONE_USDC]);
+    pool.call(pool_call::DEPOSIT_USDC, returns(100_000 * ONE_USDC), &deposit).await?;
+    let result = pool.call(pool_call::SET_APR_BOUNDS, returns(1), &felts(&[500, 3000])).await;
+    assert!(result.is_err());
+
+    // The rejected bounds don't apply, so an APR below them still goes through
+    let (max, min) = (10_000 * ONE_USDC, 1_000 * ONE_USDC);
+    let offer = custom_offer_args(max, min, max, 100, lp_id, COOLDOWN_END);
+    pool.call(pool_call::CREATE_OFFER, returns(0), &offer).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_offer_creation_is_rate_limited_per_lp() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let mut pool = pool_harness(usdc)?;
+    let lp_id = pool.sender_id;
+    let now = COOLDOWN_END;
+
+    let deposit = felts(&[100_000 * ONE_USDC]);
+    pool.call(pool_call::DEPOSIT_USDC, returns(100_000 * ONE_USDC), &deposit).await?;
+    pool.call(pool_call::SET_OFFER_COOLDOWN, returns(3600), &felts(&[3600])).await?;
+
+    let offer_at = |now| offer_args(10_000 * ONE_USDC, 1_000 * ONE_USDC, lp_id, now);
+    pool.call(pool_call::CREATE_OFFER, returns(0), &offer_at(now)).await?;
+    assert!(pool.call(pool_call::CREATE_OFFER, returns(1), &offer_at(now + 10)).await.is_err());
+    pool.call(pool_call::CREATE_OFFER, returns(1), &offer_at(now + 3600)).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_only_the_admin_sets_the_offer_cooldown() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let outsider = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET_1)?;
+    let mut pool = Harness::new(pool_account(usdc, outsider)?, "pool-call-note")?;
+    let lp_id = pool.sender_id;
+    let now = COOLDOWN_END;
+
+    let deposit = felts(&[100_000 * ONE_USDC]);
+    pool.call(pool_call::DEPOSIT_USDC, returns(100_000 * ONE_USDC), &deposit).await?;
+    let result = pool.call(pool_call::SET_OFFER_COOLDOWN, returns(3600), &felts(&[3600])).await;
+    assert!(result.is_err());
+
+    // With no cooldown in force, offers can follow each other straight away
+    let offer_at = |now| offer_args(10_000 * ONE_USDC, 1_000 * ONE_USDC, lp_id, now);
+    pool.call(pool_call::CREATE_OFFER, returns(0), &offer_at(now)).await?;
+    pool.call(pool_call::CREATE_OFFER, returns(1), &offer_at(now + 10)).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_offers_stop_at_the_max_safe_size() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let mut pool = pool_harness(usdc)?;
+    let lp_id = pool.sender_id;
+
+    let deposit = felts(&[100_000 * ONE_USDC]);
+    pool.call(pool_call::DEPOSIT_USDC, returns(100_000 * ONE_USDC), &deposit).await?;
+    pool.call(pool_call::SET_MIN_RESERVE, returns(1), &felts(&[20_000 * ONE_USDC])).await?;
+    pool.call(pool_call::MAX_SAFE_OFFER, returns(80_000 * ONE_USDC), &[]).await?;
+
+    let offer_of = |max| offer_args(max, 1_000 * ONE_USDC, lp_id, COOLDOWN_END);
+    let over = offer_of(80_000 * ONE_USDC + 1);
+    assert!(pool.call(pool_call::CREATE_OFFER, returns(0), &over).await.is_err());
+    pool.call(pool_call::CREATE_OFFER, returns(0), &offer_of(80_000 * ONE_USDC)).await?;
+    pool.call(pool_call::MAX_SAFE_OFFER, returns(0), &[]).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_max_safe_offer_leaves_out_outstanding_advances() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let staked = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET_1)?;
+    let mut pool = pool_with_deal(usdc, staked).await?;
+
+    // $90,500 available, $40,500 still reserved by offer 0, and the $9,500
+    // advanced out counts for nothing until it is repaid
+    pool.call(pool_call::GET_USDC_BALANCE, returns(90_500 * ONE_USDC), &[]).await?;
+    pool.call(pool_call::MAX_SAFE_OFFER, returns(50_000 * ONE_USDC), &[]).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reserved_liquidity_cannot_be_withdrawn() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let staked = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET_1)?;
+    let mut pool = pool_with_deal(usdc, staked).await?;
+
+    // Offer 0 still reserves $40,500 of the $90,500 available; the $9,500
+    // outstanding can't stand in for it
+    let too_much = felts(&[50_000 * ONE_USDC + 1]);
+    assert!(pool.call(pool_call::WITHDRAW_USDC, returns(0), &too_much).await.is_err());
+    let free = felts(&[50_000 * ONE_USDC]);
+    pool.call(pool_call::WITHDRAW_USDC, returns(40_500 * ONE_USDC), &free).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_only_the_admin_sets_the_min_reserve() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let outsider = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET_1)?;
+    let mut pool = Harness::new(pool_account(usdc, outsider)?, "pool-call-note")?;
+
+    let deposit = felts(&[100_000 * ONE_USDC]);
+    pool.call(pool_call::DEPOSIT_USDC, returns(100_000 * ONE_USDC), &deposit).await?;
+    let reserve = felts(&[20_000 * ONE_USDC]);
+    assert!(pool.call(pool_call::SET_MIN_RESERVE, returns(1), &reserve).await.is_err());
+    pool.call(pool_call::MAX_SAFE_OFFER, returns(100_000 * ONE_USDC), &[]).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancelled_request_secret_stays_spent() -> anyhow::Result<()> {
+    let mut user = Harness::new(user_account()?, "user-call-note")?;
+    user.call(user_call::DEPOSIT_STAKED_ASSETS, returns(1000), &felts(&[1000])).await?;
+    user.call(user_call::IS_NULLIFIER_SPENT, returns(0), &felts(&[11])).await?;
+
+    let request = unlock_request_args(400, COOLDOWN_END, 1, 11);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(0), &request).await?;
+    user.call(user_call::CANCEL_REQUEST, returns(1), &felts(&[0])).await?;
+
+    // Cancelling frees the assets but not the nullifier or secret, so the
+    // request can't be replayed
+    user.call(user_call::IS_NULLIFIER_SPENT, returns(1), &felts(&[11])).await?;
+    let replay = user.call(user_call::CREATE_UNLOCK_REQUEST, returns(1), &request).await;
+    assert!(replay.is_err());
+    user.call(user_call::GET_STAKED_BALANCE, returns(1000), &[]).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_batch_cancel_skips_matched_requests() -> anyhow::Result<()> {
+    let mut user = Harness::new(user_account()?, "user-call-note")?;
+    user.call(user_call::DEPOSIT_STAKED_ASSETS, returns(1000), &felts(&[1000])).await?;
+    for (request_id, amount) in [(0, 100), (1, 200), (2, 300)] {
+        let request = unlock_request_args(amount, COOLDOWN_END, request_id + 1, request_id + 11);
+        user.call(user_call::CREATE_UNLOCK_REQUEST, returns(request_id), &request).await?;
+    }
+    user.call(user_call::MARK_REQUEST_MATCHED, returns(1), &felts(&[1, 7, 7, 7, 7])).await?;
+    user.call(user_call::GET_STAKED_BALANCE, returns(400), &[]).await?;
+
+    // The matched request is skipped and the rest of the batch still runs
+    let batch = felts(&[0, 1, 2, 0, 100, 200, 300, 0]);
+    let mask = Word::from([Felt::new(1), Felt::new(0), Felt::new(1), Felt::new(0)]);
+    user.call(user_call::CANCEL_REQUESTS_BATCH, mask, &batch).await?;
+
+    user.call(user_call::GET_STAKED_BALANCE, returns(800), &[]).await?;
+    user.call(user_call::GET_REQUEST_STATUS, returns(4), &felts(&[0])).await?;
+    user.call(user_call::GET_REQUEST_STATUS, returns(2), &felts(&[1])).await?;
+    user.call(user_call::GET_REQUEST_STATUS, returns(4), &felts(&[2])).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancel_request_by_nullifier() -> anyhow::Result<()> {
+    let mut user = Harness::new(user_account()?, "user-call-note")?;
+    user.call(user_call::DEPOSIT_STAKED_ASSETS, returns(1000), &felts(&[1000])).await?;
+    let open = unlock_request_args(400, COOLDOWN_END, 1, 11);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(0), &open).await?;
+    let matched = unlock_request_args(200, COOLDOWN_END, 2, 12);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(1), &matched).await?;
+    user.call(user_call::MARK_REQUEST_MATCHED, returns(1), &felts(&[1, 7, 7, 7, 7])).await?;
+
+    let cancel = |nullifier, amount| felts(&[nullifier, amount]);
+    user.call(user_call::CANCEL_REQUEST_BY_NULLIFIER, returns(0), &cancel(99, 400)).await?;
+    user.call(user_call::CANCEL_REQUEST_BY_NULLIFIER, returns(0), &cancel(11, 300)).await?;
+    user.call(user_call::CANCEL_REQUEST_BY_NULLIFIER, returns(0), &cancel(12, 200)).await?;
+
+    // The locked assets come back, and only once
+    user.call(user_call::CANCEL_REQUEST_BY_NULLIFIER, returns(1), &cancel(11, 400)).await?;
+    user.call(user_call::GET_STAKED_BALANCE, returns(800), &[]).await?;
+    user.call(user_call::GET_REQUEST_STATUS, returns(4), &felts(&[0])).await?;
+    user.call(user_call::CANCEL_REQUEST_BY_NULLIFIER, returns(0), &cancel(11, 400)).await?;
+    user.call(user_call::GET_STAKED_BALANCE, returns(800), &[]).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_settlement_with_a_falsified_cooldown_end_is_rejected() -> anyhow::Result<()> {
+    let mut user = Harness::new(user_account()?, "user-call-note")?;
+    user.call(user_call::DEPOSIT_STAKED_ASSETS, returns(1000), &felts(&[1000])).await?;
+    let request = unlock_request_args(400, COOLDOWN_END, 1, 11);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(0), &request).await?;
+    user.call(user_call::MARK_REQUEST_MATCHED, returns(1), &felts(&[0, 7, 7, 7, 7])).await?;
+
+    // A note claiming the cooldown ended a day early, consumed at that time
+    let claimed_end = COOLDOWN_END - 24 * 60 * 60;
+    let falsified = felts(&[0, claimed_end, claimed_end]);
+    let result = user.call(user_call::AUTHORIZE_SETTLEMENT, returns(1), &falsified).await;
+    assert!(result.is_err());
+
+    let honest = felts(&[0, COOLDOWN_END, COOLDOWN_END]);
+    user.call(user_call::AUTHORIZE_SETTLEMENT, returns(1), &honest).await?;
+    user.call(user_call::MARK_SETTLED, returns(1), &honest).await?;
+
+    // Settled requests can't be authorized again
+    let result = user.call(user_call::AUTHORIZE_SETTLEMENT, returns(1), &honest).await;
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_matching_accumulates_fees_paid() -> anyhow::Result<()> {
+    let mut user = Harness::new(user_account()?, "user-call-note")?;
+    user.call(user_call::DEPOSIT_STAKED_ASSETS, returns(1000), &felts(&[1000])).await?;
+    let first = unlock_request_args(400, COOLDOWN_END, 1, 11);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(0), &first).await?;
+    let second = unlock_request_args(200, COOLDOWN_END, 2, 12);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(1), &second).await?;
+    user.call(user_call::TOTAL_FEES_PAID, returns(0), &[]).await?;
+
+    // 5% of each request's amount
+    user.call(user_call::MARK_REQUEST_MATCHED, returns(1), &felts(&[0, 7, 7, 7, 7])).await?;
+    user.call(user_call::TOTAL_FEES_PAID, returns(20), &[]).await?;
+    user.call(user_call::MARK_REQUEST_MATCHED, returns(1), &felts(&[1, 8, 8, 8, 8])).await?;
+    user.call(user_call::TOTAL_FEES_PAID, returns(30), &[]).await?;
+
+    // Marking a request matched again doesn't charge its fee twice
+    user.call(user_call::MARK_REQUEST_MATCHED, returns(1), &felts(&[0, 9, 9, 9, 9])).await?;
+    user.call(user_call::TOTAL_FEES_PAID, returns(30), &[]).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fees_round_down_on_amounts_not_a_multiple_of_twenty() -> anyhow::Result<()> {
+    let mut user = Harness::new(user_account()?, "user-call-note")?;
+    user.call(user_call::DEPOSIT_STAKED_ASSETS, returns(1000), &felts(&[1000])).await?;
+    let request = unlock_request_args(419, COOLDOWN_END, 1, 11);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(0), &request).await?;
+
+    // 5% of 419 is 20.95
+    user.call(user_call::MARK_REQUEST_MATCHED, returns(1), &felts(&[0, 7, 7, 7, 7])).await?;
+    user.call(user_call::TOTAL_FEES_PAID, returns(20), &[]).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_only_live_requests_can_be_matched() -> anyhow::Result<()> {
+    let mut user = Harness::new(user_account()?, "user-call-note")?;
+    user.call(user_call::DEPOSIT_STAKED_ASSETS, returns(1000), &felts(&[1000])).await?;
+    let request = unlock_request_args(400, COOLDOWN_END, 1, 11);
+    user.call(user_call::CREATE_UNLOCK_REQUEST, returns(0), &request).await?;
+    user.call(user_call::CANCEL_REQUEST, returns(1), &felts(&[0])).await?;
+
+    // Neither the cancelled request nor one never created can be matched
+    let lp_commitment = |request_id| felts(&[request_id, 7, 7, 7, 7]);
+    let cancelled = user.call(user_call::MARK_REQUEST_MATCHED, returns(1), &lp_commitment(0)).await;
+    assert!(cancelled.is_err());
+    let unknown = user.call(user_call::MARK_REQUEST_MATCHED, returns(1), &lp_commitment(1)).await;
+    assert!(unknown.is_err());
+    user.call(user_call::GET_REQUEST_STATUS, returns(4), &felts(&[0])).await?;
+    user.call(user_call::TOTAL_FEES_PAID, returns(0), &[]).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_settlement_note_settles_only_after_the_cooldown() -> anyhow::Result<()> {
+    let mut user = Harness::new(user_account()?, "user-call-note")?;
+    user.call(user_call::DEPOSIT_STAKED_ASSETS, returns(1000), &felts(&[1000])).await?;
+
+    // Cooldowns either side of the mock chain's block timestamps, one ended
+    // recently enough to settle and one whose settlement window has closed
+    let now = u64::from(user.chain.latest_block_header().timestamp());
+    let (ended, pending) = (now - 60 * 60, now + 30 * 24 * 60 * 60);
+    let lapsed = now - SETTLEMENT_GRACE_SECONDS - 60 * 60;
+    for (request_id, cooldown_end) in [(0, ended), (1, pending), (2, lapsed)] {
+        let request = unlock_request_args(300, cooldown_end, request_id + 1, request_id + 11);
+        user.call(user_call::CREATE_UNLOCK_REQUEST, returns(request_id), &request).await?;
+        let lp_commitment = felts(&[request_id, 7, 7, 7, 7]);
+        user.call(user_call::MARK_REQUEST_MATCHED, returns(1), &lp_commitment).await?;
+    }
+
+    let sender_id = user.sender_id;
+    let settlement = |request_id, cooldown_end| -> anyhow::Result<Note> {
+        let config = settlement_note_config(
+            Felt::new(request_id),
+            Felt::new(300),
+            Felt::new(cooldown_end),
+            Felt::new(request_id),
+        );
+        Ok(create_testing_note(note_script("settlement-note")?, sender_id, config)?)
+    };
+
+    let early = settlement(1, pending)?;
+    assert!(consume(&mut user.chain, user.account_id, early).await.is_err());
+    user.call(user_call::GET_REQUEST_STATUS, returns(2), &felts(&[1])).await?;
+
+    // A note claiming the pending request's cooldown already ended
+    let falsified = settlement(1, ended)?;
+    assert!(consume(&mut user.chain, user.account_id, falsified).await.is_err());
+
+    // Too late: the pool may already have defaulted the deal
+    let late = settlement(2, lapsed)?;
+    assert!(consume(&mut user.chain, user.account_id, late).await.is_err());
+    user.call(user_call::GET_REQUEST_STATUS, returns(2), &felts(&[2])).await?;
+
+    let due = settlement(0, ended)?;
+    consume(&mut user.chain, user.account_id, due).await?;
+    user.call(user_call::GET_REQUEST_STATUS, returns(3), &felts(&[0])).await?;
+
+    // The same settlement can't go through twice
+    let repeat = settlement(0, ended)?;
+    assert!(consume(&mut user.chain, user.account_id, repeat).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dead_offers_page_from_a_start_index() -> anyhow::Result<()> {
+    let usdc = AccountId::try_from(ACCOUNT_ID_PUBLIC_FUNGIBLE_FAUCET)?;
+    let mut pool = pool_harness(usdc)?;
+    let lp_id = pool.sender_id;
+
+    let deposit = felts(&[100_000 * ONE_USDC]);
+    pool.call(pool_call::DEPOSIT_USDC, returns(100_000 * ONE_USDC), &deposit).await?;
+    let live = offer_args(10_000 * ONE_USDC, 1_000 * ONE_USDC, lp_id, COOLDOWN_END);
+    pool.call(pool_call::CREATE_OFFER, returns(0), &live).await?;
+    // A min above the max can never match
+    let dead = offer_args(1_000 * ONE_USDC, 5_000 * ONE_USDC, lp_id, COOLDOWN_END);
+    pool.call(pool_call::CREATE_OFFER, returns(1), &dead).await?;
+
+    // Bits count from the start index, so offer 1 moves with it
+    let mask = |bits| Word::from([Felt::new(bits), Felt::new(0), Felt::new(0), Felt::new(0)]);
+    pool.call(pool_call::GET_DEAD_OFFERS, mask(0b10), &felts(&[0])).await?;
+    pool.call(pool_call::GET_DEAD_OFFERS, mask(0b1), &felts(&[1])).await?;
+    pool.call(pool_call::GET_DEAD_OFFERS, mask(0), &felts(&[2])).await?;
+    Ok(())
+}
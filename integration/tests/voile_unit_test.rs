@@ -1,20 +1,52 @@
 //! Voile Protocol - Unit Tests
 //! Tests the off-chain matching logic and pricing calculations
 
+use integration::helpers::NoteCreationConfig;
 use integration::voile_helpers::{
-    cooldown_end_timestamp, LpOffer, MatchingEngine, PricingCalculator, UnlockRequest,
-    DEFAULT_COOLDOWN_SECONDS, LP_FEE_BPS, ONE_USDC, PROTOCOL_FEE_BPS,
+    account_id_word, advance_note_config, build_offer_tree, check_note_matches_deal,
+    classify_note_error, cooldown_end_timestamp, current_timestamp, decode_earnings_event,
+    execute_match, expected_advance_recipient, export_ledger, felt_to_u64_checked,
+    fetch_request_events, format_display, format_units, insurance_fund_target, liquidity_runway,
+    lock_request_for_matching, optimal_apr_bps, plan_settlement_batches, request_commitment,
+    settled_deals, settlement_note_config, settlement_note_config_with_assets, simulate_settlement,
+    stress_test, user_net_position, validate_transaction_request, verify_commitments,
+    verify_offer_proof, DayCount, DealReceipt, DealStatus, DealView, DisputeFinding, EarningsEvent,
+    LedgerBackend, LedgerEntry, LpOffer, MatchBackend, MatchLocks, MatchedDeal, MatchingEngine,
+    NoMatchReason, NoteFailure, OfferBackend, OfferDenomination, OfferView, OverlapPolicy,
+    PoolHealth, PricingCalculator, ProtocolConfig, RequestBackend, RequestEvent,
+    RequestEventBackend, RequestEventKind, RequestIdAllocator, RequestView, SettledDeal,
+    SettlementView, StressEvent, StressResult, StressScenario, UnlockRequest, VoileNoteKind,
+    ADVANCE_NOTE_USER_COMMITMENT_INPUT, DEFAULT_APR_BPS, DEFAULT_COOLDOWN_SECONDS, LP_FEE_BPS,
+    MIN_MARGIN_APR_BPS, ONE_USDC, PROTOCOL_FEE_BPS,
 };
 
+use anyhow::{anyhow, bail, Result};
 use miden_client::account::{AccountId, AccountStorageMode, AccountType};
+use miden_client::asset::{Asset, FungibleAsset};
+use miden_client::crypto::Rpo256;
+use miden_client::transaction::TransactionRequestBuilder;
+use miden_client::{Felt, Word};
 use miden_protocol::account::AccountIdVersion;
 use rand::rngs::StdRng;
 use rand::SeedableRng;
 
 fn mock_account_id() -> AccountId {
+    mock_account_id_from(0)
+}
+
+fn mock_faucet_id_from(seed: u8) -> AccountId {
+    AccountId::dummy(
+        [seed; 15],
+        AccountIdVersion::Version0,
+        AccountType::FungibleFaucet,
+        AccountStorageMode::Public,
+    )
+}
+
+fn mock_account_id_from(seed: u8) -> AccountId {
     // Create a mock account ID for testing
     AccountId::dummy(
-        [0u8; 15],
+        [seed; 15],
         AccountIdVersion::Version0,
         AccountType::RegularAccountImmutableCode,
         AccountStorageMode::Public,
@@ -89,8 +121,8 @@ fn test_lp_offer_creation() {
     );
 
     assert_eq!(offer.offer_id, 1);
-    assert_eq!(offer.max_amount, 100_000 * ONE_USDC);
-    assert_eq!(offer.min_amount, 1_000 * ONE_USDC);
+    assert_eq!(offer.max_amount(), 100_000 * ONE_USDC);
+    assert_eq!(offer.min_amount(), 1_000 * ONE_USDC);
     assert_eq!(offer.custom_apr_bps, Some(900));
     assert!(offer.is_active);
 
@@ -124,7 +156,7 @@ fn test_matching_engine() {
     let request = UnlockRequest::new(1, request_amount, cooldown_end, user_account_id, &mut rng);
 
     // Find matches
-    let matches = engine.find_matches(&request);
+    let matches = engine.find_matches(&request, current_timestamp());
     assert_eq!(matches.len(), 3); // All three should match
 
     // Best match should be offer2 (lowest APR)
@@ -132,7 +164,7 @@ fn test_matching_engine() {
     assert_eq!(matches[0].custom_apr_bps, Some(800));
 
     // Match request
-    let deal = engine.match_request(request.clone(), &mut rng);
+    let deal = engine.match_request(request.clone(), current_timestamp(), &mut rng);
     assert!(deal.is_some());
 
     let deal = deal.unwrap();
@@ -155,7 +187,7 @@ fn test_matched_deal_earnings() {
     let mut engine = MatchingEngine::new();
     engine.add_offer(offer);
 
-    let deal = engine.match_request(request, &mut rng).unwrap();
+    let deal = engine.match_request(request, current_timestamp(), &mut rng).unwrap();
 
     // LP earnings
     let (lp_fee, interest) = deal.lp_earnings(14);
@@ -186,9 +218,1760 @@ fn test_no_matching_offers() {
     let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
     let request = UnlockRequest::new(1, request_amount, cooldown_end, user_account_id, &mut rng);
 
-    let matches = engine.find_matches(&request);
+    let matches = engine.find_matches(&request, current_timestamp());
     assert!(matches.is_empty());
 
-    let deal = engine.match_request(request, &mut rng);
+    let deal = engine.match_request(request, current_timestamp(), &mut rng);
     assert!(deal.is_none());
 }
+
+#[test]
+fn test_overlapping_offers_coalesced() {
+    let lp_account_id = mock_account_id();
+    let other_lp_account_id = mock_account_id_from(1);
+
+    let mut engine = MatchingEngine::with_overlap_policy(OverlapPolicy::Coalesce);
+    assert!(engine.add_offer(LpOffer::new(1, lp_account_id, 50_000 * ONE_USDC, 1_000 * ONE_USDC, None)));
+    assert!(engine.add_offer(LpOffer::new(2, lp_account_id, 80_000 * ONE_USDC, 10_000 * ONE_USDC, None)));
+    // A different LP with the same range is kept separately
+    assert!(engine.add_offer(LpOffer::new(3, other_lp_account_id, 50_000 * ONE_USDC, 1_000 * ONE_USDC, None)));
+
+    assert_eq!(engine.offers.len(), 2);
+    let merged = &engine.offers[0];
+    assert_eq!(merged.offer_id, 1);
+    assert_eq!(merged.min_amount(), 1_000 * ONE_USDC);
+    assert_eq!(merged.max_amount(), 80_000 * ONE_USDC);
+    assert!(merged.can_match(70_000 * ONE_USDC));
+}
+
+#[test]
+fn test_overlapping_offers_rejected() {
+    let lp_account_id = mock_account_id();
+
+    let mut engine = MatchingEngine::with_overlap_policy(OverlapPolicy::Reject);
+    assert!(engine.add_offer(LpOffer::new(1, lp_account_id, 50_000 * ONE_USDC, 1_000 * ONE_USDC, None)));
+    assert!(!engine.add_offer(LpOffer::new(2, lp_account_id, 50_000 * ONE_USDC, 1_000 * ONE_USDC, None)));
+    // Disjoint ranges from the same LP are fine
+    assert!(engine.add_offer(LpOffer::new(3, lp_account_id, 90_000 * ONE_USDC, 60_000 * ONE_USDC, None)));
+
+    assert_eq!(engine.offers.len(), 2);
+}
+
+#[test]
+fn test_max_apr_cap() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let user_account_id = mock_account_id();
+    let lp_account_id = mock_account_id();
+
+    let mut engine = MatchingEngine::new();
+    engine.add_offer(LpOffer::new(1, lp_account_id, 100_000 * ONE_USDC, 1_000 * ONE_USDC, Some(1200))); // 12%
+    engine.add_offer(LpOffer::new(2, lp_account_id, 100_000 * ONE_USDC, 1_000 * ONE_USDC, Some(900))); // 9%
+
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+
+    // 5% cap rules out every offer
+    let request = UnlockRequest::new(1, 25_000 * ONE_USDC, cooldown_end, user_account_id, &mut rng)
+        .with_max_apr(500);
+    let result = engine.try_match_request(request.clone(), current_timestamp(), &mut rng);
+    assert_eq!(result.unwrap_err(), NoMatchReason::AllTooExpensive);
+    assert!(engine.match_request(request, current_timestamp(), &mut rng).is_none());
+
+    // 10% cap admits the 9% offer
+    let request = UnlockRequest::new(2, 25_000 * ONE_USDC, cooldown_end, user_account_id, &mut rng)
+        .with_max_apr(1000);
+    let deal = engine.try_match_request(request, current_timestamp(), &mut rng).unwrap();
+    assert_eq!(deal.offer.offer_id, 2);
+}
+
+#[test]
+fn test_preview_match_does_not_consume_capacity() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let user_account_id = mock_account_id();
+    let lp_account_id = mock_account_id();
+
+    let mut engine = MatchingEngine::new();
+    engine.add_offer(LpOffer::new(1, lp_account_id, 100_000 * ONE_USDC, 1_000 * ONE_USDC, Some(900)));
+
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+    let request = UnlockRequest::new(1, 25_000 * ONE_USDC, cooldown_end, user_account_id, &mut rng);
+
+    let preview = engine.preview_match(&request, current_timestamp()).unwrap();
+    assert_eq!(preview.offer_id, 1);
+    assert_eq!(preview.advance_amount, request.net_advance());
+    assert_eq!(preview.fee, request.advance_fee());
+    assert_eq!(preview.interest, PricingCalculator::apr_interest_at(request.amount, 900, 14));
+    assert_eq!(engine.offers[0].remaining_amount(), 100_000 * ONE_USDC);
+
+    let deal = engine.match_request_mut(request, current_timestamp(), &mut rng).unwrap();
+    assert_eq!(deal.offer.offer_id, preview.offer_id);
+    assert_eq!(deal.advance_amount, preview.advance_amount);
+    assert_eq!(
+        engine.offers[0].remaining_amount(),
+        100_000 * ONE_USDC - preview.advance_amount
+    );
+}
+
+#[test]
+fn test_settlement_note_carries_staked_asset() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let user_account_id = mock_account_id();
+    let staked_faucet_id = mock_faucet_id_from(1);
+    let other_faucet_id = mock_faucet_id_from(2);
+
+    let mut engine = MatchingEngine::new();
+    engine.add_offer(LpOffer::new(1, mock_account_id(), 100_000 * ONE_USDC, 1_000 * ONE_USDC, None));
+
+    let request_amount = 10_000 * ONE_USDC;
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+    let request = UnlockRequest::new(1, request_amount, cooldown_end, user_account_id, &mut rng)
+        .with_staked_asset(staked_faucet_id);
+    let deal = engine.match_request(request, current_timestamp(), &mut rng).unwrap();
+
+    let staked_asset = FungibleAsset::new(staked_faucet_id, request_amount).unwrap();
+    let config = settlement_note_config_with_assets(&deal, staked_asset).unwrap();
+    assert_eq!(config.assets.num_assets(), 1);
+    assert_eq!(config.assets.iter().next(), Some(&Asset::Fungible(staked_asset)));
+    assert_eq!(config.inputs[3], deal.deal_id[0]);
+
+    // Wrong collateral type or amount is rejected
+    let wrong_faucet = FungibleAsset::new(other_faucet_id, request_amount).unwrap();
+    assert!(settlement_note_config_with_assets(&deal, wrong_faucet).is_err());
+    let short_amount = FungibleAsset::new(staked_faucet_id, request_amount - 1).unwrap();
+    assert!(settlement_note_config_with_assets(&deal, short_amount).is_err());
+}
+
+#[test]
+fn test_request_nullifier() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let account_id = mock_account_id();
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+
+    let request = UnlockRequest::new(1, 10_000 * ONE_USDC, cooldown_end, account_id, &mut rng);
+    let other = UnlockRequest::new(2, 10_000 * ONE_USDC, cooldown_end, account_id, &mut rng);
+
+    // Stable for a request, distinct across requests, and never the value
+    // exposed in the public commitment
+    assert_eq!(request.nullifier(), request.nullifier());
+    assert_ne!(request.nullifier(), other.nullifier());
+    assert_ne!(request.nullifier(), request.commitment[2]);
+
+    // A restored copy of the same secret yields the same nullifier, which the
+    // account rejects on the second create_unlock_request
+    let mut replay = other.clone();
+    replay.nullifier_secret = request.nullifier_secret;
+    assert_eq!(replay.nullifier(), request.nullifier());
+}
+
+/// In-memory pool standing in for the on-chain match transactions
+#[derive(Default)]
+struct StubMatchBackend {
+    fail_publish: bool,
+    open_deals: Vec<Felt>,
+    published_notes: Vec<NoteCreationConfig>,
+    next_deal_id: u64,
+}
+
+impl MatchBackend for StubMatchBackend {
+    async fn accept_match(&mut self, _pool_account_id: AccountId, _deal: &MatchedDeal) -> Result<Felt> {
+        let deal_id = Felt::new(self.next_deal_id);
+        self.next_deal_id += 1;
+        self.open_deals.push(deal_id);
+        Ok(deal_id)
+    }
+
+    async fn publish_advance_note(
+        &mut self,
+        _pool_account_id: AccountId,
+        _user_account_id: AccountId,
+        config: NoteCreationConfig,
+    ) -> Result<Word> {
+        if self.fail_publish {
+            bail!("note rejected by node");
+        }
+        let hash = Word::from([config.inputs[0], config.inputs[1], config.inputs[2], config.inputs[3]]);
+        self.published_notes.push(config);
+        Ok(hash)
+    }
+
+    async fn cancel_match(&mut self, _pool_account_id: AccountId, pool_deal_id: Felt) -> Result<()> {
+        self.open_deals.retain(|deal_id| *deal_id != pool_deal_id);
+        Ok(())
+    }
+}
+
+fn matched_deal(rng: &mut StdRng) -> MatchedDeal {
+    let mut engine = MatchingEngine::new();
+    engine.add_offer(LpOffer::new(7, mock_account_id(), 100_000 * ONE_USDC, 1_000 * ONE_USDC, None));
+
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+    let request = UnlockRequest::new(1, 10_000 * ONE_USDC, cooldown_end, mock_account_id(), rng);
+    engine.match_request(request, current_timestamp(), rng).unwrap()
+}
+
+#[tokio::test]
+async fn test_execute_match() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let deal = matched_deal(&mut rng);
+    let user_account_id = mock_account_id();
+    let pool_account_id = mock_account_id_from(1);
+
+    let mut backend = StubMatchBackend::default();
+    let receipt = execute_match(&mut backend, user_account_id, pool_account_id, &deal)
+        .await
+        .unwrap();
+
+    assert_eq!(receipt.deal_id, deal.deal_id);
+    assert_eq!(receipt.pool_deal_id, Felt::new(0));
+    assert_eq!(receipt.advance_amount, deal.advance_amount);
+    assert_eq!(backend.open_deals, vec![receipt.pool_deal_id]);
+
+    let note = &backend.published_notes[0];
+    assert_eq!(note.inputs[0], Felt::new(deal.advance_amount));
+    assert_eq!(note.inputs[1], receipt.pool_deal_id);
+    assert_eq!(note.inputs[2], Felt::new(7));
+    assert_eq!(note.inputs[3], deal.request.commitment[0]);
+}
+
+#[tokio::test]
+async fn test_execute_match_cancels_on_publish_failure() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let deal = matched_deal(&mut rng);
+
+    let mut backend = StubMatchBackend {
+        fail_publish: true,
+        ..Default::default()
+    };
+    let result = execute_match(&mut backend, mock_account_id(), mock_account_id_from(1), &deal).await;
+
+    assert!(result.is_err());
+    assert!(backend.open_deals.is_empty());
+    assert!(backend.published_notes.is_empty());
+}
+
+#[test]
+fn test_expected_advance_recipient() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let deal = matched_deal(&mut rng);
+
+    let config = advance_note_config(
+        Felt::new(deal.advance_amount),
+        Felt::new(0),
+        Felt::new(deal.offer.offer_id),
+        deal.request.commitment[0],
+    );
+
+    let expected = expected_advance_recipient(&deal);
+    assert_eq!(expected, deal.request.commitment);
+    assert_eq!(config.inputs[ADVANCE_NOTE_USER_COMMITMENT_INPUT], expected[0]);
+
+    // A note built for a different request doesn't check out
+    let other = matched_deal(&mut rng);
+    assert_ne!(config.inputs[ADVANCE_NOTE_USER_COMMITMENT_INPUT], expected_advance_recipient(&other)[0]);
+}
+
+#[test]
+fn test_stale_offers() {
+    let lp_account_id = mock_account_id();
+
+    let mut engine = MatchingEngine::new();
+    engine.add_offer(LpOffer::new(1, lp_account_id, 100_000 * ONE_USDC, 1_000 * ONE_USDC, Some(950))); // near
+    engine.add_offer(LpOffer::new(2, lp_account_id, 100_000 * ONE_USDC, 1_000 * ONE_USDC, Some(1500))); // far above
+    engine.add_offer(LpOffer::new(3, lp_account_id, 100_000 * ONE_USDC, 1_000 * ONE_USDC, Some(400))); // far below
+    engine.add_offer(LpOffer::new(4, lp_account_id, 100_000 * ONE_USDC, 1_000 * ONE_USDC, None)); // default 10%
+
+    // Reference 9% with 1% tolerance
+    assert_eq!(engine.stale_offers(900, 100), vec![2, 3]);
+
+    // Inactive offers are not reported
+    engine.offers[1].is_active = false;
+    assert_eq!(engine.stale_offers(900, 100), vec![3]);
+}
+
+#[test]
+fn test_total_user_cost_includes_forgone_staking() {
+    let principal = 3000 * ONE_USDC;
+    let days = 14;
+
+    let fee = PricingCalculator::advance_fee(principal);
+    let interest = PricingCalculator::apr_interest(principal, days);
+    let total = PricingCalculator::total_user_cost(principal, days, 400); // 4% staking APR
+
+    // 3000 * 400 * 14 / (10000 * 365) ≈ $4.60 of forgone rewards
+    let forgone = PricingCalculator::apr_interest_at(principal, 400, days);
+    assert!(forgone > 4 * ONE_USDC && forgone < 5 * ONE_USDC);
+    assert_eq!(total, fee + interest + forgone);
+    assert!(total > fee + interest);
+
+    // No staking yield means no opportunity cost
+    assert_eq!(PricingCalculator::total_user_cost(principal, days, 0), fee + interest);
+}
+
+#[test]
+fn test_request_commitment_matches_contract_layout() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let account_id = mock_account_id();
+    let amount = 10_000 * ONE_USDC;
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+
+    let request = UnlockRequest::new(1, amount, cooldown_end, account_id, &mut rng);
+
+    // Same elements, in the same order, that create_unlock_request hashes
+    let onchain = Rpo256::hash_elements(&[
+        Felt::new(amount),
+        Felt::new(cooldown_end),
+        request.commitment_secret(),
+        account_id.prefix().as_felt(),
+        account_id.suffix(),
+    ]);
+
+    assert_eq!(request.commitment, onchain);
+    assert_eq!(
+        request_commitment(amount, cooldown_end, &request.nullifier_secret, account_id),
+        onchain
+    );
+
+    // Changing any value changes the commitment
+    assert_ne!(
+        request_commitment(amount + 1, cooldown_end, &request.nullifier_secret, account_id),
+        onchain
+    );
+}
+
+#[test]
+fn test_apr_ceiling_filters_matches() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let user_account_id = mock_account_id();
+    let lp_account_id = mock_account_id();
+
+    let mut engine = MatchingEngine::new();
+    engine.add_offer(LpOffer::new(1, lp_account_id, 100_000 * ONE_USDC, 1_000 * ONE_USDC, Some(1100))); // 11%
+    engine.add_offer(LpOffer::new(2, lp_account_id, 100_000 * ONE_USDC, 1_000 * ONE_USDC, Some(1200))); // 12%
+
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+
+    // Every offer is above a 10% ceiling
+    let request = UnlockRequest::new(1, 25_000 * ONE_USDC, cooldown_end, user_account_id, &mut rng)
+        .with_max_apr(1000);
+    assert!(engine.find_matches(&request, current_timestamp()).is_empty());
+    assert!(engine.match_request(request, current_timestamp(), &mut rng).is_none());
+
+    // Only the 9.5% offer fits under the ceiling
+    engine.add_offer(LpOffer::new(3, lp_account_id, 100_000 * ONE_USDC, 1_000 * ONE_USDC, Some(950)));
+    let request = UnlockRequest::new(2, 25_000 * ONE_USDC, cooldown_end, user_account_id, &mut rng)
+        .with_max_apr(1000);
+    let matches = engine.find_matches(&request, current_timestamp());
+    assert_eq!(matches.len(), 1);
+    assert_eq!(matches[0].offer_id, 3);
+    assert!(matches[0].can_match_request(&request));
+    assert!(!engine.offers[0].can_match_request(&request));
+}
+
+#[test]
+fn test_seeded_match_is_reproducible() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let user_account_id = mock_account_id();
+
+    let mut engine = MatchingEngine::new();
+    engine.add_offer(LpOffer::new(1, mock_account_id(), 100_000 * ONE_USDC, 1_000 * ONE_USDC, None));
+
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+    let request = UnlockRequest::new(1, 10_000 * ONE_USDC, cooldown_end, user_account_id, &mut rng);
+    let now = current_timestamp();
+
+    let first = engine.match_request_seeded(request.clone(), now, [7u8; 32]).unwrap();
+    let second = engine.match_request_seeded(request.clone(), now, [7u8; 32]).unwrap();
+    let other = engine.match_request_seeded(request, now, [8u8; 32]).unwrap();
+
+    assert_eq!(first.deal_id, second.deal_id);
+    assert_ne!(first.deal_id, other.deal_id);
+}
+
+#[test]
+fn test_min_profitable_amount() {
+    // $50 proving cost per settlement, 10% APR over 14 days
+    let fixed_cost = 50 * ONE_USDC;
+    let threshold = LpOffer::min_profitable_amount(fixed_cost, 1000, 14);
+
+    // LP earns 4% fee share + ~0.38% interest, so the break-even is ~$1,140
+    assert!(threshold > 1_100 * ONE_USDC && threshold < 1_200 * ONE_USDC);
+    assert!(PricingCalculator::lp_earnings(threshold, 1000, 14) > fixed_cost);
+
+    // Just below the threshold the settlement cost eats the earnings
+    let below = PricingCalculator::lp_earnings(threshold - 1, 1000, 14) as i64 - fixed_cost as i64;
+    assert!(below <= 0);
+}
+
+#[test]
+fn test_account_id_word() {
+    let lp_account_id = mock_account_id_from(3);
+    let other_account_id = mock_account_id_from(4);
+
+    // The word get_offer_lp returns for an offer created by this LP
+    let word = account_id_word(lp_account_id);
+    assert_eq!(word[0], lp_account_id.prefix().as_felt());
+    assert_eq!(word[1], lp_account_id.suffix());
+    assert_eq!(word[2], Felt::new(0));
+    assert_eq!(word[3], Felt::new(0));
+
+    assert_ne!(word, account_id_word(other_account_id));
+}
+
+#[test]
+fn test_verify_commitments_batch() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let account_id = mock_account_id();
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+
+    let valid = UnlockRequest::new(1, 10_000 * ONE_USDC, cooldown_end, account_id, &mut rng);
+    let other = UnlockRequest::new(2, 20_000 * ONE_USDC, cooldown_end, account_id, &mut rng);
+
+    // Amount changed after the commitment was made
+    let mut tampered = UnlockRequest::new(3, 5_000 * ONE_USDC, cooldown_end, account_id, &mut rng);
+    let tampered_commitment = tampered.commitment;
+    tampered.amount += 1;
+
+    let batch = vec![
+        (valid.clone(), valid.commitment),
+        (tampered, tampered_commitment),
+        (other.clone(), other.commitment),
+        // Commitment belonging to a different request
+        (valid, other.commitment),
+    ];
+
+    assert_eq!(verify_commitments(&batch), vec![true, false, true, false]);
+    assert!(verify_commitments(&[]).is_empty());
+}
+
+#[test]
+fn test_felt_to_u64_checked() {
+    assert_eq!(felt_to_u64_checked(Felt::new(25_000 * ONE_USDC)), Some(25_000 * ONE_USDC));
+    assert_eq!(felt_to_u64_checked(Felt::new(0)), Some(0));
+
+    // A balance subtracted below zero wraps to the top of the field
+    let wrapped = Felt::new(5 * ONE_USDC) - Felt::new(6 * ONE_USDC);
+    assert_eq!(felt_to_u64_checked(wrapped), None);
+}
+
+#[test]
+fn test_offer_commitment_cached_until_update() {
+    let mut offer = LpOffer::new(1, mock_account_id(), 100_000 * ONE_USDC, 1_000 * ONE_USDC, None);
+
+    let commitment = offer.commitment();
+    assert_eq!(offer.commitment(), commitment);
+    assert_eq!(offer.clone().commitment(), commitment);
+
+    // Changing the range recomputes the commitment and cuts back capacity
+    // the new max no longer allows
+    offer.update(80_000 * ONE_USDC, 1_000 * ONE_USDC);
+    assert_eq!(offer.max_amount(), 80_000 * ONE_USDC);
+    assert_eq!(offer.remaining_amount(), 80_000 * ONE_USDC);
+    let updated = offer.commitment();
+    assert_ne!(updated, commitment);
+    let fresh = LpOffer::new(1, mock_account_id(), 80_000 * ONE_USDC, 1_000 * ONE_USDC, None);
+    assert_eq!(updated, fresh.commitment());
+}
+
+#[test]
+fn test_cheapest_offer_for() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let lp_account_id = mock_account_id();
+    let now = 1_700_000_000;
+    let mut request = |amount| {
+        let cooldown_end = now + 14 * 24 * 60 * 60;
+        UnlockRequest::new(1, amount, cooldown_end, mock_account_id_from(1), &mut rng)
+    };
+
+    let mut engine = MatchingEngine::new();
+    engine.add_offer(LpOffer::new(1, lp_account_id, 100_000 * ONE_USDC, 1_000 * ONE_USDC, Some(1100))); // 11%
+    engine.add_offer(LpOffer::new(2, lp_account_id, 100_000 * ONE_USDC, 1_000 * ONE_USDC, Some(850))); // 8.5%
+    engine.add_offer(LpOffer::new(3, lp_account_id, 20_000 * ONE_USDC, 1_000 * ONE_USDC, Some(500))); // 5%, too small
+    engine.add_offer(LpOffer::new(4, lp_account_id, 100_000 * ONE_USDC, 1_000 * ONE_USDC, None)); // default 10%
+
+    let cheapest = engine.cheapest_offer_for(&request(25_000 * ONE_USDC), now).unwrap();
+    assert_eq!(cheapest.offer_id, 2);
+
+    // The small offer wins once it can fund the amount
+    let small = request(10_000 * ONE_USDC);
+    assert_eq!(engine.cheapest_offer_for(&small, now).unwrap().offer_id, 3);
+
+    // Nothing covers this amount
+    assert!(engine.cheapest_offer_for(&request(500_000 * ONE_USDC), now).is_none());
+
+    // Nor is any offer within this user's APR cap
+    let capped = request(25_000 * ONE_USDC).with_max_apr(800);
+    assert!(engine.cheapest_offer_for(&capped, now).is_none());
+}
+
+#[test]
+fn test_time_to_liquidity() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let user_account_id = mock_account_id();
+    let now = current_timestamp();
+
+    let mut engine = MatchingEngine::new();
+    engine.add_offer(LpOffer::new(1, mock_account_id(), 30_000 * ONE_USDC, 1_000 * ONE_USDC, None));
+
+    // Two deals draw the offer down to $3,400
+    let first_end = now + 7 * 24 * 60 * 60;
+    let second_end = now + DEFAULT_COOLDOWN_SECONDS;
+    let first = UnlockRequest::new(1, 10_000 * ONE_USDC, first_end, user_account_id, &mut rng);
+    let second = UnlockRequest::new(2, 18_000 * ONE_USDC, second_end, user_account_id, &mut rng);
+    engine.match_request_mut(second, current_timestamp(), &mut rng).unwrap();
+    engine.match_request_mut(first, current_timestamp(), &mut rng).unwrap();
+    assert_eq!(engine.offers[0].remaining_amount(), 3_400 * ONE_USDC);
+
+    // Fits in what's left
+    assert_eq!(engine.time_to_liquidity(2_000 * ONE_USDC, now), Some(now));
+
+    // Needs the first settlement to free its $9,500
+    assert_eq!(engine.time_to_liquidity(10_000 * ONE_USDC, now), Some(first_end));
+
+    // Needs both settlements
+    assert_eq!(engine.time_to_liquidity(25_000 * ONE_USDC, now), Some(second_end));
+
+    // Above every offer's range
+    assert_eq!(engine.time_to_liquidity(50_000 * ONE_USDC, now), None);
+}
+
+#[test]
+fn test_amounts_format_with_declared_decimals() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+
+    // Defaults to USDC's 6 decimals
+    let offer = LpOffer::new(1, mock_account_id(), 100_000 * ONE_USDC, 1_000 * ONE_USDC, None);
+    assert_eq!(offer.decimals, 6);
+    assert_eq!(offer.format_amount(1_500_000), "1.500000");
+
+    let two_decimal_offer = offer.clone().with_decimals(2);
+    assert_eq!(two_decimal_offer.format_amount(1_500_000), "15000.00");
+
+    let request = UnlockRequest::new(1, 5 * 10u64.pow(18), cooldown_end, mock_account_id(), &mut rng)
+        .with_decimals(18);
+    assert_eq!(request.format_amount(request.amount), "5.000000000000000000");
+
+    assert_eq!(format_units(42, 0), "42");
+    assert_eq!(format_units(5, 3), "0.005");
+}
+
+#[test]
+fn test_simulate_settlement() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let deal = matched_deal(&mut rng);
+    let amount = 10_000 * ONE_USDC;
+
+    let outcome = simulate_settlement(&deal, DEFAULT_COOLDOWN_SECONDS, PROTOCOL_FEE_BPS);
+
+    // Same figures record_settlement applies to the pool: staked assets in,
+    // the advance released from outstanding, 4/5 of the fee earned
+    assert_eq!(outcome.pool_balance_delta, amount);
+    assert_eq!(outcome.outstanding_released, deal.advance_amount);
+    assert_eq!(outcome.lp_fee, deal.request.advance_fee() * 4 / 5);
+    assert_eq!(outcome.protocol_fee, deal.protocol_earnings());
+    assert_eq!(outcome.lp_fee + outcome.protocol_fee, deal.request.advance_fee());
+    assert_eq!(outcome.user_locked_released, amount);
+
+    // Interest follows the actual cooldown, rounded up to whole days, but
+    // never past the agreed one, which is all record_settlement accepts
+    assert_eq!(outcome.lp_interest, deal.lp_earnings(14).1);
+    let late = simulate_settlement(&deal, DEFAULT_COOLDOWN_SECONDS + 1, PROTOCOL_FEE_BPS);
+    assert_eq!(late.lp_interest, PricingCalculator::apr_interest(amount, 14));
+    let short = simulate_settlement(&deal, 7 * 24 * 60 * 60, PROTOCOL_FEE_BPS);
+    assert_eq!(short.lp_interest, PricingCalculator::apr_interest(amount, 7));
+
+    // A partial fill settles only the principal it funded
+    let mut partial = deal.clone();
+    partial.principal = 6_000 * ONE_USDC;
+    partial.advance_amount = PricingCalculator::net_advance(6_000 * ONE_USDC);
+    let outcome = simulate_settlement(&partial, DEFAULT_COOLDOWN_SECONDS, PROTOCOL_FEE_BPS);
+    assert_eq!(outcome.pool_balance_delta, 6_000 * ONE_USDC);
+    assert_eq!(outcome.user_locked_released, 6_000 * ONE_USDC);
+    assert_eq!(outcome.lp_interest, PricingCalculator::apr_interest(6_000 * ONE_USDC, 14));
+
+    // A pool configured with a 30% protocol share splits the $500 fee 150/350
+    let custom = simulate_settlement(&deal, DEFAULT_COOLDOWN_SECONDS, 3000);
+    assert_eq!(custom.protocol_fee, 150 * ONE_USDC);
+    assert_eq!(custom.lp_fee, 350 * ONE_USDC);
+}
+
+#[test]
+fn test_request_validated_against_staked_balance() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+    let request = UnlockRequest::new(1, 10_000 * ONE_USDC, cooldown_end, mock_account_id(), &mut rng);
+
+    assert!(request.validate_against_balance(10_000 * ONE_USDC).is_ok());
+    assert!(request.validate_against_balance(50_000 * ONE_USDC).is_ok());
+
+    let err = request.validate_against_balance(9_999 * ONE_USDC).unwrap_err();
+    assert!(err.to_string().contains("exceeds available staked balance"));
+}
+
+/// In-memory pool storage standing in for the deal getters
+#[derive(Default)]
+struct StubLedgerBackend {
+    deals: Vec<DealView>,
+    settlements: Vec<SettlementView>,
+}
+
+impl LedgerBackend for StubLedgerBackend {
+    async fn deal_count(&mut self, _pool_account_id: AccountId) -> Result<u64> {
+        Ok(self.deals.len() as u64)
+    }
+
+    async fn deal(&mut self, _pool_account_id: AccountId, deal_id: u64) -> Result<DealView> {
+        Ok(self.deals[deal_id as usize].clone())
+    }
+
+    async fn settlement(
+        &mut self,
+        _pool_account_id: AccountId,
+        deal_id: u64,
+    ) -> Result<SettlementView> {
+        Ok(self.settlements.get(deal_id as usize).cloned().unwrap_or_default())
+    }
+}
+
+#[tokio::test]
+async fn test_export_ledger() {
+    let pool_account_id = mock_account_id_from(1);
+
+    let mut backend = StubLedgerBackend::default();
+    assert_eq!(export_ledger(&mut backend, pool_account_id).await.unwrap(), "[]");
+
+    let matched_at = 1_700_000_000;
+    let cooldown_end = matched_at + DEFAULT_COOLDOWN_SECONDS;
+    backend.deals = vec![
+        DealView {
+            user_commitment: Felt::new(11),
+            advance_amount: Felt::new(9_500 * ONE_USDC),
+            offer_id: Felt::new(0),
+            apr_bps: Felt::new(900),
+            matched_at: Felt::new(matched_at),
+            cooldown_end: Felt::new(cooldown_end),
+            is_settled: true,
+            settled_at: Felt::new(cooldown_end + 60),
+            earned: Felt::new(434 * ONE_USDC),
+            is_defaulted: false,
+        },
+        DealView {
+            user_commitment: Felt::new(22),
+            advance_amount: Felt::new(2_850 * ONE_USDC),
+            offer_id: Felt::new(3),
+            apr_bps: Felt::new(DEFAULT_APR_BPS),
+            matched_at: Felt::new(matched_at),
+            cooldown_end: Felt::new(cooldown_end),
+            is_settled: false,
+            settled_at: Felt::new(0),
+            earned: Felt::new(0),
+            is_defaulted: false,
+        },
+    ];
+
+    let json = export_ledger(&mut backend, pool_account_id).await.unwrap();
+    let entries: Vec<LedgerEntry> = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(
+        entries,
+        vec![
+            LedgerEntry {
+                deal_id: 0,
+                offer_id: 0,
+                user_commitment: 11,
+                advance_amount: 9_500 * ONE_USDC,
+                advance_fee: 500 * ONE_USDC,
+                apr_bps: 900,
+                matched_at,
+                cooldown_end,
+                settled_at: Some(cooldown_end + 60),
+                // The earnings the pool recorded, fee share and interest alike
+                lp_earnings: 434 * ONE_USDC,
+                status: DealStatus::Settled,
+            },
+            LedgerEntry {
+                deal_id: 1,
+                offer_id: 3,
+                user_commitment: 22,
+                advance_amount: 2_850 * ONE_USDC,
+                advance_fee: 150 * ONE_USDC,
+                apr_bps: DEFAULT_APR_BPS,
+                matched_at,
+                cooldown_end,
+                settled_at: None,
+                lp_earnings: 0,
+                status: DealStatus::Open,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_match_best_effort_partial() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let user_account_id = mock_account_id();
+    let lp_account_id = mock_account_id();
+
+    let mut engine = MatchingEngine::new();
+    let mut small = LpOffer::new(1, lp_account_id, 100_000 * ONE_USDC, 1_000 * ONE_USDC, Some(800));
+    small.set_remaining_amount(6_000 * ONE_USDC);
+    let mut larger = LpOffer::new(2, lp_account_id, 100_000 * ONE_USDC, 1_000 * ONE_USDC, Some(900));
+    larger.set_remaining_amount(9_500 * ONE_USDC);
+    engine.add_offer(small);
+    engine.add_offer(larger);
+
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+    let request = UnlockRequest::new(1, 30_000 * ONE_USDC, cooldown_end, user_account_id, &mut rng);
+    let now = current_timestamp();
+    assert!(engine.match_request(request.clone(), now, &mut rng).is_none());
+
+    // The larger offer funds $10,000 of principal ($9,500 net)
+    let (deal, shortfall) = engine.match_best_effort(request.clone(), now, &mut rng).unwrap();
+    assert_eq!(deal.offer.offer_id, 2);
+    assert_eq!(deal.principal, 10_000 * ONE_USDC);
+    assert_eq!(deal.advance_amount, 9_500 * ONE_USDC);
+    assert_eq!(deal.advance_fee(), 500 * ONE_USDC);
+    assert_eq!(shortfall, 20_000 * ONE_USDC);
+
+    // The deal still refers to the request the user committed on-chain
+    assert_eq!(deal.request.amount, 30_000 * ONE_USDC);
+    assert_eq!(deal.request.commitment, request.commitment);
+    assert_eq!(verify_commitments(&[(deal.request.clone(), request.commitment)]), vec![true]);
+
+    // A request the book can cover has no shortfall
+    let request = UnlockRequest::new(2, 5_000 * ONE_USDC, cooldown_end, user_account_id, &mut rng);
+    let (deal, shortfall) = engine.match_best_effort(request, now, &mut rng).unwrap();
+    assert_eq!(deal.principal, 5_000 * ONE_USDC);
+    assert_eq!(deal.request.amount, 5_000 * ONE_USDC);
+    assert_eq!(shortfall, 0);
+}
+
+#[test]
+fn test_deal_amounts_in_display_units() {
+    let mut rng = StdRng::seed_from_u64(42);
+
+    let mut engine = MatchingEngine::new();
+    engine.add_offer(LpOffer::new(1, mock_account_id(), 100_000 * ONE_USDC, 1_000 * ONE_USDC, None));
+
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+    let request = UnlockRequest::new(1, 3_000 * ONE_USDC, cooldown_end, mock_account_id(), &mut rng);
+    let deal = engine.match_request(request, current_timestamp(), &mut rng).unwrap();
+
+    assert_eq!(deal.fee_display(), "150.00");
+    assert_eq!(deal.advance_display(), "2850.00");
+    // 14 days at 10% is $11.506849, which `/ ONE_USDC` would show as 11
+    assert_eq!(deal.interest_display(14), "11.51");
+
+    assert_eq!(format_display(11_504_999, 6), "11.50");
+    assert_eq!(format_display(1_005, 3), "1.01");
+    assert_eq!(format_display(7, 0), "7.00");
+}
+
+/// In-memory user account storage standing in for the request getters
+struct StubRequestBackend {
+    commitment: Felt,
+    amount: Felt,
+    counter: u64,
+    requests: Vec<RequestView>,
+}
+
+impl RequestBackend for StubRequestBackend {
+    async fn request_commitment(&mut self, _account_id: AccountId, _request_id: u64) -> Result<Felt> {
+        Ok(self.commitment)
+    }
+
+    async fn request_amount(&mut self, _account_id: AccountId, _request_id: u64) -> Result<Felt> {
+        Ok(self.amount)
+    }
+
+    async fn request_counter(&mut self, _account_id: AccountId) -> Result<u64> {
+        Ok(self.counter)
+    }
+
+    async fn request(&mut self, _account_id: AccountId, request_id: u64) -> Result<RequestView> {
+        Ok(self.requests[request_id as usize].clone())
+    }
+}
+
+#[tokio::test]
+async fn test_reconstruct_request_matches_onchain() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let account_id = mock_account_id();
+    let amount = 10_000 * ONE_USDC;
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+    let original = UnlockRequest::new(1, amount, cooldown_end, account_id, &mut rng);
+
+    // What create_unlock_request stored for the original request
+    let mut backend = StubRequestBackend {
+        commitment: original.commitment[0],
+        amount: Felt::new(amount),
+        counter: 2,
+        requests: Vec::new(),
+    };
+
+    let restored =
+        UnlockRequest::reconstruct(1, amount, cooldown_end, original.nullifier_secret, account_id);
+    assert_eq!(restored.commitment, original.commitment);
+    assert!(restored.matches_onchain(&mut backend, account_id).await.unwrap());
+
+    // A misremembered cooldown end doesn't validate
+    let wrong =
+        UnlockRequest::reconstruct(1, amount, cooldown_end - 1, original.nullifier_secret, account_id);
+    assert!(!wrong.matches_onchain(&mut backend, account_id).await.unwrap());
+
+    // Nor does a stored amount that differs
+    backend.amount = Felt::new(amount - 1);
+    assert!(!restored.matches_onchain(&mut backend, account_id).await.unwrap());
+}
+
+#[test]
+fn test_plan_settlement_batches() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let user_account_id = mock_account_id();
+    let now = 1_700_000_000;
+    let matched_at = now - DEFAULT_COOLDOWN_SECONDS;
+
+    let mut engine = MatchingEngine::new();
+    engine.add_offer(LpOffer::new(1, mock_account_id(), 100_000 * ONE_USDC, 1_000 * ONE_USDC, None));
+
+    let mut deal_for = |request_id, cooldown_end| {
+        let amount = 5_000 * ONE_USDC;
+        let request = UnlockRequest::new(request_id, amount, cooldown_end, user_account_id, &mut rng);
+        engine.match_request(request, matched_at, &mut rng).unwrap()
+    };
+
+    let mut deals: Vec<MatchedDeal> = (0..7).map(|id| deal_for(id, now - 60)).collect();
+    // Still cooling down
+    deals.push(deal_for(7, now + DEFAULT_COOLDOWN_SECONDS));
+    // Already settled
+    let mut settled = deal_for(8, now - 60);
+    settled.is_settled = true;
+    deals.push(settled);
+
+    let batches = plan_settlement_batches(&deals, 4, now);
+    assert_eq!(batches.len(), 2);
+    assert_eq!(batches[0].len(), 4);
+    assert_eq!(batches[1].len(), 3);
+
+    let due: Vec<_> = deals[..7].iter().map(|deal| deal.deal_id).collect();
+    assert_eq!(batches.concat(), due);
+}
+
+#[test]
+fn test_request_cooldown_bounds() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let account_id = mock_account_id();
+    let amount = 10_000 * ONE_USDC;
+    let day = 24 * 60 * 60;
+
+    let config = ProtocolConfig::default();
+    let (min, max) = (config.min_cooldown_seconds, config.max_cooldown_seconds);
+    let mut request_with = |config: &ProtocolConfig, cooldown_seconds| {
+        UnlockRequest::try_new(config, 1, amount, cooldown_seconds, account_id, &mut rng)
+    };
+
+    // Both bounds are inclusive
+    assert!(request_with(&config, min).is_ok());
+    assert!(request_with(&config, max).is_ok());
+    assert_eq!(request_with(&config, DEFAULT_COOLDOWN_SECONDS).unwrap().amount, amount);
+
+    // Just outside either bound
+    assert!(request_with(&config, min - 1).is_err());
+    assert!(request_with(&config, max + 1).is_err());
+
+    // Custom bounds
+    let strict = ProtocolConfig {
+        min_cooldown_seconds: 7 * day,
+        max_cooldown_seconds: 14 * day,
+    };
+    assert!(request_with(&strict, 3 * day).is_err());
+    assert!(request_with(&strict, 10 * day).is_ok());
+}
+
+#[test]
+fn test_insurance_fund_target() {
+    let outstanding = 1_000_000 * ONE_USDC;
+
+    // 2% default rate with 60% recovered: 1,000,000 * 0.02 * 0.4 = $8,000
+    assert_eq!(insurance_fund_target(outstanding, 200, 6000), 8_000 * ONE_USDC);
+
+    // No recovery means the whole defaulted share is lost
+    assert_eq!(insurance_fund_target(outstanding, 200, 0), 20_000 * ONE_USDC);
+
+    // No expected defaults or full recovery need no reserve
+    assert_eq!(insurance_fund_target(outstanding, 0, 6000), 0);
+    assert_eq!(insurance_fund_target(outstanding, 200, 10000), 0);
+}
+
+#[test]
+fn test_request_locked_while_matching() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+    let request = UnlockRequest::new(1, 10_000 * ONE_USDC, cooldown_end, mock_account_id(), &mut rng);
+    let other = UnlockRequest::new(2, 10_000 * ONE_USDC, cooldown_end, mock_account_id(), &mut rng);
+    let now = current_timestamp();
+
+    let locks = MatchLocks::new(60);
+    let guard = lock_request_for_matching(&locks, request.commitment, now).unwrap();
+
+    // A second matcher can't take the same request, but other requests are free
+    assert!(lock_request_for_matching(&locks.clone(), request.commitment, now).is_err());
+    assert!(lock_request_for_matching(&locks, other.commitment, now).is_ok());
+    assert!(locks.is_locked(request.commitment, now));
+
+    // Finalizing the deal releases the lock
+    drop(guard);
+    assert!(!locks.is_locked(request.commitment, now));
+    let guard = lock_request_for_matching(&locks, request.commitment, now).unwrap();
+
+    // An abandoned lock lapses after the timeout
+    std::mem::forget(guard);
+    assert!(lock_request_for_matching(&locks, request.commitment, now + 30).is_err());
+    assert!(lock_request_for_matching(&locks, request.commitment, now + 60).is_ok());
+}
+
+#[test]
+fn test_quote_breakdown() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let lp_account_id = mock_account_id();
+
+    let mut engine = MatchingEngine::new();
+    engine.add_offer(LpOffer::new(1, lp_account_id, 100_000 * ONE_USDC, 1_000 * ONE_USDC, Some(1200)));
+    engine.add_offer(LpOffer::new(2, lp_account_id, 100_000 * ONE_USDC, 1_000 * ONE_USDC, Some(730)));
+
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+    let request = UnlockRequest::new(1, 10_000 * ONE_USDC, cooldown_end, mock_account_id(), &mut rng);
+
+    let quote = engine.quote(&request, current_timestamp()).unwrap();
+    assert_eq!(quote.offer_id, 2);
+    assert_eq!(quote.apr_bps, 730);
+    assert_eq!(quote.advance_amount, 9_500 * ONE_USDC);
+    assert_eq!(quote.fee, 500 * ONE_USDC);
+    // 10,000 * 7.3% * 14 / 365 = $28
+    assert_eq!(quote.interest, 28 * ONE_USDC);
+    assert_eq!(quote.lp_fee_share, 400 * ONE_USDC);
+    assert_eq!(quote.protocol_fee_share, 100 * ONE_USDC);
+    assert_eq!(quote.total_cost, 528 * ONE_USDC);
+    assert_eq!(quote.settlement_eta, cooldown_end);
+
+    // Fees split by the pool's configured share, not the default
+    let engine = engine.with_fee_split(5000);
+    let quote = engine.quote(&request, current_timestamp()).unwrap();
+    assert_eq!(quote.lp_fee_share, 250 * ONE_USDC);
+    assert_eq!(quote.protocol_fee_share, 250 * ONE_USDC);
+
+    // Nothing to quote when no offer covers the amount
+    let request = UnlockRequest::new(2, 500 * ONE_USDC, cooldown_end, mock_account_id(), &mut rng);
+    assert!(engine.quote(&request, current_timestamp()).is_none());
+}
+
+#[test]
+fn test_savings_vs_liquidation() {
+    let principal = 10_000 * ONE_USDC;
+
+    // Borrowing costs a $500 fee plus ~$38.35 interest over 14 days
+    let borrow_cost =
+        PricingCalculator::advance_fee(principal) + PricingCalculator::apr_interest(principal, 14);
+
+    // Selling at a 15% discount loses $1,500, so borrowing saves ~$961
+    let savings = PricingCalculator::savings_vs_liquidation(principal, 14, 1500);
+    assert_eq!(savings, (1_500 * ONE_USDC) as i64 - borrow_cost as i64);
+    assert!(savings > 900 * ONE_USDC as i64);
+
+    // A 1% discount makes selling cheaper
+    assert!(PricingCalculator::savings_vs_liquidation(principal, 14, 100) < 0);
+}
+
+/// In-memory pool storage standing in for the offer getters
+struct StubOfferBackend {
+    offers: Vec<OfferView>,
+    fee_split: u64,
+}
+
+impl OfferBackend for StubOfferBackend {
+    async fn offer_count(&mut self, _pool_account_id: AccountId) -> Result<u64> {
+        Ok(self.offers.len() as u64)
+    }
+
+    async fn fee_split(&mut self, _pool_account_id: AccountId) -> Result<u64> {
+        Ok(self.fee_split)
+    }
+
+    async fn offer(&mut self, _pool_account_id: AccountId, offer_id: u64) -> Result<OfferView> {
+        Ok(self.offers[offer_id as usize].clone())
+    }
+}
+
+#[tokio::test]
+async fn test_sync_from_pool_uses_remaining_capacity() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let lp_account_id = mock_account_id_from(2);
+    let offer_view = |remaining: u64, is_active| OfferView {
+        lp_account_id,
+        max_amount: Felt::new(50_000 * ONE_USDC),
+        min_amount: Felt::new(1_000 * ONE_USDC),
+        apr_bps: Felt::new(900),
+        remaining_amount: Felt::new(remaining),
+        is_active,
+    };
+
+    // Offer 0 was cancelled; offer 1 has had $40,000 drawn by earlier deals
+    let mut backend = StubOfferBackend {
+        offers: vec![offer_view(50_000 * ONE_USDC, false), offer_view(10_000 * ONE_USDC, true)],
+        fee_split: 3000,
+    };
+
+    let mut engine = MatchingEngine::new();
+    engine.add_offer(LpOffer::new(9, mock_account_id(), 100_000 * ONE_USDC, 1_000 * ONE_USDC, None));
+    engine.sync_from_pool(&mut backend, mock_account_id_from(1)).await.unwrap();
+
+    assert_eq!(engine.offers.len(), 1);
+    let offer = &engine.offers[0];
+    assert_eq!(offer.offer_id, 1);
+    assert_eq!(offer.lp_account_id, lp_account_id);
+    assert_eq!(offer.max_amount(), 50_000 * ONE_USDC);
+    assert_eq!(offer.custom_apr_bps, Some(900));
+    assert_eq!(offer.remaining_amount(), 10_000 * ONE_USDC);
+    assert_eq!(engine.protocol_fee_bps, 3000);
+
+    // Within the offer's range, but more than its remaining capacity
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+    let request = UnlockRequest::new(1, 20_000 * ONE_USDC, cooldown_end, mock_account_id(), &mut rng);
+    assert!(engine.match_request(request, current_timestamp(), &mut rng).is_none());
+    let request = UnlockRequest::new(2, 10_000 * ONE_USDC, cooldown_end, mock_account_id(), &mut rng);
+    assert!(engine.match_request(request, current_timestamp(), &mut rng).is_some());
+}
+
+#[test]
+fn test_deal_cash_flows() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let start = current_timestamp();
+    let settled_at = start + DEFAULT_COOLDOWN_SECONDS;
+
+    let mut engine = MatchingEngine::new();
+    engine.add_offer(LpOffer::new(1, mock_account_id(), 100_000 * ONE_USDC, 1_000 * ONE_USDC, None));
+    let request = UnlockRequest::new(1, 10_000 * ONE_USDC, settled_at, mock_account_id(), &mut rng);
+    let deal = engine.match_request(request, current_timestamp(), &mut rng).unwrap();
+
+    let flows = deal.cash_flows(start);
+    assert!(flows.iter().all(|(at, _, _)| *at == start || *at == settled_at));
+
+    let net = |party: &str| -> i64 {
+        flows
+            .iter()
+            .filter(|(_, _, label)| label.starts_with(party))
+            .map(|(_, amount, _)| amount)
+            .sum()
+    };
+    let fee = deal.request.advance_fee() as i64;
+    let (lp_fee, interest) = deal.lp_earnings(14);
+
+    // The user nets the advance up front, then pays back principal plus interest
+    let user_at_start: i64 = flows
+        .iter()
+        .filter(|(at, _, label)| *at == start && label.starts_with("user"))
+        .map(|(_, amount, _)| amount)
+        .sum();
+    assert_eq!(user_at_start, deal.advance_amount as i64);
+
+    assert_eq!(net("user"), -fee - interest as i64);
+    assert_eq!(net("lp"), (lp_fee + interest) as i64);
+    assert_eq!(net("protocol"), deal.protocol_earnings() as i64);
+    assert_eq!(flows.iter().map(|(_, amount, _)| amount).sum::<i64>(), 0);
+}
+
+#[test]
+fn test_commitments_bind_account_id() {
+    let alice = mock_account_id_from(1);
+    let bob = mock_account_id_from(2);
+    let amount = 10_000 * ONE_USDC;
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+    let secret = [7u8; 32];
+
+    // Same amount, cooldown and secret, different account
+    assert_ne!(
+        request_commitment(amount, cooldown_end, &secret, alice),
+        request_commitment(amount, cooldown_end, &secret, bob)
+    );
+
+    let alice_request = UnlockRequest::reconstruct(1, amount, cooldown_end, secret, alice);
+    let bob_request = UnlockRequest::reconstruct(1, amount, cooldown_end, secret, bob);
+    assert_ne!(alice_request.commitment, bob_request.commitment);
+
+    let alice_offer = LpOffer::new(1, alice, 100_000 * ONE_USDC, 1_000 * ONE_USDC, None);
+    let bob_offer = LpOffer::new(1, bob, 100_000 * ONE_USDC, 1_000 * ONE_USDC, None);
+    assert_ne!(alice_offer.commitment(), bob_offer.commitment());
+}
+
+/// Request event log kept in memory as `[request_id, status, 0, 0]` words
+struct StubEventBackend {
+    events: Vec<Word>,
+}
+
+impl RequestEventBackend for StubEventBackend {
+    async fn event_count(&mut self, _account_id: AccountId) -> Result<u64> {
+        Ok(self.events.len() as u64)
+    }
+
+    async fn event(&mut self, _account_id: AccountId, event_index: u64) -> Result<Word> {
+        Ok(self.events[event_index as usize])
+    }
+}
+
+#[tokio::test]
+async fn test_fetch_request_events() {
+    let account_id = mock_account_id();
+    let event = |request_id, status| {
+        Word::from([Felt::new(request_id), Felt::new(status), Felt::new(0), Felt::new(0)])
+    };
+
+    // Request 0 created then matched, request 1 created
+    let mut backend = StubEventBackend { events: vec![event(0, 1), event(0, 3), event(1, 1)] };
+
+    let events = fetch_request_events(&mut backend, account_id, 0).await.unwrap();
+    assert_eq!(events.len(), 3);
+    let created = RequestEvent { event_index: 0, request_id: 0, kind: RequestEventKind::Created };
+    assert_eq!(events[0], created);
+    assert_eq!(events[1].kind, RequestEventKind::Matched);
+
+    // Resuming from a cursor returns only the newer events
+    let newer = fetch_request_events(&mut backend, account_id, 2).await.unwrap();
+    let created = RequestEvent { event_index: 2, request_id: 1, kind: RequestEventKind::Created };
+    assert_eq!(newer, vec![created]);
+
+    // An unknown status is an error rather than a silently dropped event
+    backend.events.push(event(1, 9));
+    assert!(fetch_request_events(&mut backend, account_id, 0).await.is_err());
+}
+
+#[test]
+fn test_offer_tree_proofs() {
+    let offers: Vec<LpOffer> = (1..=5)
+        .map(|id| {
+            LpOffer::new(id, mock_account_id_from(id as u8), id * 10_000 * ONE_USDC, ONE_USDC, None)
+        })
+        .collect();
+    let (root, tree) = build_offer_tree(&offers);
+    assert_eq!(root, tree.root());
+
+    // Every offer, including the one next to the padding, proves against the root
+    for offer in &offers {
+        let proof = tree.prove(offer.offer_id).unwrap();
+        assert!(verify_offer_proof(root, &proof, offer.commitment()));
+    }
+
+    // A proof for one offer doesn't vouch for another's commitment
+    let proof = tree.prove(3).unwrap();
+    assert!(!verify_offer_proof(root, &proof, offers[0].commitment()));
+
+    // Nor does a forged sibling or position
+    let mut forged = proof.clone();
+    forged.siblings[0] = offers[0].commitment();
+    assert!(!verify_offer_proof(root, &forged, offers[2].commitment()));
+    let mut moved = proof.clone();
+    moved.leaf_index = 1;
+    assert!(!verify_offer_proof(root, &moved, offers[2].commitment()));
+
+    // Offers outside the book can't be proven
+    assert!(tree.prove(99).is_err());
+}
+
+#[test]
+fn test_staked_denominated_offer_matches_like_usdc_equivalent() {
+    let lp_account_id = mock_account_id();
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+    // One staked unit is worth 2 USDC
+    let staked_price_bps = 20000;
+
+    let mut usdc_engine = MatchingEngine::new().with_staked_price(staked_price_bps);
+    let usdc_offer = LpOffer::new(1, lp_account_id, 100_000 * ONE_USDC, 2_000 * ONE_USDC, None);
+    usdc_engine.add_offer(usdc_offer);
+
+    // The same offer quoted as the staked collateral the LP wants to receive
+    let mut staked_engine = MatchingEngine::new().with_staked_price(staked_price_bps);
+    staked_engine.add_offer(
+        LpOffer::new(1, lp_account_id, 50_000 * ONE_USDC, 1_000 * ONE_USDC, None)
+            .with_denomination(OfferDenomination::StakedAsset),
+    );
+    assert_eq!(staked_engine.offers[0].max_amount(), 100_000 * ONE_USDC);
+    assert_eq!(staked_engine.offers[0].denomination, OfferDenomination::Usdc);
+
+    let mut rng = StdRng::seed_from_u64(42);
+    let now = current_timestamp();
+    for amount in [2_000 * ONE_USDC, 40_000 * ONE_USDC, 100_000 * ONE_USDC] {
+        let request = UnlockRequest::new(1, amount, cooldown_end, mock_account_id(), &mut rng);
+        let usdc_deal = usdc_engine.match_request(request.clone(), now, &mut rng).unwrap();
+        let staked_deal = staked_engine.match_request(request, now, &mut rng).unwrap();
+        assert_eq!(usdc_deal.advance_amount, staked_deal.advance_amount);
+    }
+
+    // Both reject a request past the equivalent max
+    let request =
+        UnlockRequest::new(2, 100_001 * ONE_USDC, cooldown_end, mock_account_id(), &mut rng);
+    assert!(usdc_engine.match_request(request.clone(), now, &mut rng).is_none());
+    assert!(staked_engine.match_request(request, now, &mut rng).is_none());
+}
+
+#[test]
+fn test_find_matches_orders_ties_by_offer_id() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+    let request =
+        UnlockRequest::new(1, 10_000 * ONE_USDC, cooldown_end, mock_account_id(), &mut rng);
+
+    // (offer_id, apr) with ties at the default APR and at 8%
+    let book = [(4, None), (2, Some(800)), (3, None), (1, None), (5, Some(800))];
+    let ordered_ids = |offers: &[(u64, Option<u64>)]| {
+        let mut engine = MatchingEngine::new();
+        for (offer_id, apr) in offers {
+            let lp_account_id = mock_account_id_from(*offer_id as u8);
+            let offer = LpOffer::new(*offer_id, lp_account_id, 100_000 * ONE_USDC, ONE_USDC, *apr);
+            engine.add_offer(offer);
+        }
+        let matches = engine.find_matches(&request, current_timestamp());
+        matches.iter().map(|offer| offer.offer_id).collect::<Vec<_>>()
+    };
+
+    let expected = vec![2, 5, 1, 3, 4];
+    assert_eq!(ordered_ids(&book), expected);
+    assert_eq!(ordered_ids(&book), expected);
+
+    // Insertion order doesn't change the result
+    let mut reversed = book;
+    reversed.reverse();
+    assert_eq!(ordered_ids(&reversed), expected);
+}
+
+#[tokio::test]
+async fn test_request_id_allocator_follows_account_counter() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let account_id = mock_account_id();
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+
+    // The account has already created three requests
+    let mut backend = StubRequestBackend {
+        commitment: Felt::new(0),
+        amount: Felt::new(0),
+        counter: 3,
+        requests: Vec::new(),
+    };
+    let mut request_ids =
+        RequestIdAllocator::from_account(&mut backend, account_id).await.unwrap();
+
+    let amount = 5_000 * ONE_USDC;
+    let first =
+        UnlockRequest::new(request_ids.next_id(), amount, cooldown_end, account_id, &mut rng);
+    let second =
+        UnlockRequest::new(request_ids.next_id(), amount, cooldown_end, account_id, &mut rng);
+
+    // The IDs the account will assign, so neither overwrites an existing request
+    assert_eq!(first.request_id, 3);
+    assert_eq!(second.request_id, 4);
+    assert_ne!(first.commitment, second.commitment);
+
+    // A fresh account starts at zero
+    assert_eq!(RequestIdAllocator::new().next_id(), 0);
+}
+
+#[test]
+fn test_uneconomical_deal_rejected() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let user_account_id = mock_account_id();
+
+    let mut engine = MatchingEngine::new();
+    engine.add_offer(LpOffer::new(1, mock_account_id(), 100_000 * ONE_USDC, ONE_USDC, None));
+
+    // Ten years at the default 10% APR is all of the principal in interest,
+    // before the 5% fee
+    let cooldown_end = cooldown_end_timestamp(10 * 365 * 24 * 60 * 60);
+    let request = UnlockRequest::new(1, 10 * ONE_USDC, cooldown_end, user_account_id, &mut rng);
+    let now = current_timestamp();
+    let result = engine.try_match_request(request.clone(), now, &mut rng);
+    assert_eq!(result.unwrap_err(), NoMatchReason::NotEconomical);
+    assert!(engine.match_request(request.clone(), now, &mut rng).is_none());
+
+    // Nothing else offers or reserves terms a match would refuse
+    assert!(engine.preview_match(&request, now).is_none());
+    assert!(engine.quote(&request, now).is_none());
+    assert!(engine.match_best_effort(request.clone(), now, &mut rng).is_none());
+    assert!(engine.hold(request, now).is_none());
+    assert_eq!(engine.offers[0].remaining_amount(), 100_000 * ONE_USDC);
+
+    // The same amount over a normal cooldown matches
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+    let request = UnlockRequest::new(2, 10 * ONE_USDC, cooldown_end, user_account_id, &mut rng);
+    assert!(engine.try_match_request(request, now, &mut rng).is_ok());
+}
+
+#[test]
+fn test_optimal_apr_undercuts_median_competitor() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let user_account_id = mock_account_id();
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+    let request = |request_id, amount, max_apr: Option<u64>, rng: &mut StdRng| {
+        let request = UnlockRequest::new(request_id, amount, cooldown_end, user_account_id, rng);
+        match max_apr {
+            Some(max_apr) => request.with_max_apr(max_apr),
+            None => request,
+        }
+    };
+
+    // The 3% competitor is below cost and ignored; the median of the rest is 10%
+    let competitors = [300, 800, 900, 1000, 1100, 1200];
+    let uncapped = vec![request(1, 10_000 * ONE_USDC, None, &mut rng)];
+    assert_eq!(optimal_apr_bps(&uncapped, &competitors), 999);
+
+    // Most volume only accepts up to 9.5%, so the recommendation stays under it
+    let capped = vec![
+        request(1, 30_000 * ONE_USDC, Some(950), &mut rng),
+        request(2, 10_000 * ONE_USDC, None, &mut rng),
+    ];
+    assert_eq!(optimal_apr_bps(&capped, &competitors), 950);
+
+    // Demand below cost doesn't drag the recommendation under the margin floor
+    let cheap = vec![request(1, 10_000 * ONE_USDC, Some(200), &mut rng)];
+    assert_eq!(optimal_apr_bps(&cheap, &competitors), MIN_MARGIN_APR_BPS);
+
+    // With no competition or history, quote the default
+    assert_eq!(optimal_apr_bps(&[], &[]), DEFAULT_APR_BPS);
+}
+
+#[tokio::test]
+async fn test_settled_deals_statement() {
+    let pool_account_id = mock_account_id_from(1);
+    let deal = |advance, is_settled| DealView {
+        user_commitment: Felt::new(1),
+        advance_amount: Felt::new(advance),
+        offer_id: Felt::new(0),
+        apr_bps: Felt::new(DEFAULT_APR_BPS),
+        matched_at: Felt::new(0),
+        cooldown_end: Felt::new(DEFAULT_COOLDOWN_SECONDS),
+        is_settled,
+        settled_at: Felt::new(0),
+        earned: Felt::new(0),
+        is_defaulted: false,
+    };
+    let settlement = |staked, lp_fee, interest| SettlementView {
+        staked_received: Felt::new(staked),
+        earned: Felt::new(lp_fee + interest),
+        interest: Felt::new(interest),
+    };
+
+    // Two settled deals around one still open
+    let mut backend = StubLedgerBackend {
+        deals: vec![
+            deal(9_500 * ONE_USDC, true),
+            deal(4_750 * ONE_USDC, false),
+            deal(2_850 * ONE_USDC, true),
+        ],
+        settlements: vec![
+            settlement(10_000 * ONE_USDC, 400 * ONE_USDC, 38 * ONE_USDC),
+            SettlementView::default(),
+            settlement(3_000 * ONE_USDC, 120 * ONE_USDC, 11 * ONE_USDC),
+        ],
+    };
+
+    let statement = settled_deals(&mut backend, pool_account_id).await.unwrap();
+    assert_eq!(statement.iter().map(|deal| deal.deal_id).collect::<Vec<_>>(), vec![0, 2]);
+    assert_eq!(
+        statement[1],
+        SettledDeal {
+            deal_id: 2,
+            staked_received: 3_000 * ONE_USDC,
+            lp_fee: 120 * ONE_USDC,
+            interest: 11 * ONE_USDC,
+        }
+    );
+
+    let total = |field: fn(&SettledDeal) -> u64| statement.iter().map(field).sum::<u64>();
+    assert_eq!(total(|deal| deal.staked_received), 13_000 * ONE_USDC);
+    assert_eq!(total(|deal| deal.lp_fee), 520 * ONE_USDC);
+    assert_eq!(total(|deal| deal.interest), 49 * ONE_USDC);
+}
+
+#[test]
+fn test_classify_note_error() {
+    let cooldown_end = 1_700_000_000;
+    let matched = RequestView {
+        amount: Felt::new(10_000 * ONE_USDC),
+        cooldown_end: Felt::new(cooldown_end),
+        is_matched: true,
+        is_settled: false,
+    };
+    let vm_error = || anyhow!("assertion failed at clock cycle 1234 with error code: 0");
+
+    let early = vm_error();
+    let failure = classify_note_error(&early, &matched, cooldown_end, cooldown_end - 1);
+    assert_eq!(failure, NoteFailure::CooldownNotEnded);
+    assert!(failure.is_retryable());
+
+    // Context added on the way up doesn't hide the cause
+    let wrapped = vm_error().context("Failed to consume settlement note");
+    let settled = RequestView { is_settled: true, ..matched.clone() };
+    let failure = classify_note_error(&wrapped, &settled, cooldown_end, cooldown_end);
+    assert_eq!(failure, NoteFailure::SettlementNotAuthorized);
+    assert!(!failure.is_retryable());
+
+    // A note claiming another cooldown end fails that check first, even early
+    let failure = classify_note_error(&vm_error(), &matched, cooldown_end - 1, cooldown_end - 1);
+    assert_eq!(failure, NoteFailure::CommitmentMismatch);
+
+    let unknown = anyhow!("connection reset");
+    let failure = classify_note_error(&unknown, &matched, cooldown_end, cooldown_end - 1);
+    assert_eq!(failure, NoteFailure::Other("connection reset".to_string()));
+    assert!(!failure.is_retryable());
+}
+
+#[tokio::test]
+async fn test_user_net_position() {
+    let user_account_id = mock_account_id();
+    let now = current_timestamp();
+
+    // One matched deal due today, so it owes its locked amount and no more interest,
+    // next to a cancelled request and a settled one that owe nothing
+    let request = |amount, is_matched, is_settled| RequestView {
+        amount: Felt::new(amount),
+        cooldown_end: Felt::new(now),
+        is_matched,
+        is_settled,
+    };
+    let mut backend = StubRequestBackend {
+        commitment: Felt::new(0),
+        amount: Felt::new(0),
+        counter: 3,
+        requests: vec![
+            request(10_000 * ONE_USDC, true, false),
+            request(0, false, false),
+            request(5_000 * ONE_USDC, true, true),
+        ],
+    };
+
+    let position = user_net_position(&mut backend, user_account_id, 12_000 * ONE_USDC, now).await;
+    assert_eq!(position.unwrap(), 2_000 * ONE_USDC as i64);
+
+    // Slashing cuts the collateral below what's owed
+    let position = user_net_position(&mut backend, user_account_id, 9_000 * ONE_USDC, now).await;
+    assert_eq!(position.unwrap(), -(1_000 * ONE_USDC as i64));
+
+    // With 73 days left the deal also owes 2% interest
+    backend.requests[0].cooldown_end = Felt::new(now + 73 * 24 * 60 * 60);
+    let position = user_net_position(&mut backend, user_account_id, 12_000 * ONE_USDC, now).await;
+    assert_eq!(position.unwrap(), 1_800 * ONE_USDC as i64);
+
+    // Collateral past i64 range is an error rather than a wrapped position
+    let position = user_net_position(&mut backend, user_account_id, u64::MAX, now).await;
+    assert!(position.is_err());
+}
+
+#[test]
+fn test_deal_receipt_validation() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let now = current_timestamp();
+
+    let mut engine = MatchingEngine::new();
+    engine.add_offer(LpOffer::new(1, mock_account_id(), 100_000 * ONE_USDC, ONE_USDC, Some(900)));
+    let cooldown_end = now + DEFAULT_COOLDOWN_SECONDS;
+    let request =
+        UnlockRequest::new(1, 10_000 * ONE_USDC, cooldown_end, mock_account_id(), &mut rng);
+    let deal = engine.match_request(request, current_timestamp(), &mut rng).unwrap();
+
+    let receipt: DealReceipt = deal.receipt(now);
+    assert_eq!(receipt.apr_bps, 900);
+    assert_eq!(receipt.cooldown_days, 14);
+    assert_eq!(receipt.validate(), Ok(()));
+
+    // Inflated interest is caught with what it should have been
+    let mut tampered = receipt.clone();
+    tampered.interest += ONE_USDC;
+    assert_eq!(
+        tampered.validate(),
+        Err(DisputeFinding::InterestMismatch {
+            recorded: receipt.interest + ONE_USDC,
+            expected: receipt.interest,
+        })
+    );
+
+    // Shifting fee from the protocol to the LP is caught even though the total holds
+    let mut tampered = receipt.clone();
+    tampered.lp_fee_share += 1;
+    tampered.protocol_fee_share -= 1;
+    assert!(matches!(tampered.validate(), Err(DisputeFinding::FeeSplitMismatch { .. })));
+}
+
+#[test]
+fn test_interest_day_count_conventions() {
+    let principal = 100_000 * ONE_USDC;
+    // 2025-01-01 to 2025-03-01: 59 actual days, 60 days under 30/360
+    let start = 1_735_689_600;
+    let end = start + 59 * 24 * 60 * 60;
+
+    let interest = |convention| {
+        PricingCalculator::apr_interest_with_convention(principal, start, end, 1000, convention)
+    };
+    let actual_365 = interest(DayCount::Actual365);
+    let actual_360 = interest(DayCount::Actual360);
+    let thirty_360 = interest(DayCount::Thirty360);
+
+    // The default convention is what the protocol already charges
+    assert_eq!(DayCount::default(), DayCount::Actual365);
+    assert_eq!(actual_365, PricingCalculator::apr_interest_at(principal, 1000, 59));
+
+    // A shorter year and February counted as 30 days each charge more
+    assert!(actual_365 < actual_360);
+    assert!(actual_360 < thirty_360);
+    assert_eq!(thirty_360, 1_666_666_666);
+
+    // An empty or reversed period accrues nothing
+    let with_convention = PricingCalculator::apr_interest_with_convention;
+    for convention in [DayCount::Actual365, DayCount::Actual360, DayCount::Thirty360] {
+        assert_eq!(with_convention(principal, end, start, 1000, convention), 0);
+        assert_eq!(with_convention(principal, start, start, 1000, convention), 0);
+    }
+}
+
+#[test]
+fn test_check_note_matches_deal() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let deal = matched_deal(&mut rng);
+
+    let mut settlement = settlement_note_config(
+        Felt::new(deal.request.request_id),
+        Felt::new(deal.request.amount),
+        Felt::new(deal.request.cooldown_end_timestamp),
+        deal.deal_id[0],
+    );
+    assert!(check_note_matches_deal(&settlement, &deal, VoileNoteKind::Settlement).is_ok());
+
+    // Any pool deal ID is accepted on the advance note
+    let advance = advance_note_config(
+        Felt::new(deal.advance_amount),
+        Felt::new(7),
+        Felt::new(deal.offer.offer_id),
+        deal.request.commitment[0],
+    );
+    assert!(check_note_matches_deal(&advance, &deal, VoileNoteKind::Advance).is_ok());
+
+    // A settlement note with the wrong amount is rejected, naming the input
+    settlement.inputs[1] = Felt::new(deal.request.amount - 1);
+    let err = check_note_matches_deal(&settlement, &deal, VoileNoteKind::Settlement).unwrap_err();
+    assert!(err.to_string().contains("amount"));
+
+    // As is checking a note against the wrong kind
+    assert!(check_note_matches_deal(&advance, &deal, VoileNoteKind::Settlement).is_err());
+}
+
+#[test]
+fn test_stress_test_scenario() {
+    let mut engine = MatchingEngine::new();
+    let offer = LpOffer::new(0, mock_account_id(), 50_000 * ONE_USDC, 1_000 * ONE_USDC, None);
+    engine.add_offer(offer);
+
+    // Two large requests drain most of the offer, a third can't be funded,
+    // then the first deal defaults
+    let scenario = StressScenario {
+        user_account_id: mock_account_id_from(7),
+        start_timestamp: 1_700_000_000,
+        cooldown_days: 14,
+        events: vec![
+            StressEvent::Request { amount: 20_000 * ONE_USDC },
+            StressEvent::Request { amount: 20_000 * ONE_USDC },
+            StressEvent::Request { amount: 20_000 * ONE_USDC },
+            StressEvent::Default { deal: 0 },
+            StressEvent::Default { deal: 0 },
+        ],
+    };
+
+    let result = stress_test(&engine, &scenario);
+
+    // The surviving deal earns its fee share and interest over the cooldown
+    let earnings = PricingCalculator::lp_fee_share(1_000 * ONE_USDC)
+        + PricingCalculator::apr_interest_at(20_000 * ONE_USDC, DEFAULT_APR_BPS, 14);
+    assert_eq!(
+        result,
+        StressResult {
+            requests: 3,
+            filled: 2,
+            fill_rate_bps: 6666,
+            peak_utilization_bps: 7600,
+            losses: 19_000 * ONE_USDC,
+            ending_capital: 50_000 * ONE_USDC + earnings - 19_000 * ONE_USDC,
+        }
+    );
+
+    // The engine's own book is left untouched
+    assert_eq!(engine.offers[0].remaining_amount(), 50_000 * ONE_USDC);
+}
+
+#[test]
+fn test_min_offers_to_fill() {
+    let lp = mock_account_id();
+    let mut engine = MatchingEngine::new();
+    engine.add_offer(LpOffer::new(0, lp, 30_000 * ONE_USDC, 1_000 * ONE_USDC, None));
+    engine.add_offer(LpOffer::new(1, lp, 50_000 * ONE_USDC, 1_000 * ONE_USDC, None));
+
+    let now = 1_700_000_000;
+
+    // Fits in the larger offer alone
+    assert_eq!(engine.min_offers_to_fill(40_000 * ONE_USDC, now), Some(1));
+
+    // Needs the larger offer's 50k plus 20k from the other
+    assert_eq!(engine.min_offers_to_fill(70_000 * ONE_USDC, now), Some(2));
+
+    // More than both offers together
+    assert_eq!(engine.min_offers_to_fill(100_000 * ONE_USDC, now), None);
+}
+
+#[test]
+fn test_decode_earnings_event() {
+    // As `record_settlement` logs it: [deal_id, fee, interest, total earned]
+    let event = Word::from([
+        Felt::new(3),
+        Felt::new(400 * ONE_USDC),
+        Felt::new(76 * ONE_USDC),
+        Felt::new(1_476 * ONE_USDC),
+    ]);
+    assert_eq!(
+        decode_earnings_event(event).unwrap(),
+        EarningsEvent {
+            deal_id: 3,
+            fee_credited: 400 * ONE_USDC,
+            interest_credited: 76 * ONE_USDC,
+            total_earned: 1_476 * ONE_USDC,
+        }
+    );
+
+    // A total that wrapped below zero is rejected
+    let below_zero = Felt::new(5 * ONE_USDC) - Felt::new(6 * ONE_USDC);
+    let wrapped = Word::from([event[0], event[1], event[2], below_zero]);
+    let err = decode_earnings_event(wrapped).unwrap_err();
+    assert!(err.to_string().contains("total earned"));
+}
+
+#[test]
+fn test_hold_blocks_competing_match() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut engine = MatchingEngine::new().with_hold_timeout(60);
+    let offer = LpOffer::new(0, mock_account_id(), 20_000 * ONE_USDC, 1_000 * ONE_USDC, None);
+    engine.add_offer(offer);
+
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+    let request = |id, rng: &mut StdRng| {
+        UnlockRequest::new(id, 15_000 * ONE_USDC, cooldown_end, mock_account_id_from(1), rng)
+    };
+    let now = current_timestamp();
+
+    // While the first request holds the offer, the second can't match
+    let token = engine.hold(request(1, &mut rng), now).unwrap();
+    assert!(engine.match_request(request(2, &mut rng), now, &mut rng).is_none());
+    assert!(engine.hold(request(2, &mut rng), now).is_none());
+
+    // Releasing frees the capacity again
+    assert!(engine.release(token));
+    assert!(!engine.release(token));
+    assert!(engine.match_request(request(2, &mut rng), now, &mut rng).is_some());
+
+    // A committed hold becomes an open deal
+    let token = engine.hold(request(3, &mut rng), now).unwrap();
+    let deal = engine.commit(token, now + 30, &mut rng).unwrap();
+    assert_eq!(deal.request.request_id, 3);
+    assert_eq!(engine.open_deals.len(), 1);
+    assert_eq!(engine.offers[0].remaining_amount(), 20_000 * ONE_USDC - deal.advance_amount);
+
+    // A hold lapses after the timeout and can no longer be committed
+    engine.offers[0].set_remaining_amount(20_000 * ONE_USDC);
+    let token = engine.hold(request(4, &mut rng), now).unwrap();
+    assert!(engine.commit(token, now + 60, &mut rng).is_none());
+    assert_eq!(engine.offers[0].remaining_amount(), 20_000 * ONE_USDC);
+}
+
+#[test]
+fn test_lapsed_hold_capacity_is_matchable() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut engine = MatchingEngine::new().with_hold_timeout(60);
+    let offer = LpOffer::new(0, mock_account_id(), 20_000 * ONE_USDC, 1_000 * ONE_USDC, None);
+    engine.add_offer(offer);
+
+    let cooldown_end = cooldown_end_timestamp(DEFAULT_COOLDOWN_SECONDS);
+    let request = |id, rng: &mut StdRng| {
+        UnlockRequest::new(id, 15_000 * ONE_USDC, cooldown_end, mock_account_id_from(1), rng)
+    };
+    let now = current_timestamp();
+    engine.hold(request(1, &mut rng), now).unwrap();
+
+    // Before the timeout every read path sees the capacity as held
+    let competing = request(2, &mut rng);
+    assert!(engine.find_matches(&competing, now + 59).is_empty());
+    assert!(engine.preview_match(&competing, now + 59).is_none());
+    assert!(engine.quote(&competing, now + 59).is_none());
+
+    assert!(engine.cheapest_offer_for(&competing, now + 59).is_none());
+    assert_eq!(engine.min_offers_to_fill(competing.amount, now + 59), None);
+    assert_eq!(engine.time_to_liquidity(competing.amount, now + 59), Some(now + 60));
+
+    // Once the hold lapses they see it back, without anything releasing it
+    assert_eq!(engine.find_matches(&competing, now + 60).len(), 1);
+    assert!(engine.cheapest_offer_for(&competing, now + 60).is_some());
+    assert_eq!(engine.min_offers_to_fill(competing.amount, now + 60), Some(1));
+    assert_eq!(engine.time_to_liquidity(competing.amount, now + 60), Some(now + 60));
+    assert!(engine.preview_match(&competing, now + 60).is_some());
+    assert!(engine.quote(&competing, now + 60).is_some());
+    assert!(engine.match_request(competing.clone(), now + 60, &mut rng).is_some());
+
+    // Drawing on it releases the lapsed hold before taking the advance
+    let deal = engine.match_request_mut(competing, now + 60, &mut rng).unwrap();
+    assert_eq!(engine.offers[0].remaining_amount(), 20_000 * ONE_USDC - deal.advance_amount);
+}
+
+#[test]
+fn test_liquidity_runway() {
+    let day = 24 * 60 * 60;
+    let pool = PoolHealth {
+        available: 100_000 * ONE_USDC,
+        outstanding: 50_000 * ONE_USDC,
+        as_of: 1_000,
+    };
+    let schedule = [(pool.as_of + 5 * day, 50_000 * ONE_USDC)];
+
+    // 20k a day drains 100k by day 5, the settlement buys two more days, and
+    // day 7 can't be covered
+    assert_eq!(
+        liquidity_runway(&pool, &schedule, 20_000 * ONE_USDC),
+        Some(pool.as_of + 7 * day)
+    );
+
+    // 5k a day locks at most 70k before the first advances come back
+    assert_eq!(liquidity_runway(&pool, &schedule, 5_000 * ONE_USDC), None);
+}
+
+#[test]
+fn test_marginal_apr_bps() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let now = 1_700_000_000;
+    let cooldown_end = now + DEFAULT_COOLDOWN_SECONDS;
+    let flow: Vec<UnlockRequest> = (0..5)
+        .map(|id| {
+            UnlockRequest::new(id, 10_000 * ONE_USDC, cooldown_end, mock_account_id(), &mut rng)
+                .with_max_apr(1500)
+        })
+        .collect();
+
+    // A 10k book fills one request; 40k more serves the other four at their cap
+    let mut under_supplied = MatchingEngine::new();
+    let offer = LpOffer::new(0, mock_account_id(), 10_000 * ONE_USDC, 1_000 * ONE_USDC, None);
+    under_supplied.add_offer(offer);
+    let marginal = under_supplied.marginal_apr_bps(40_000 * ONE_USDC, &flow, now);
+    assert_eq!(marginal, 1500 * 38_000 / 40_000);
+
+    // A book that already covers the flow leaves new capital idle
+    let mut over_supplied = MatchingEngine::new();
+    let offer = LpOffer::new(0, mock_account_id(), 1_000_000 * ONE_USDC, 1_000 * ONE_USDC, None);
+    over_supplied.add_offer(offer);
+    assert_eq!(over_supplied.marginal_apr_bps(40_000 * ONE_USDC, &flow, now), 0);
+}
+
+#[test]
+fn test_validate_transaction_request_rejects_empty() {
+    let request = TransactionRequestBuilder::new().build().unwrap();
+
+    let err = validate_transaction_request(mock_account_id(), &request).unwrap_err();
+    assert!(err.to_string().contains("no input or output notes"));
+}